@@ -0,0 +1,645 @@
+//! A two-pass 6502 assembler for tests and easy6502 programs.
+//!
+//! ```text
+//! define sysRandom $fe     ; or: sysRandom = $fe
+//!   .org $0600             ; the default origin
+//! start:
+//!   lda sysRandom
+//!   sta ($00),y
+//!   bne start
+//!   .byte 1, 2, "text"
+//!   .word start
+//! ```
+//!
+//! Operands take every mode in `AddressingMode`, plus accumulator (`lsr`
+//! or `lsr a`), `jmp (addr)` and branches. Values that fit in a byte use
+//! zero page when the instruction has it and the value is known by then;
+//! forward references are always absolute.
+//!
+//! Expressions: `42`, `$2a`, `%101010`, `'c'`, labels, `*` for the current
+//! address, `<` and `>` for the low and high byte, `- ~`, `* /`, `+ -`,
+//! `<< >>`, `&`, `^`, `|` and parentheses.
+use alloc::collections::BTreeMap;
+
+use crate::cpu::{AddressingMode, CPU};
+use crate::machine::{Machine, PROGRAM_START};
+use crate::opcodes::{OpCode, CPU_OPS_CODES};
+use crate::prelude::*;
+
+/// Assembled bytes, contiguous from `origin`; gaps between `.org` blocks
+/// are zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assembly {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    /// Addresses of the code labels, not of `define`d constants.
+    pub labels: BTreeMap<String, u16>,
+}
+
+/// Assembles source text; errors name the line.
+pub fn assemble(source: &str) -> Result<Assembly, String> {
+    let mut asm = Assembler::default();
+    asm.pass(source)?;
+    asm.second_pass = true;
+    asm.pass(source)?;
+
+    let origin = match asm.output.keys().next() {
+        Some(&first) => first,
+        None => asm.first_org.unwrap_or(PROGRAM_START),
+    };
+    let mut bytes = vec![];
+    for (&addr, &byte) in &asm.output {
+        let offset = (addr - origin) as usize;
+        bytes.resize(offset, 0);
+        bytes.push(byte);
+    }
+    Ok(Assembly {
+        origin,
+        bytes,
+        labels: asm.labels,
+    })
+}
+
+/// Assembles source lines and returns the bytes, panicking on errors.
+/// Meant for tests:
+///
+/// ```
+/// # #[macro_use] extern crate nes_core;
+/// let program = asm!("lda #$c0", "tax", "inx", "brk");
+/// assert_eq!(program, vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
+/// ```
+#[macro_export]
+macro_rules! asm {
+    ($($line:expr),* $(,)?) => {
+        $crate::asm::assemble(&[$($line),*].join("\n"))
+            .unwrap_or_else(|e| panic!("{}", e))
+            .bytes
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Implied,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+impl Mode {
+    fn of(op: &OpCode) -> Mode {
+        match (&op.address_mode, op.bytes) {
+            (AddressingMode::Immediate, _) => Mode::Immediate,
+            (AddressingMode::ZeroPage, _) => Mode::ZeroPage,
+            (AddressingMode::ZeroPage_X, _) => Mode::ZeroPageX,
+            (AddressingMode::ZeroPage_Y, _) => Mode::ZeroPageY,
+            (AddressingMode::Absolute, _) => Mode::Absolute,
+            (AddressingMode::Absolute_X, _) => Mode::AbsoluteX,
+            (AddressingMode::Absolute_Y, _) => Mode::AbsoluteY,
+            (AddressingMode::Indirect_X, _) => Mode::IndirectX,
+            (AddressingMode::Indirect_Y, _) => Mode::IndirectY,
+            // the table folds these into NoneAddressing; the length tells them apart
+            (AddressingMode::NoneAddressing, 1) => Mode::Implied,
+            (AddressingMode::NoneAddressing, 2) => Mode::Relative,
+            (AddressingMode::NoneAddressing, _) => Mode::Indirect,
+        }
+    }
+}
+
+fn opcode(name: &str, mode: Mode) -> Option<&'static OpCode> {
+    CPU_OPS_CODES
+        .iter()
+        .find(|op| op.name == name && Mode::of(op) == mode)
+}
+
+fn has_mnemonic(name: &str) -> bool {
+    CPU_OPS_CODES.iter().any(|op| op.name == name)
+}
+
+#[derive(Default)]
+struct Assembler {
+    second_pass: bool,
+    pc: u32,
+    symbols: BTreeMap<String, i64>,
+    labels: BTreeMap<String, u16>,
+    // the mode picked for each instruction in the first pass, so sizes
+    // stay the same in the second
+    modes: Vec<Mode>,
+    instruction: usize,
+    first_org: Option<u16>,
+    output: BTreeMap<u16, u8>,
+}
+
+impl Assembler {
+    fn pass(&mut self, source: &str) -> Result<(), String> {
+        self.pc = PROGRAM_START as u32;
+        self.instruction = 0;
+        for (number, line) in source.lines().enumerate() {
+            self.line(line)
+                .map_err(|e| format!("line {}: {}", number + 1, e))?;
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let mut rest = strip_comment(line).trim();
+
+        // `name:` labels, possibly followed by a statement
+        if let Some(colon) = rest.find(':') {
+            let name = rest[..colon].trim();
+            if is_name(name) {
+                self.define_label(name)?;
+                rest = rest[colon + 1..].trim();
+            }
+        }
+        if rest.is_empty() {
+            return Ok(());
+        }
+
+        let (word, operand) = split_word(rest);
+        if word.eq_ignore_ascii_case("define") {
+            let (name, value) = split_word(operand);
+            return self.define_constant(name, value);
+        }
+        if let Some(value) = operand.strip_prefix('=') {
+            return self.define_constant(word, value);
+        }
+        if let Some(directive) = word.strip_prefix('.') {
+            return self.directive(&directive.to_ascii_lowercase(), operand);
+        }
+        if word.eq_ignore_ascii_case("dcb") {
+            return self.directive("byte", operand);
+        }
+        self.instruction(&word.to_ascii_uppercase(), operand)
+    }
+
+    fn define_label(&mut self, name: &str) -> Result<(), String> {
+        if !self.second_pass && self.symbols.contains_key(name) {
+            return Err(format!("'{}' is already defined", name));
+        }
+        self.symbols.insert(name.to_string(), self.pc as i64);
+        self.labels.insert(name.to_string(), self.pc as u16);
+        Ok(())
+    }
+
+    fn define_constant(&mut self, name: &str, value: &str) -> Result<(), String> {
+        if !is_name(name) {
+            return Err(format!("bad name '{}'", name));
+        }
+        if !self.second_pass && self.symbols.contains_key(name) {
+            return Err(format!("'{}' is already defined", name));
+        }
+        if let Some(value) = self.eval(value)? {
+            self.symbols.insert(name.to_string(), value);
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, name: &str, operand: &str) -> Result<(), String> {
+        match name {
+            "org" => {
+                let addr = self
+                    .eval(operand)?
+                    .ok_or("the .org address must be known before it")?;
+                if !(0..=0xffff).contains(&addr) {
+                    return Err(format!("origin ${:X} is out of range", addr));
+                }
+                self.first_org.get_or_insert(addr as u16);
+                self.pc = addr as u32;
+            }
+            "byte" | "db" => {
+                for item in split_list(operand) {
+                    if let Some(text) = item.strip_prefix('"') {
+                        let text = text.strip_suffix('"').ok_or("unterminated string")?;
+                        for byte in text.bytes() {
+                            self.emit(byte)?;
+                        }
+                    } else {
+                        let value = self.eval(&item)?.unwrap_or(0);
+                        self.emit(byte(value)?)?;
+                    }
+                }
+            }
+            "word" | "dw" => {
+                for item in split_list(operand) {
+                    let value = self.eval(&item)?.unwrap_or(0);
+                    if !(-0x8000..=0xffff).contains(&value) {
+                        return Err(format!("${:X} does not fit in a word", value));
+                    }
+                    self.emit(value as u8)?;
+                    self.emit((value >> 8) as u8)?;
+                }
+            }
+            _ => return Err(format!("unknown directive .{}", name)),
+        }
+        Ok(())
+    }
+
+    fn instruction(&mut self, name: &str, operand: &str) -> Result<(), String> {
+        if !has_mnemonic(name) {
+            return Err(format!("unknown instruction '{}'", name));
+        }
+        let operand: String = operand.chars().filter(|c| !c.is_whitespace()).collect();
+        let (syntax, expr) = parse_operand(&operand);
+        let value = match expr {
+            Some(expr) => self.eval(expr)?,
+            None => Some(0),
+        };
+
+        let mode = if self.second_pass {
+            self.modes[self.instruction]
+        } else {
+            let mode = pick_mode(name, syntax, value)?;
+            self.modes.push(mode);
+            mode
+        };
+        self.instruction += 1;
+        let op = opcode(name, mode).expect("mode was checked in the first pass");
+
+        let start = self.pc;
+        self.emit(op.code)?;
+        // values are only final in the second pass
+        let value = value.unwrap_or(0);
+        match op.bytes {
+            2 if mode == Mode::Relative => {
+                let offset = value - (start as i64 + 2);
+                if self.second_pass && !(-128..=127).contains(&offset) {
+                    return Err(format!("branch target is {} bytes away", offset));
+                }
+                self.emit(offset as u8)?;
+            }
+            2 => self.emit(byte(value)?)?,
+            3 => {
+                if !(0..=0xffff).contains(&value) {
+                    return Err(format!("${:X} is not an address", value));
+                }
+                self.emit(value as u8)?;
+                self.emit((value >> 8) as u8)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, byte: u8) -> Result<(), String> {
+        if self.pc > 0xffff {
+            return Err("assembled past $FFFF".to_string());
+        }
+        if self.second_pass {
+            self.output.insert(self.pc as u16, byte);
+        }
+        self.pc += 1;
+        Ok(())
+    }
+
+    /// `Ok(None)` when the expression uses a label not defined yet, which
+    /// is only allowed in the first pass.
+    fn eval(&self, src: &str) -> Result<Option<i64>, String> {
+        let tokens = tokenize(src)?;
+        if tokens.is_empty() {
+            return Err("missing value".to_string());
+        }
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            asm: self,
+            unknown: None,
+        };
+        let value = parser.binary(0)?;
+        if parser.pos != parser.tokens.len() {
+            return Err(format!("unexpected text in '{}'", src));
+        }
+        match parser.unknown {
+            Some(name) if self.second_pass => Err(format!("unknown label '{}'", name)),
+            Some(_) => Ok(None),
+            None => Ok(Some(value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Syntax {
+    None,
+    Immediate,
+    Plain,
+    IndexX,
+    IndexY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+}
+
+/// Splits an operand, already stripped of spaces, into its syntax and the
+/// expression inside it.
+fn parse_operand(operand: &str) -> (Syntax, Option<&str>) {
+    let upper = operand.to_ascii_uppercase();
+    if operand.is_empty() || upper == "A" {
+        return (Syntax::None, None);
+    }
+    if let Some(expr) = operand.strip_prefix('#') {
+        return (Syntax::Immediate, Some(expr));
+    }
+    if operand.starts_with('(') {
+        let close = matching_paren(operand);
+        if close == Some(operand.len() - 1) {
+            let inner = &operand[1..operand.len() - 1];
+            if upper[1..upper.len() - 1].ends_with(",X") {
+                return (Syntax::IndirectX, Some(&inner[..inner.len() - 2]));
+            }
+            return (Syntax::Indirect, Some(inner));
+        }
+        if close == Some(operand.len() - 3) && upper.ends_with(",Y") {
+            return (Syntax::IndirectY, Some(&operand[1..operand.len() - 3]));
+        }
+    }
+    if upper.ends_with(",X") {
+        return (Syntax::IndexX, Some(&operand[..operand.len() - 2]));
+    }
+    if upper.ends_with(",Y") {
+        return (Syntax::IndexY, Some(&operand[..operand.len() - 2]));
+    }
+    (Syntax::Plain, Some(operand))
+}
+
+fn matching_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn pick_mode(name: &str, syntax: Syntax, value: Option<i64>) -> Result<Mode, String> {
+    let zero_page = value.is_some_and(|v| (0..=0xff).contains(&v));
+    let choices: &[Mode] = match syntax {
+        Syntax::None => &[Mode::Implied],
+        Syntax::Immediate => &[Mode::Immediate],
+        Syntax::Plain if zero_page => &[Mode::Relative, Mode::ZeroPage, Mode::Absolute],
+        Syntax::Plain => &[Mode::Relative, Mode::Absolute],
+        Syntax::IndexX if zero_page => &[Mode::ZeroPageX, Mode::AbsoluteX],
+        Syntax::IndexX => &[Mode::AbsoluteX],
+        Syntax::IndexY if zero_page => &[Mode::ZeroPageY, Mode::AbsoluteY],
+        Syntax::IndexY => &[Mode::AbsoluteY],
+        Syntax::Indirect => &[Mode::Indirect],
+        Syntax::IndirectX => &[Mode::IndirectX],
+        Syntax::IndirectY => &[Mode::IndirectY],
+    };
+    choices
+        .iter()
+        .copied()
+        .find(|&mode| opcode(name, mode).is_some())
+        .ok_or_else(|| format!("{} has no {:?} mode", name, syntax))
+}
+
+fn byte(value: i64) -> Result<u8, String> {
+    if !(-0x80..=0xff).contains(&value) {
+        return Err(format!("${:X} does not fit in a byte", value));
+    }
+    Ok(value as u8)
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.find(|c: char| c.is_whitespace() || c == '=') {
+        Some(end) => (&text[..end], text[end..].trim()),
+        None => (text, ""),
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (c, quote) {
+            ('"', None) | ('\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (';', None) => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits a directive's comma separated list, leaving strings whole.
+fn split_list(text: &str) -> Vec<String> {
+    let mut items = vec![];
+    let mut item = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                item.push(c);
+            }
+            ',' if !quoted => items.push(core::mem::take(&mut item).trim().to_string()),
+            _ => item.push(c),
+        }
+    }
+    items.push(item.trim().to_string());
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 13] = [
+    "<<", ">>", "(", ")", "+", "-", "*", "/", "&", "|", "^", "~", "<",
+];
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = src.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let (token, len) = if c == '$' || c == '%' || c.is_ascii_digit() {
+            let (radix, digits) = match c {
+                '$' => (16, &rest[1..]),
+                '%' => (2, &rest[1..]),
+                _ => (10, rest),
+            };
+            let end = digits
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(digits.len());
+            let len = end + rest.len() - digits.len();
+            let value = i64::from_str_radix(&digits[..end], radix)
+                .map_err(|_| format!("bad number '{}'", &rest[..len]))?;
+            (Token::Number(value), len)
+        } else if c == '\'' {
+            let mut chars = rest[1..].chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), Some('\'')) if ch.is_ascii() => (Token::Number(ch as i64), 3),
+                _ => return Err(format!("bad character in '{}'", src)),
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (Token::Name(rest[..end].to_string()), end)
+        } else if c == '>' {
+            // `>>` is a shift, a lone `>` takes the high byte
+            if rest.starts_with(">>") {
+                (Token::Op(">>"), 2)
+            } else {
+                (Token::Op(">"), 1)
+            }
+        } else {
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => (Token::Op(op), op.len()),
+                None => return Err(format!("unexpected '{}' in '{}'", c, src)),
+            }
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+// binary operators from loosest to tightest
+const LEVELS: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/"],
+];
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    asm: &'a Assembler,
+    unknown: Option<String>,
+}
+
+impl Parser<'_> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|op| LEVELS[level].contains(op)) {
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = match op {
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                _ if right == 0 => 0,
+                _ => left / right,
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let op = self.peek_op();
+        match op {
+            Some("-") | Some("~") | Some("<") | Some(">") => {
+                self.pos += 1;
+                let value = self.unary()?;
+                Ok(match op {
+                    Some("-") => value.wrapping_neg(),
+                    Some("~") => !value,
+                    Some("<") => value & 0xff,
+                    _ => (value >> 8) & 0xff,
+                })
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(n)) => Ok(n),
+            Some(Token::Name(name)) => match self.asm.symbols.get(&name) {
+                Some(&value) => Ok(value),
+                None => {
+                    self.unknown.get_or_insert(name);
+                    Ok(0)
+                }
+            },
+            // `*` where a value belongs is the current address
+            Some(Token::Op("*")) => Ok(self.asm.pc as i64),
+            Some(Token::Op("(")) => {
+                let value = self.binary(0)?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Op(")")) => {
+                        self.pos += 1;
+                        Ok(value)
+                    }
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            _ => Err("expected a value".to_string()),
+        }
+    }
+}
+
+impl CPU {
+    /// Assembles an easy6502 program, loads it at its origin (normally
+    /// $0600), switches to the easy6502 machine and resets. The reset
+    /// vector points at the origin unless the program reaches $FFFC and
+    /// sets its own. Its labels replace the debugger's symbols.
+    pub fn load_asm(&mut self, source: &str) -> Result<(), String> {
+        let assembly = assemble(source)?;
+        let start = assembly.origin as usize;
+        let end = start + assembly.bytes.len();
+
+        self.machine = Machine::Easy6502;
+        self.cartridge = None;
+        self.memory[start..end].copy_from_slice(&assembly.bytes);
+        if end <= 0xfffc {
+            self.memory[0xfffc..=0xfffd].copy_from_slice(&assembly.origin.to_le_bytes());
+        }
+
+        let symbols = self.debugger.symbols_mut();
+        symbols.clear();
+        for (name, &addr) in &assembly.labels {
+            symbols.add(name, addr, None);
+        }
+        self.reset();
+        Ok(())
+    }
+}
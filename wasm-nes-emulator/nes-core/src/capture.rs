@@ -0,0 +1,239 @@
+//! Frame-accurate recording of the emulator's output: video as YUV4MPEG2
+//! and audio as WAV, both driven by the per-frame hook so they stay in
+//! step with the frame count. easy6502 has no sound, so its audio is
+//! silence; the NES has no APU yet, so its audio is refused rather than
+//! recorded as silence.
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::cpu::CPU;
+use crate::machine::Machine;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+// the NTSC CPU clock, and the cycles in a frame times three (see `frame`)
+const CPU_HZ: u64 = 1_789_773;
+const FRAME_CYCLES_X3: u64 = 341 * 262;
+
+/// Where the WAV goes; it is rewritten at the end to fill in the sizes.
+pub trait Sink: Write + Seek {}
+impl<T: Write + Seek> Sink for T {}
+
+/// Writes 4:2:0 YUV4MPEG2 frames at the NTSC frame rate.
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    width: usize,
+    height: usize,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// `width` and `height` must be even.
+    pub fn new(mut out: W, width: usize, height: usize) -> io::Result<Self> {
+        writeln!(
+            out,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg",
+            width,
+            height,
+            CPU_HZ * 3,
+            FRAME_CYCLES_X3
+        )?;
+        Ok(Y4mWriter { out, width, height })
+    }
+
+    /// Converts RGB pixels to full-range BT.601 and writes them as a frame.
+    pub fn write_frame(&mut self, rgb: &[u8]) -> io::Result<()> {
+        let (w, h) = (self.width, self.height);
+        if rgb.len() != w * h * 3 {
+            let message = format!("frame is not {}x{}", w, h);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        let pixel = |x: usize, y: usize| {
+            let i = (y * w + x) * 3;
+            (rgb[i] as f32, rgb[i + 1] as f32, rgb[i + 2] as f32)
+        };
+        let clamp = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+
+        let mut luma = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                let (r, g, b) = pixel(x, y);
+                luma.push(clamp(0.299 * r + 0.587 * g + 0.114 * b));
+            }
+        }
+        // each chroma sample averages a 2x2 block
+        let mut cb = Vec::with_capacity(w * h / 4);
+        let mut cr = Vec::with_capacity(w * h / 4);
+        for y in (0..h).step_by(2) {
+            for x in (0..w).step_by(2) {
+                let (mut r, mut g, mut b) = (0.0, 0.0, 0.0);
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let p = pixel(x + dx, y + dy);
+                    r += p.0 / 4.0;
+                    g += p.1 / 4.0;
+                    b += p.2 / 4.0;
+                }
+                cb.push(clamp(128.0 - 0.168_736 * r - 0.331_264 * g + 0.5 * b));
+                cr.push(clamp(128.0 + 0.5 * r - 0.418_688 * g - 0.081_312 * b));
+            }
+        }
+
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&luma)?;
+        self.out.write_all(&cb)?;
+        self.out.write_all(&cr)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Writes 16-bit mono PCM at `SAMPLE_RATE`.
+pub struct WavWriter<W: Sink> {
+    out: W,
+    samples: u32,
+}
+
+impl<W: Sink> WavWriter<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        Self::header(&mut out, 0)?;
+        Ok(WavWriter { out, samples: 0 })
+    }
+
+    fn header(out: &mut W, samples: u32) -> io::Result<()> {
+        let data_len = samples * 2;
+        out.write_all(b"RIFF")?;
+        out.write_all(&(36 + data_len).to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        // PCM, one channel
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        out.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&data_len.to_le_bytes())
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.out.write_all(&bytes)?;
+        self.samples += samples.len() as u32;
+        Ok(())
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Fills in the header sizes.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.seek(SeekFrom::Start(0))?;
+        Self::header(&mut self.out, self.samples)?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// The audio samples that fall within the first `frames` frames.
+pub fn samples_for_frames(frames: u64) -> u64 {
+    frames * SAMPLE_RATE as u64 * FRAME_CYCLES_X3 / (CPU_HZ * 3)
+}
+
+pub struct Capture {
+    video: Option<Y4mWriter<Box<dyn Write>>>,
+    audio: Option<WavWriter<Box<dyn Sink>>>,
+    scale: usize,
+    frames: u64,
+    error: Option<String>,
+}
+
+impl Capture {
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+impl CPU {
+    /// Starts recording every frame of the picture, scaled up by `scale`,
+    /// to `video` and the matching stretch of audio to `audio`. Audio is
+    /// silence on easy6502 and an error on the NES until there is an APU.
+    pub fn start_capture(
+        &mut self,
+        video: Option<Box<dyn Write>>,
+        audio: Option<Box<dyn Sink>>,
+        scale: u32,
+    ) -> Result<(), String> {
+        if !(1..=64).contains(&scale) {
+            return Err(format!("scale must be 1 to 64, not {}", scale));
+        }
+        if audio.is_some() && self.machine == Machine::Nes {
+            return Err("NES audio cannot be captured until there is an APU".to_string());
+        }
+        let scale = scale as usize;
+        let (width, height) = self.screen_size();
+        let video = video.map(|out| Y4mWriter::new(out, width * scale, height * scale));
+        let audio = audio.map(WavWriter::new);
+        self.capture = Some(Capture {
+            video: video.transpose().map_err(|e| e.to_string())?,
+            audio: audio.transpose().map_err(|e| e.to_string())?,
+            scale,
+            frames: 0,
+            error: None,
+        });
+        Ok(())
+    }
+
+    pub fn capture(&self) -> Option<&Capture> {
+        self.capture.as_ref()
+    }
+
+    /// Finishes both files. Returns the first write error, if recording
+    /// stopped early because of one.
+    pub fn stop_capture(&mut self) -> Result<u64, String> {
+        let capture = match self.capture.take() {
+            Some(capture) => capture,
+            None => return Ok(0),
+        };
+        if let Some(error) = capture.error {
+            return Err(error);
+        }
+        if let Some(video) = capture.video {
+            video.finish().map_err(|e| e.to_string())?;
+        }
+        if let Some(audio) = capture.audio {
+            audio.finish().map_err(|e| e.to_string())?;
+        }
+        Ok(capture.frames)
+    }
+
+    /// Called at the start of each frame while capturing.
+    pub(crate) fn record_capture(&mut self) {
+        let rgb = match &self.capture {
+            Some(capture) if capture.error.is_none() && capture.video.is_some() => {
+                self.frame_rgb(capture.scale)
+            }
+            _ => vec![],
+        };
+        let capture = match self.capture.as_mut() {
+            Some(capture) if capture.error.is_none() => capture,
+            _ => return,
+        };
+
+        capture.frames += 1;
+        let mut result = Ok(());
+        if let Some(video) = capture.video.as_mut() {
+            result = video.write_frame(&rgb);
+        }
+        if let (Ok(()), Some(audio)) = (&result, capture.audio.as_mut()) {
+            let due = samples_for_frames(capture.frames) - audio.samples() as u64;
+            result = audio.write_samples(&vec![0; due as usize]);
+        }
+        if let Err(e) = result {
+            capture.error = Some(e.to_string());
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! iNES cartridge images and battery-backed PRG-RAM.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+use std::path::{Path, PathBuf};
+
+use crate::cpu::CPU;
+use crate::machine::Machine;
+use crate::ppu::Ppu;
+use crate::prelude::*;
+
+const NES_TAG: [u8; 4] = [b'N', b'E', b'S', 0x1a];
+const PRG_ROM_PAGE_SIZE: usize = 0x4000;
+const CHR_ROM_PAGE_SIZE: usize = 0x2000;
+
+pub const PRG_RAM_START: u16 = 0x6000;
+pub const PRG_RAM_SIZE: usize = 0x2000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mirroring {
+    Vertical,
+    Horizontal,
+    FourScreen,
+}
+
+pub struct Rom {
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    pub mapper: u8,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+}
+
+impl Rom {
+    pub fn new(raw: &[u8]) -> Result<Rom, String> {
+        if raw.len() < 16 || raw[0..4] != NES_TAG {
+            return Err("File is not in iNES file format".to_string());
+        }
+
+        let mapper = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
+
+        let four_screen = raw[6] & 0b1000 != 0;
+        let vertical_mirroring = raw[6] & 0b1 != 0;
+        let mirroring = match (four_screen, vertical_mirroring) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+        let battery = raw[6] & 0b10 != 0;
+
+        let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
+        let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
+
+        let skip_trainer = raw[6] & 0b100 != 0;
+
+        let prg_rom_start = 16 + if skip_trainer { 512 } else { 0 };
+        let chr_rom_start = prg_rom_start + prg_rom_size;
+
+        if raw.len() < chr_rom_start + chr_rom_size {
+            return Err("iNES file is truncated".to_string());
+        }
+
+        Ok(Rom {
+            prg_rom: raw[prg_rom_start..chr_rom_start].to_vec(),
+            chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
+            mapper,
+            mirroring,
+            battery,
+        })
+    }
+}
+
+impl CPU {
+    pub fn cartridge(&self) -> Option<&Rom> {
+        self.cartridge.as_ref()
+    }
+
+    /// Maps an iNES image into memory, switches to the NES machine and
+    /// resets. Only NROM (mapper 0) boards are supported; 16K images are
+    /// mirrored at $C000. Like swapping cartridges, this powers on afresh:
+    /// work RAM, PRG-RAM, the controllers and the cheats start out clear,
+    /// any movie and RAM search end, and the rewind buffer, event log and
+    /// profiler start over from the new game.
+    pub fn load_rom(&mut self, raw: &[u8]) -> Result<(), String> {
+        let rom = Rom::new(raw)?;
+        if rom.mapper != 0 {
+            return Err(format!("mapper {} is not supported", rom.mapper));
+        }
+        if rom.prg_rom.len() != PRG_ROM_PAGE_SIZE && rom.prg_rom.len() != 2 * PRG_ROM_PAGE_SIZE {
+            return Err("NROM needs 16K or 32K of PRG ROM".to_string());
+        }
+
+        for (i, byte) in self.memory[0x8000..].iter_mut().enumerate() {
+            *byte = rom.prg_rom[i % rom.prg_rom.len()];
+        }
+        self.memory[..0x800].fill(0);
+        let ram = PRG_RAM_START as usize;
+        self.memory[ram..ram + PRG_RAM_SIZE].fill(0);
+        self.save_ram_dirty = false;
+        self.joypads = Default::default();
+        self.cheats.clear();
+        self.ppu = Ppu::new(rom.chr_rom.clone(), rom.mirroring);
+        self.ppu_viewer = None;
+        self.cdl = None;
+        self.cartridge = Some(rom);
+        self.machine = Machine::Nes;
+        self.reset();
+
+        self.movie = None;
+        self.ram_search = None;
+        if self.events.is_some() {
+            self.start_event_log();
+        }
+        if self.profiler.is_some() {
+            self.start_profiler();
+        }
+        let state = self.save_state();
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+            rewind.push(state, self.cycles);
+        }
+        Ok(())
+    }
+
+    /// True when the cartridge keeps PRG-RAM alive with a battery.
+    pub fn has_battery(&self) -> bool {
+        self.cartridge.as_ref().is_some_and(|rom| rom.battery)
+    }
+
+    /// The contents of PRG-RAM ($6000-$7FFF), for writing to a `.sav` file
+    /// or browser storage.
+    pub fn save_ram(&self) -> Vec<u8> {
+        let ram = PRG_RAM_START as usize;
+        self.memory[ram..ram + PRG_RAM_SIZE].to_vec()
+    }
+
+    /// Restores PRG-RAM from an earlier `save_ram`. Shorter saves fill
+    /// the start of the RAM.
+    pub fn load_save_ram(&mut self, data: &[u8]) -> Result<(), String> {
+        if data.len() > PRG_RAM_SIZE {
+            return Err(format!(
+                "save RAM is {} bytes, at most {} expected",
+                data.len(),
+                PRG_RAM_SIZE
+            ));
+        }
+        let ram = PRG_RAM_START as usize;
+        self.memory[ram..ram + data.len()].copy_from_slice(data);
+        self.save_ram_dirty = false;
+        Ok(())
+    }
+
+    pub fn reset_save_ram_dirty(&mut self) {
+        self.save_ram_dirty = false;
+    }
+}
+
+/// The `.sav` file kept next to a ROM: `zelda.nes` saves to `zelda.sav`.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub fn sav_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("sav")
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl CPU {
+    /// Loads a ROM file and, for battery-backed cartridges, its `.sav`.
+    pub fn load_rom_file(&mut self, path: &Path) -> Result<(), String> {
+        let raw = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.load_rom(&raw)?;
+
+        let sav = sav_path(path);
+        if self.has_battery() && sav.exists() {
+            let data = std::fs::read(&sav).map_err(|e| format!("{}: {}", sav.display(), e))?;
+            self.load_save_ram(&data)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the `.sav` next to `rom_path` if PRG-RAM changed since the
+    /// last write. Returns whether a file was written.
+    pub fn flush_save_ram(&mut self, rom_path: &Path) -> Result<bool, String> {
+        if !self.has_battery() || !self.save_ram_dirty {
+            return Ok(false);
+        }
+        let sav = sav_path(rom_path);
+        std::fs::write(&sav, self.save_ram()).map_err(|e| format!("{}: {}", sav.display(), e))?;
+        self.save_ram_dirty = false;
+        Ok(true)
+    }
+}
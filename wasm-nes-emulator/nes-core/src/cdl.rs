@@ -0,0 +1,233 @@
+//! The code/data logger: marks which PRG ROM bytes ran as code or were
+//! read as data and which CHR ROM bytes were drawn or read, in the layout
+//! of FCEUX's `.cdl` files, one flag byte per PRG byte then one per CHR
+//! byte.
+use crate::cpu::{AddressingMode, CPU};
+use crate::opcodes::OpCode;
+use crate::prelude::*;
+
+// PRG flags
+pub const CODE: u8 = 0x01;
+pub const DATA: u8 = 0x02;
+/// Bits 2-3: the 8K window the byte was mapped into, $8000 to $E000.
+pub const BANK_MASK: u8 = 0x0c;
+/// The target of a `JMP ($nnnn)`.
+pub const INDIRECT_CODE: u8 = 0x10;
+/// Read through a `($nn,X)` or `($nn),Y` pointer.
+pub const INDIRECT_DATA: u8 = 0x20;
+/// Played by the DMC as a sample.
+pub const PCM: u8 = 0x40;
+
+// CHR flags
+pub const DRAWN: u8 = 0x01;
+/// Read by the program through $2007.
+pub const READ: u8 = 0x02;
+
+// PPUMASK
+const SHOW_BACKGROUND: u8 = 0b0000_1000;
+const SHOW_SPRITES: u8 = 0b0001_0000;
+
+pub struct CodeDataLog {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+}
+
+impl CodeDataLog {
+    pub fn prg(&self) -> &[u8] {
+        &self.prg
+    }
+
+    pub fn chr(&self) -> &[u8] {
+        &self.chr
+    }
+
+    /// The log as an FCEUX `.cdl` file.
+    pub fn to_cdl(&self) -> Vec<u8> {
+        [&self.prg[..], &self.chr[..]].concat()
+    }
+
+    fn mark_prg(&mut self, addr: u16, flags: u8) {
+        if addr < 0x8000 || self.prg.is_empty() {
+            return;
+        }
+        let offset = (addr - 0x8000) as usize % self.prg.len();
+        let bank = ((addr >> 13) & 3) as u8;
+        self.prg[offset] = (self.prg[offset] & !BANK_MASK) | bank << 2 | flags;
+    }
+
+    fn mark_chr(&mut self, addr: u16, len: u16, flags: u8) {
+        if self.chr.is_empty() {
+            return;
+        }
+        for i in 0..len {
+            let offset = (addr + i) as usize % self.chr.len();
+            self.chr[offset] |= flags;
+        }
+    }
+}
+
+impl CPU {
+    pub fn code_data_log(&self) -> Option<&CodeDataLog> {
+        self.cdl.as_ref()
+    }
+
+    /// The flags logged for the PRG ROM byte at `addr`, if logging.
+    pub fn cdl_flags(&self, addr: u16) -> Option<u8> {
+        let log = self.cdl.as_ref()?;
+        if addr < 0x8000 || log.prg.is_empty() {
+            return None;
+        }
+        Some(log.prg[(addr - 0x8000) as usize % log.prg.len()])
+    }
+
+    /// True for bytes that were only ever read as data, which the
+    /// disassembler lists as `.byte` rather than decoding.
+    pub fn is_logged_data(&self, addr: u16) -> bool {
+        self.cdl_flags(addr)
+            .is_some_and(|flags| flags & CODE == 0 && flags & (DATA | INDIRECT_DATA | PCM) != 0)
+    }
+
+    /// Called before the instruction at `addr` runs.
+    pub(crate) fn cdl_instruction(&mut self, addr: u16, opcode: &OpCode) {
+        let indirect_data = match opcode.address_mode {
+            AddressingMode::Indirect_X | AddressingMode::Indirect_Y if opcode.name != "STA" => {
+                Some(self.get_absolute_address(&opcode.address_mode, addr.wrapping_add(1)))
+            }
+            _ => None,
+        };
+        // jmp indirect reads its vector, including the page wrap bug
+        let indirect_code = if opcode.code == 0x6C {
+            let lo_addr = self.mem_read_u16(addr.wrapping_add(1));
+            let hi_addr = (lo_addr & 0xff00) | (lo_addr.wrapping_add(1) & 0x00ff);
+            let target = (self.mem_peek(hi_addr) as u16) << 8 | self.mem_peek(lo_addr) as u16;
+            Some((lo_addr, hi_addr, target))
+        } else {
+            None
+        };
+
+        let log = match self.cdl.as_mut() {
+            Some(log) => log,
+            None => return,
+        };
+        for i in 0..opcode.bytes as u16 {
+            log.mark_prg(addr.wrapping_add(i), CODE);
+        }
+        if let Some(target) = indirect_data {
+            log.mark_prg(target, INDIRECT_DATA);
+        }
+        if let Some((lo_addr, hi_addr, target)) = indirect_code {
+            log.mark_prg(lo_addr, DATA);
+            log.mark_prg(hi_addr, DATA);
+            log.mark_prg(target, INDIRECT_CODE);
+        }
+    }
+
+    /// Called for every read the program makes.
+    pub(crate) fn cdl_read(&mut self, addr: u16) {
+        let chr_addr = self.ppu.v & 0x3fff;
+        let log = match self.cdl.as_mut() {
+            Some(log) => log,
+            None => return,
+        };
+        match addr {
+            0x2000..=0x3fff if addr & 7 == 7 && chr_addr < 0x2000 => {
+                log.mark_chr(chr_addr, 1, READ)
+            }
+            0x8000..=0xffff => log.mark_prg(addr, DATA),
+            _ => {}
+        }
+    }
+
+    /// Called for every write; starting the DMC marks its sample.
+    pub(crate) fn cdl_write(&mut self, addr: u16, data: u8) {
+        if addr != 0x4015 || data & 0x10 == 0 {
+            return;
+        }
+        let start = 0xc000 + self.mem_peek(0x4012) as u16 * 64;
+        let len = self.mem_peek(0x4013) as u16 * 16 + 1;
+        if let Some(log) = self.cdl.as_mut() {
+            for i in 0..len {
+                // the sample address wraps from $FFFF to $8000
+                let addr = start.wrapping_add(i) | 0x8000;
+                log.mark_prg(addr, PCM);
+            }
+        }
+    }
+
+    /// Marks the tiles the frame about to be shown would draw: the
+    /// background under the scroll window and the visible sprites.
+    pub(crate) fn cdl_frame_done(&mut self) {
+        let ppu = &self.ppu;
+        let mut tiles = vec![];
+        if ppu.mask & SHOW_BACKGROUND != 0 {
+            let table = ppu.background_table();
+            let (sx, sy) = ppu.scroll();
+            for row in 0..31 {
+                for col in 0..33 {
+                    let x = (sx / 8 + col) % 64;
+                    let y = (sy / 8 + row) % 60;
+                    let nametable = 0x2000 + ((y / 30) * 2 + x / 32) as u16 * 0x400;
+                    let tile = ppu.read(nametable + ((y % 30) * 32 + x % 32) as u16);
+                    tiles.push(table + tile as u16 * 16);
+                }
+            }
+        }
+        if ppu.mask & SHOW_SPRITES != 0 {
+            for sprite in ppu.sprites().iter().filter(|s| s.y < 0xef) {
+                if ppu.tall_sprites() {
+                    let top = sprite.pattern_table + (sprite.tile & 0xfe) as u16 * 16;
+                    tiles.push(top);
+                    tiles.push(top + 16);
+                } else {
+                    tiles.push(sprite.pattern_table + sprite.tile as u16 * 16);
+                }
+            }
+        }
+        if let Some(log) = self.cdl.as_mut() {
+            for tile in tiles {
+                log.mark_chr(tile, 16, DRAWN);
+            }
+        }
+    }
+
+    /// Starts an empty log for the cartridge.
+    pub fn start_cdl(&mut self) -> Result<(), String> {
+        let rom = self
+            .cartridge
+            .as_ref()
+            .ok_or_else(|| "the code/data logger needs a cartridge".to_string())?;
+        self.cdl = Some(CodeDataLog {
+            prg: vec![0; rom.prg_rom.len()],
+            chr: vec![0; rom.chr_rom.len()],
+        });
+        Ok(())
+    }
+
+    /// Carries on logging from a `.cdl` file saved from the same ROM.
+    pub fn load_cdl(&mut self, data: &[u8]) -> Result<(), String> {
+        self.start_cdl()?;
+        let log = self.cdl.as_mut().unwrap();
+        if data.len() != log.prg.len() + log.chr.len() {
+            let expected = log.prg.len() + log.chr.len();
+            self.cdl = None;
+            return Err(format!(
+                "the .cdl is {} bytes but this ROM needs {}",
+                data.len(),
+                expected
+            ));
+        }
+        let (prg, chr) = data.split_at(log.prg.len());
+        log.prg.copy_from_slice(prg);
+        log.chr.copy_from_slice(chr);
+        Ok(())
+    }
+
+    pub fn stop_cdl(&mut self) {
+        self.cdl = None;
+    }
+
+    /// The log in FCEUX's `.cdl` format; empty when not logging.
+    pub fn export_cdl(&self) -> Vec<u8> {
+        self.cdl.as_ref().map_or(vec![], |log| log.to_cdl())
+    }
+}
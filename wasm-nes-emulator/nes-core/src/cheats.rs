@@ -0,0 +1,264 @@
+//! Cheats: Game Genie style patches that substitute what the CPU reads,
+//! and Pro Action Replay style freezes that rewrite RAM every frame.
+use crate::cpu::CPU;
+use crate::prelude::*;
+
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheatKind {
+    /// Reads of `addr` return `value`, if the real byte equals `compare`.
+    Patch,
+    /// `value` is written to `addr` at the start of every frame.
+    Freeze,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    pub name: String,
+    pub kind: CheatKind,
+    pub addr: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    /// Reads a 6 or 8 letter Game Genie code, or a raw code: `AAAA:VV`
+    /// freezes RAM and `AAAA?CC:VV` patches reads that see `CC`.
+    pub fn parse(code: &str, name: &str) -> Result<Cheat, String> {
+        let code = code.trim();
+        let (kind, addr, value, compare) = if code.contains(':') {
+            parse_raw(code)?
+        } else {
+            let (addr, value, compare) = decode_game_genie(code)?;
+            (CheatKind::Patch, addr, value, compare)
+        };
+        Ok(Cheat {
+            name: name.to_string(),
+            kind,
+            addr,
+            value,
+            compare,
+            enabled: true,
+        })
+    }
+
+    /// The cheat as a line of an FCEUX `.cht` file.
+    pub fn to_cht(&self) -> String {
+        let mut line = String::new();
+        if self.kind == CheatKind::Patch {
+            line.push('S');
+        }
+        if self.compare.is_some() {
+            line.push('C');
+        }
+        if !self.enabled {
+            line.push(':');
+        }
+        line.push_str(&format!("{:04X}:{:02X}", self.addr, self.value));
+        if let Some(compare) = self.compare {
+            line.push_str(&format!(":{:02X}", compare));
+        }
+        line.push(':');
+        line.push_str(&self.name);
+        line
+    }
+}
+
+fn hex(text: &str, what: &str) -> Result<u32, String> {
+    u32::from_str_radix(text.trim(), 16).map_err(|_| format!("bad {} '{}'", what, text))
+}
+
+fn parse_raw(code: &str) -> Result<(CheatKind, u16, u8, Option<u8>), String> {
+    let (target, value) = code
+        .split_once(':')
+        .ok_or_else(|| format!("'{}' is not a raw code", code))?;
+    let (addr, compare) = match target.split_once('?') {
+        Some((addr, compare)) => (addr, Some(hex(compare, "compare value")?)),
+        None => (target, None),
+    };
+    let addr = hex(addr, "address")?;
+    let value = hex(value, "value")?;
+    if addr > 0xffff || value > 0xff || compare.is_some_and(|c| c > 0xff) {
+        return Err(format!("'{}' is out of range", code));
+    }
+    let kind = match compare {
+        Some(_) => CheatKind::Patch,
+        None => CheatKind::Freeze,
+    };
+    Ok((kind, addr as u16, value as u8, compare.map(|c| c as u8)))
+}
+
+/// Decodes a Game Genie code into the ROM address, the replacement byte
+/// and, for 8 letter codes, the byte that must be there.
+pub fn decode_game_genie(code: &str) -> Result<(u16, u8, Option<u8>), String> {
+    let n = code
+        .chars()
+        .map(|c| {
+            GAME_GENIE_LETTERS
+                .find(c.to_ascii_uppercase())
+                .map(|i| i as u16)
+                .ok_or_else(|| format!("'{}' is not a Game Genie letter", c))
+        })
+        .collect::<Result<Vec<u16>, String>>()?;
+    if n.len() != 6 && n.len() != 8 {
+        return Err(format!("'{}' is not a 6 or 8 letter code", code));
+    }
+
+    let addr = 0x8000
+        | ((n[3] & 7) << 12)
+        | ((n[5] & 7) << 8)
+        | ((n[4] & 8) << 8)
+        | ((n[2] & 7) << 4)
+        | ((n[1] & 8) << 4)
+        | (n[4] & 7)
+        | (n[3] & 8);
+    // the last letter carries the value's top bit of its low nibble
+    let last = if n.len() == 6 { n[5] } else { n[7] };
+    let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7) | (last & 8);
+    let compare = if n.len() == 8 {
+        Some((((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8)) as u8)
+    } else {
+        None
+    };
+    Ok((addr, value as u8, compare))
+}
+
+/// Reads an FCEUX `.cht` file: one `[S][C][:]AAAA:VV[:CC]:name` per line,
+/// where `S` substitutes reads instead of freezing RAM, `C` adds the
+/// compare byte and a leading `:` means disabled.
+pub fn parse_cht(text: &str) -> Result<Vec<Cheat>, String> {
+    let mut cheats = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        let error = |e: String| format!("line {}: {}", number + 1, e);
+
+        let mut rest = line;
+        let substitute = rest.starts_with('S');
+        if substitute {
+            rest = &rest[1..];
+        }
+        let has_compare = rest.starts_with('C');
+        if has_compare {
+            rest = &rest[1..];
+        }
+        let enabled = !rest.starts_with(':');
+        if !enabled {
+            rest = &rest[1..];
+        }
+
+        let fields = if has_compare { 4 } else { 3 };
+        let parts: Vec<&str> = rest.splitn(fields, ':').collect();
+        if parts.len() < fields {
+            return Err(error(format!("expected {} fields", fields)));
+        }
+        let addr = hex(parts[0], "address").map_err(error)?;
+        let value = hex(parts[1], "value").map_err(error)?;
+        let compare = if has_compare {
+            Some(hex(parts[2], "compare value").map_err(error)?)
+        } else {
+            None
+        };
+        if addr > 0xffff || value > 0xff || compare.is_some_and(|c| c > 0xff) {
+            return Err(error("value out of range".to_string()));
+        }
+        cheats.push(Cheat {
+            name: parts[fields - 1].to_string(),
+            kind: if substitute {
+                CheatKind::Patch
+            } else {
+                CheatKind::Freeze
+            },
+            addr: addr as u16,
+            value: value as u8,
+            compare: compare.map(|c| c as u8),
+            enabled,
+        });
+    }
+    Ok(cheats)
+}
+
+impl CPU {
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn push_cheat(&mut self, cheat: Cheat) -> usize {
+        self.cheats.push(cheat);
+        self.cheats.len() - 1
+    }
+
+    /// What a read of `addr` returns once enabled patches are applied.
+    pub(crate) fn patch_read(&self, addr: u16, data: u8) -> u8 {
+        self.cheats
+            .iter()
+            .find(|c| {
+                c.enabled
+                    && c.kind == CheatKind::Patch
+                    && c.addr == addr
+                    && (c.compare.is_none() || c.compare == Some(data))
+            })
+            .map_or(data, |c| c.value)
+    }
+
+    /// Called at the start of each frame while there are cheats.
+    pub(crate) fn apply_freezes(&mut self) {
+        for i in 0..self.cheats.len() {
+            let cheat = &self.cheats[i];
+            if cheat.enabled && cheat.kind == CheatKind::Freeze {
+                let (addr, value) = (cheat.addr, cheat.value);
+                self.bus_write(addr, value);
+            }
+        }
+    }
+
+    fn cheat_mut(&mut self, index: usize) -> Result<&mut Cheat, String> {
+        let count = self.cheats.len();
+        self.cheats
+            .get_mut(index)
+            .ok_or_else(|| format!("no cheat {}, there are {}", index, count))
+    }
+
+    /// Adds an enabled cheat from a Game Genie or raw code and returns its
+    /// index.
+    pub fn add_cheat(&mut self, code: &str, name: &str) -> Result<usize, String> {
+        let cheat = Cheat::parse(code, name)?;
+        Ok(self.push_cheat(cheat))
+    }
+
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) -> Result<(), String> {
+        self.cheat_mut(index)?.enabled = enabled;
+        Ok(())
+    }
+
+    pub fn remove_cheat(&mut self, index: usize) -> Result<(), String> {
+        self.cheat_mut(index)?;
+        self.cheats.remove(index);
+        Ok(())
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear();
+    }
+
+    pub fn cheat_count(&self) -> usize {
+        self.cheats.len()
+    }
+
+    /// Adds the cheats from an FCEUX `.cht` file; returns how many.
+    pub fn load_cht(&mut self, text: &str) -> Result<usize, String> {
+        let cheats = parse_cht(text)?;
+        let count = cheats.len();
+        self.cheats.extend(cheats);
+        Ok(count)
+    }
+
+    /// Lists the cheats in `.cht` format, one per line, in index order.
+    pub fn export_cht(&self) -> String {
+        self.cheats.iter().map(|c| c.to_cht() + "\n").collect()
+    }
+}
@@ -0,0 +1,1123 @@
+// lints the original code predates
+#![allow(
+    clippy::assign_op_pattern,
+    clippy::needless_return,
+    clippy::unnecessary_cast
+)]
+
+#[cfg(feature = "std")]
+use crate::capture::Capture;
+use crate::cdl::CodeDataLog;
+use crate::cartridge::{Mirroring, Rom};
+use crate::cheats::Cheat;
+use crate::debugger::{Access, Debugger, StopReason};
+use crate::events::{EventKind, EventLog};
+use crate::joypad::Joypad;
+use crate::machine::{Bare, Bus, Easy6502, Machine, Nes, PROGRAM_START};
+use crate::movie::MovieState;
+use crate::opcodes;
+use crate::ppu::Ppu;
+use crate::ppu_view::PpuViewer;
+use crate::prelude::*;
+use crate::profiler::Profiler;
+use crate::ram_search::RamSearch;
+use crate::rewind::Rewind;
+use crate::trace::trace;
+
+#[derive(Debug, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum AddressingMode {
+    Immediate,
+    ZeroPage,
+    ZeroPage_X,
+    ZeroPage_Y,
+    Absolute,
+    Absolute_X,
+    Absolute_Y,
+    Indirect_X,
+    Indirect_Y,
+    NoneAddressing,
+}
+
+pub struct CPU {
+    pub register_a: u8,
+    pub register_x: u8,
+    pub register_y: u8,
+    //NV1BDIZC
+    pub status: u8,
+    pub program_counter: u16,
+    pub update: bool,
+    pub check: bool,
+    pub save_ram_dirty: bool,
+    pub machine: Machine,
+    pub(crate) memory: [u8; 0x10000],
+    pub stack_ptr: u8,
+    pub cycles: u64,
+    // where the current instruction's operand starts; PC is already past it
+    pub(crate) operand: u16,
+    trace_log: Option<Vec<String>>,
+    pub(crate) debugger: Debugger,
+    pub(crate) rewind: Option<Rewind>,
+    pub(crate) cartridge: Option<Rom>,
+    pub(crate) joypads: [Joypad; 2],
+    pub(crate) movie: Option<MovieState>,
+    #[cfg(feature = "std")]
+    pub(crate) capture: Option<Capture>,
+    // xorshift state behind the easy6502 random byte at $FE
+    pub(crate) random: Option<u32>,
+    pub(crate) cheats: Vec<Cheat>,
+    pub(crate) ram_search: Option<RamSearch>,
+    pub(crate) ppu: Ppu,
+    pub(crate) ppu_viewer: Option<PpuViewer>,
+    pub(crate) events: Option<EventLog>,
+    pub(crate) cdl: Option<CodeDataLog>,
+    pub(crate) profiler: Option<Profiler>,
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CPU {
+    pub fn new() -> Self {
+        CPU {
+            register_a: 0,
+            register_x: 0,
+            register_y: 0,
+            status: 0,
+            program_counter: 0,
+            update: false,
+            check: false,
+            save_ram_dirty: false,
+            machine: Machine::Easy6502,
+            memory: [0; 0x10000],
+            stack_ptr: 0,
+            cycles: 0,
+            operand: 0,
+            trace_log: None,
+            debugger: Debugger::default(),
+            rewind: None,
+            cartridge: None,
+            joypads: [Joypad::default(); 2],
+            movie: None,
+            #[cfg(feature = "std")]
+            capture: None,
+            random: None,
+            cheats: vec![],
+            ram_search: None,
+            ppu: Ppu::new(vec![], Mirroring::Horizontal),
+            ppu_viewer: None,
+            events: None,
+            cdl: None,
+            profiler: None,
+        }
+    }
+
+    pub fn mem_ptr(&self) -> *const u8 {
+        self.memory.as_ptr()
+    }
+
+    /// Loads an easy6502 program and switches to that machine.
+    pub fn load_pro(&mut self, program: Vec<u8>) {
+        self.machine = Machine::Easy6502;
+        self.cartridge = None;
+        self.load(program);
+        self.reset();
+    }
+
+    pub fn mem_write(&mut self, addr: u16, data: u8) {
+        self.debugger.check_access(addr, data, Access::Write);
+        if self.events.is_some() {
+            self.log_write(addr, data);
+        }
+        if self.cdl.is_some() {
+            self.cdl_write(addr, data);
+        }
+        self.bus_write(addr, data);
+    }
+
+    pub fn mem_read(&mut self, addr: u16) -> u8 {
+        if self.cdl.is_some() {
+            self.cdl_read(addr);
+        }
+        let data = self.bus_read(addr);
+        self.debugger.check_access(addr, data, Access::Read);
+        data
+    }
+
+    /// Reads through the machine's memory map, without the debugger
+    /// seeing it.
+    fn bus_read(&mut self, addr: u16) -> u8 {
+        let data = match self.machine {
+            Machine::Easy6502 => Easy6502::read(self, addr),
+            Machine::Nes => Nes::read(self, addr),
+            Machine::Bare => Bare::read(self, addr),
+        };
+        if self.cheats.is_empty() {
+            data
+        } else {
+            self.patch_read(addr, data)
+        }
+    }
+
+    /// A read the 6502 makes for itself: opcode and operand fetches,
+    /// pointers and the dummy reads of its addressing logic. It reaches
+    /// the bus and the bus log like any other, but watchpoints and the
+    /// code/data log only see what the program reads and writes.
+    fn cycle_read(&mut self, addr: u16) -> u8 {
+        let data = self.bus_read(addr);
+        self.debugger.log_cycle(addr, data, Access::Read);
+        data
+    }
+
+    /// The write of the unchanged value that read-modify-write
+    /// instructions make before the real one.
+    fn dummy_write(&mut self, addr: u16, data: u8) {
+        self.debugger.log_cycle(addr, data, Access::Write);
+        self.bus_write(addr, data);
+    }
+
+    /// Reads memory without side effects, for disassembly and inspection.
+    pub fn mem_peek(&self, addr: u16) -> u8 {
+        let data = match self.machine {
+            Machine::Easy6502 => Easy6502::peek(self, addr),
+            Machine::Nes => Nes::peek(self, addr),
+            Machine::Bare => Bare::peek(self, addr),
+        };
+        if self.cheats.is_empty() {
+            data
+        } else {
+            self.patch_read(addr, data)
+        }
+    }
+
+    /// Writes through the machine's memory map, without the debugger
+    /// seeing it.
+    pub(crate) fn bus_write(&mut self, addr: u16, data: u8) {
+        match self.machine {
+            Machine::Easy6502 => Easy6502::write(self, addr, data),
+            Machine::Nes => Nes::write(self, addr, data),
+            Machine::Bare => Bare::write(self, addr, data),
+        }
+    }
+
+    pub fn mem_read_u16(&self, pos: u16) -> u16 {
+        let lo = self.mem_peek(pos) as u16;
+        let hi = self.mem_peek(pos.wrapping_add(1)) as u16;
+        (hi << 8) | (lo as u16)
+    }
+
+    fn mem_write_u16(&mut self, pos: u16, data: u16) {
+        let hi = (data >> 8) as u8;
+        let lo = (data & 0xff) as u8;
+        self.mem_write(pos, lo);
+        self.mem_write(pos.wrapping_add(1), hi);
+    }
+
+    /// Resolves the operand's address with the bus cycles the 6502 spends
+    /// on it. An indexed address is first tried without the carry into
+    /// the high byte; stores and read-modify-write instructions always
+    /// make that dummy read, loads only when the index crosses a page.
+    fn get_operand_address(&mut self, mode: &AddressingMode, write: bool) -> u16 {
+        let pc = self.operand;
+        match mode {
+            AddressingMode::Immediate => pc,
+
+            AddressingMode::ZeroPage => self.cycle_read(pc) as u16,
+
+            AddressingMode::Absolute => {
+                let lo = self.cycle_read(pc) as u16;
+                let hi = self.cycle_read(pc.wrapping_add(1)) as u16;
+                (hi << 8) | lo
+            }
+
+            AddressingMode::ZeroPage_X | AddressingMode::ZeroPage_Y => {
+                let pos = self.cycle_read(pc);
+                self.cycle_read(pos as u16);
+                let index = if mode == &AddressingMode::ZeroPage_X {
+                    self.register_x
+                } else {
+                    self.register_y
+                };
+                pos.wrapping_add(index) as u16
+            }
+
+            AddressingMode::Absolute_X | AddressingMode::Absolute_Y => {
+                let lo = self.cycle_read(pc) as u16;
+                let hi = self.cycle_read(pc.wrapping_add(1)) as u16;
+                let index = if mode == &AddressingMode::Absolute_X {
+                    self.register_x
+                } else {
+                    self.register_y
+                };
+                self.indexed((hi << 8) | lo, index, write)
+            }
+
+            AddressingMode::Indirect_X => {
+                let base = self.cycle_read(pc);
+                self.cycle_read(base as u16);
+
+                let ptr: u8 = base.wrapping_add(self.register_x);
+                let lo = self.cycle_read(ptr as u16);
+                let hi = self.cycle_read(ptr.wrapping_add(1) as u16);
+                (hi as u16) << 8 | (lo as u16)
+            }
+            AddressingMode::Indirect_Y => {
+                let base = self.cycle_read(pc);
+
+                let lo = self.cycle_read(base as u16);
+                let hi = self.cycle_read(base.wrapping_add(1) as u16);
+                let deref_base = (hi as u16) << 8 | (lo as u16);
+                self.indexed(deref_base, self.register_y, write)
+            }
+
+            AddressingMode::NoneAddressing => {
+                panic!("mode {:?} is not supported", mode);
+            }
+        }
+    }
+
+    fn indexed(&mut self, base: u16, index: u8, write: bool) -> u16 {
+        let addr = base.wrapping_add(index as u16);
+        if write || base & 0xFF00 != addr & 0xFF00 {
+            self.cycle_read(base & 0xFF00 | addr & 0x00FF);
+        }
+        addr
+    }
+
+    /// The read and the dummy write of a read-modify-write instruction.
+    fn read_modify(&mut self, mode: &AddressingMode) -> (u16, u8) {
+        let addr = self.get_operand_address(mode, true);
+        let value = self.mem_read(addr);
+        self.dummy_write(addr, value);
+        (addr, value)
+    }
+
+    /// Resolves the effective address of an operand stored at `addr`
+    /// without touching any CPU state, so it is also usable by the tracer.
+    pub fn get_absolute_address(&self, mode: &AddressingMode, addr: u16) -> u16 {
+        match mode {
+            AddressingMode::Immediate => addr,
+
+            AddressingMode::ZeroPage => self.mem_peek(addr) as u16,
+
+            AddressingMode::Absolute => self.mem_read_u16(addr),
+
+            AddressingMode::ZeroPage_X => {
+                let pos = self.mem_peek(addr);
+                pos.wrapping_add(self.register_x) as u16
+            }
+            AddressingMode::ZeroPage_Y => {
+                let pos = self.mem_peek(addr);
+                pos.wrapping_add(self.register_y) as u16
+            }
+
+            AddressingMode::Absolute_X => {
+                let base = self.mem_read_u16(addr);
+                base.wrapping_add(self.register_x as u16)
+            }
+            AddressingMode::Absolute_Y => {
+                let base = self.mem_read_u16(addr);
+                base.wrapping_add(self.register_y as u16)
+            }
+
+            AddressingMode::Indirect_X => {
+                let base = self.mem_peek(addr);
+
+                let ptr: u8 = base.wrapping_add(self.register_x);
+                let lo = self.mem_peek(ptr as u16);
+                let hi = self.mem_peek(ptr.wrapping_add(1) as u16);
+                (hi as u16) << 8 | (lo as u16)
+            }
+            AddressingMode::Indirect_Y => {
+                let base = self.mem_peek(addr);
+
+                let lo = self.mem_peek(base as u16);
+                let hi = self.mem_peek(base.wrapping_add(1) as u16);
+                let deref_base = (hi as u16) << 8 | (lo as u16);
+                deref_base.wrapping_add(self.register_y as u16)
+            }
+
+            AddressingMode::NoneAddressing => {
+                panic!("mode {:?} is not supported", mode);
+            }
+        }
+    }
+
+    pub fn load(&mut self, program: Vec<u8>) {
+        let start = PROGRAM_START as usize;
+        self.memory[start..(start + program.len())].copy_from_slice(&program[..]);
+        self.mem_write_u16(0xFFFC, PROGRAM_START);
+    }
+
+    pub fn reset(&mut self) {
+        self.register_a = 0;
+        self.register_x = 0;
+        self.register_y = 0;
+        self.status = 0;
+        self.stack_ptr = 0xFD;
+        self.program_counter = self.mem_read_u16(0xFFFC);
+        //the reset sequence itself takes 7 cycles
+        self.cycles = 7;
+    }
+
+    pub fn load_and_run(&mut self, program: Vec<u8>) {
+        self.load(program);
+        self.reset();
+        self.run()
+    }
+
+    pub fn get_value(&mut self, mode: &AddressingMode) -> u8 {
+        let addr = self.get_operand_address(mode, false);
+        self.mem_read(addr)
+    }
+
+    pub fn reset_update(&mut self) {
+        self.update = false;
+    }
+
+    /// The console's reset button: RAM, A/X/Y and the cycle count are
+    /// kept, the stack pointer drops by 3 and interrupts are disabled.
+    pub fn soft_reset(&mut self) {
+        self.stack_ptr = self.stack_ptr.wrapping_sub(3);
+        self.status |= 0b0000_0100;
+        self.program_counter = self.mem_read_u16(0xFFFC);
+    }
+
+    pub fn enable_trace(&mut self) {
+        self.trace_log = Some(Vec::new());
+    }
+
+    pub fn disable_trace(&mut self) {
+        self.trace_log = None;
+    }
+
+    /// Returns the trace lines collected since the last call, one
+    /// nestest.log formatted line per executed instruction.
+    pub fn take_trace(&mut self) -> String {
+        match self.trace_log.as_mut() {
+            Some(log) => {
+                let lines = log.join("\n");
+                log.clear();
+                lines
+            }
+            None => String::new(),
+        }
+    }
+
+    /// Executes one instruction; true when the program stopped. That is
+    /// BRK on easy6502, where it ends programs, or an opcode the core does
+    /// not implement, which jams the CPU with PC left on it. Elsewhere BRK
+    /// is an interrupt through $FFFE.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> bool {
+        if self.trace_log.is_some() {
+            let line = trace(self);
+            if let Some(log) = self.trace_log.as_mut() {
+                log.push(line);
+            }
+        }
+
+        if self.machine == Machine::Easy6502 {
+            Easy6502::before_instruction(self);
+        }
+
+        let frame = self.frame();
+        let dots = self.cycles * 3;
+        //let opscode = self.mem_read(self.program_counter);
+        let code = self.cycle_read(self.program_counter);
+        //println!("{:x}", code);
+        let opcode = match opcodes::opcode(code) {
+            Some(opcode) => opcode,
+            //the CPU jams on the opcode, as the 6502 does on KIL
+            None => return true,
+        };
+        self.program_counter = self.program_counter.wrapping_add(1);
+        self.operand = self.program_counter;
+
+        //println!("{}", opcode.name);
+        if self.cdl.is_some() {
+            self.cdl_instruction(self.operand.wrapping_sub(1), opcode);
+        }
+        self.cycles += opcode.cycles as u64;
+        if self.page_crossed(opcode) {
+            self.cycles += 1;
+        }
+        //one byte instructions read the next byte anyway
+        if opcode.bytes == 1 {
+            self.cycle_read(self.program_counter);
+        }
+        //PC moves past the operand first, so jumps and branches simply
+        //overwrite it
+        self.program_counter = self.operand.wrapping_add((opcode.bytes - 1) as u16);
+
+        match code {
+            0x85 | 0x95 | 0x8D | 0x9D | 0x99 | 0x81 | 0x91 => {
+                self.write_reg(&opcode.address_mode, self.register_a)
+            }
+            0x86 | 0x96 | 0x8E => self.write_reg(&opcode.address_mode, self.register_x),
+            0x84 | 0x94 | 0x8C => self.write_reg(&opcode.address_mode, self.register_y),
+            0xA9 | 0xA5 | 0xB5 | 0xAD | 0xBD | 0xB9 | 0xA1 | 0xB1 => self.lda(&opcode.address_mode),
+            0xA2 | 0xA6 | 0xB6 | 0xAE | 0xBE => self.ldx(&opcode.address_mode),
+            0xA0 | 0xA4 | 0xB4 | 0xAC | 0xBC => self.ldy(&opcode.address_mode),
+            0xAA => self.tax(),
+            0x8A => self.txa(),
+            0xCA => self.dex(),
+            0xE8 => self.inx(),
+            0xA8 => self.tay(),
+            0x98 => self.tya(),
+            0x88 => self.dey(),
+            0xC8 => self.iny(),
+            0x29 | 0x25 | 0x35 | 0x2D | 0x3D | 0x39 | 0x21 | 0x31 => self.and(&opcode.address_mode),
+            0x49 | 0x45 | 0x55 | 0x4D | 0x5D | 0x59 | 0x41 | 0x51 => self.eor(&opcode.address_mode),
+            0x09 | 0x05 | 0x15 | 0x0D | 0x1D | 0x19 | 0x01 | 0x11 => self.ora(&opcode.address_mode),
+            0x69 | 0x65 | 0x75 | 0x6D | 0x7D | 0x79 | 0x61 | 0x71 => self.adc(&opcode.address_mode),
+            0xE9 | 0xE5 | 0xF5 | 0xED | 0xFD | 0xF9 | 0xE1 | 0xF1 => self.sbc(&opcode.address_mode),
+            0x10 => self.branch((self.status & 0b1000_0000) == 0), // BPL if not negative flag
+            0x30 => self.branch((self.status & 0b1000_0000) != 0), // BMI if negative flag
+            0x50 => self.branch((self.status & 0b0100_0000) == 0), // BVC if not overflow flag
+            0x70 => self.branch((self.status & 0b0100_0000) != 0), // BVS if overflow flag
+            0x90 => self.branch((self.status & 0b0000_0001) == 0), // BCC if not clear flag
+            0xB0 => self.branch((self.status & 0b0000_0001) != 0), // BCS if clear flag
+            0xD0 => self.branch((self.status & 0b0000_0010) == 0), // BNE if not zero flag
+            0xF0 => self.branch((self.status & 0b0000_0010) != 0), // BEQ if zero flag
+            0xE6 | 0xF6 | 0xEE | 0xFE => self.inc(&opcode.address_mode),
+            0x18 => self.rem_flag(0b1111_1110),
+            0x38 => self.set_flag(0b0000_0001),
+            0x58 => self.rem_flag(0b1111_1011),
+            0x78 => self.set_flag(0b0000_0100),
+            0xB8 => self.rem_flag(0b1011_1111),
+            0xD8 => self.rem_flag(0b1111_0111),
+            0xF8 => self.set_flag(0b0000_1000),
+            0x24 | 0x2C => self.bit(&opcode.address_mode),
+            0xC9 | 0xC5 | 0xD5 | 0xCD | 0xDD | 0xD9 | 0xC1 | 0xD1 => {
+                self.compare(self.register_a, &opcode.address_mode)
+            }
+            0xE0 | 0xE4 | 0xEC => self.compare(self.register_x, &opcode.address_mode),
+            0xC0 | 0xC4 | 0xCC => self.compare(self.register_y, &opcode.address_mode),
+            0xC6 | 0xD6 | 0xCE | 0xDE => self.dec(&opcode.address_mode),
+            0x0A | 0x06 | 0x16 | 0x0E | 0x1E => self.asl(&opcode.address_mode),
+            0x4C | 0x6C => self.jmp(&opcode.address_mode),
+            0x9A => self.stack_ptr = self.register_x,
+            0xBA => self.tsx(),
+            0x48 => self.push_stack(self.register_a),
+            0x68 => self.pla(),
+            0x08 => self.push_stack(self.status | 0b0011_0000), //B and bit 5 are set on the stack copy
+            0x28 => self.plp(),
+            0x20 => self.jsr(),
+            0x60 => self.rts(),
+            0x2A => self.register_a = self.rol_val(self.register_a),
+            0x26 | 0x36 | 0x2E | 0x3E => {
+                let (addr, value) = self.read_modify(&opcode.address_mode);
+                let rolled = self.rol_val(value);
+                self.mem_write(addr, rolled)
+            }
+            0x6A => self.register_a = self.ror_val(self.register_a),
+            0x66 | 0x76 | 0x6E | 0x7E => {
+                let (addr, value) = self.read_modify(&opcode.address_mode);
+                let rolled = self.ror_val(value);
+                self.mem_write(addr, rolled)
+            }
+            0x4A => self.register_a = self.lsr_val(self.register_a),
+            0x46 | 0x56 | 0x4E | 0x5E => {
+                let (addr, value) = self.read_modify(&opcode.address_mode);
+                let rolled = self.lsr_val(value);
+                self.mem_write(addr, rolled)
+            }
+            0x40 => self.rti(),
+            0xEA => (),
+            //easy6502 programs end with BRK
+            0x00 if self.machine == Machine::Easy6502 => return true,
+            0x00 => self.brk(),
+            //listed in the table but not handled: jam like an unknown opcode
+            _ => {
+                self.program_counter = self.operand.wrapping_sub(1);
+                return true;
+            }
+        }
+
+        if self.machine == Machine::Nes {
+            self.ppu_catch_up(dots);
+            if self.ppu.nmi {
+                self.nmi();
+            }
+        }
+        if self.profiler.is_some() {
+            self.profile_charge();
+        }
+        if self.frame() != frame {
+            self.frame_done();
+        }
+
+        return false;
+    }
+
+    pub fn run(&mut self) {
+        self.run_with_callback(|_| {});
+    }
+
+    /// Runs until the next frame begins, so the display and any capture
+    /// are ready for it. `update` says whether the display changed.
+    /// easy6502 programs pace themselves by the display rather than by
+    /// frames, so there it also stops after the first display write.
+    /// Breakpoints and watchpoints stop it early; the next call carries
+    /// on from there.
+    pub fn run_frame(&mut self) -> FrameStatus {
+        self.update = false;
+        let frame = self.frame();
+        let paced = self.machine == Machine::Easy6502;
+        match self.run_until(u32::MAX, |cpu, _| cpu.frame() != frame || (paced && cpu.update)) {
+            StopReason::Brk => FrameStatus::Brk,
+            StopReason::Jam => FrameStatus::Jam,
+            StopReason::Breakpoint => FrameStatus::Breakpoint,
+            StopReason::Watchpoint => FrameStatus::Watchpoint,
+            StopReason::Step | StopReason::Limit => FrameStatus::Complete,
+        }
+    }
+}
+
+/// How far `run_frame` got.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameStatus {
+    /// The frame ran to the end, or on easy6502 to a display write.
+    Complete,
+    /// The program executed BRK.
+    Brk,
+    /// The CPU jammed on an opcode it does not implement.
+    Jam,
+    /// Execution reached an address with a breakpoint.
+    Breakpoint,
+    /// An instruction touched a watched address.
+    Watchpoint,
+}
+
+impl CPU {
+    // per-frame hooks, run after the instruction that starts a new frame
+    fn frame_done(&mut self) {
+        if self.events.is_some() {
+            self.events_frame_done();
+        }
+        if self.cdl.is_some() {
+            self.cdl_frame_done();
+        }
+        if self.profiler.is_some() {
+            self.profile_frame_done();
+        }
+        if !self.cheats.is_empty() {
+            self.apply_freezes();
+        }
+        if self.movie.is_some() {
+            self.movie_frame_done();
+        }
+        if self.rewind.is_some() {
+            self.record_rewind();
+        }
+        #[cfg(feature = "std")]
+        if self.capture.is_some() {
+            self.record_capture();
+        }
+    }
+
+    /// Takes the NMI the PPU raised: pushes PC and the status and jumps
+    /// through $FFFA.
+    fn nmi(&mut self) {
+        self.ppu.nmi = false;
+        let dots = self.cycles * 3;
+        if self.events.is_some() {
+            self.log_event(EventKind::Nmi, 0xfffa, 0);
+        }
+        let pc = self.program_counter;
+        //the opcode at PC is fetched twice and dropped
+        self.cycle_read(pc);
+        self.cycle_read(pc);
+        self.push_stack((pc >> 8) as u8);
+        self.push_stack((pc & 0xff) as u8);
+        self.push_stack(self.status & 0b1110_1111 | 0b0010_0000);
+        self.status |= 0b0000_0100;
+        let lo = self.cycle_read(0xFFFA) as u16;
+        let hi = self.cycle_read(0xFFFB) as u16;
+        self.program_counter = (hi << 8) | lo;
+        if self.profiler.is_some() {
+            self.profile_call();
+        }
+        self.cycles += 7;
+        self.ppu_catch_up(dots);
+    }
+
+    /// Runs until `next` stops, calling `callback` before every
+    /// instruction.
+    pub fn run_with_callback<F>(&mut self, mut callback: F)
+    where
+        F: FnMut(&mut CPU),
+    {
+        loop {
+            callback(self);
+            if self.next() {
+                //return true if needed to break
+                break;
+            }
+        }
+    }
+
+    /// Scanline and dot the PPU would be on, given that it runs three dots
+    /// per CPU cycle across 262 scanlines of 341 dots.
+    pub fn ppu_position(&self) -> (u64, u64) {
+        let dots = self.cycles * 3;
+        ((dots / 341) % 262, dots % 341)
+    }
+
+    /// Number of whole video frames elapsed since power-on.
+    pub fn frame(&self) -> u64 {
+        self.cycles * 3 / (341 * 262)
+    }
+
+    /// The 16K PRG bank mapped at `addr`, for matching banked symbols.
+    /// Only NROM is supported, so this is the fixed layout with a single
+    /// 16K bank mirrored. Unknown outside cartridge ROM or without PRG ROM.
+    pub fn prg_bank(&self, addr: u16) -> Option<u8> {
+        let rom = self.cartridge.as_ref()?;
+        if addr < 0x8000 {
+            return None;
+        }
+        let banks = rom.prg_rom.len() / 0x4000;
+        if banks == 0 {
+            return None;
+        }
+        Some(((addr as usize - 0x8000) / 0x4000 % banks) as u8)
+    }
+
+    /// Size in bytes of the instruction at `addr`, 1 for unknown opcodes.
+    pub fn instruction_len(&self, addr: u16) -> u8 {
+        opcodes::opcode(self.mem_peek(addr))
+            .map_or(1, |op| op.bytes)
+    }
+
+    /// Read instructions take an extra cycle when the indexed address
+    /// lands on a different page than the base address.
+    fn page_crossed(&self, opcode: &opcodes::OpCode) -> bool {
+        match opcode.name {
+            "LDA" | "LDX" | "LDY" | "EOR" | "AND" | "ORA" | "ADC" | "SBC" | "CMP" => {}
+            _ => return false,
+        }
+        let base = match opcode.address_mode {
+            AddressingMode::Absolute_X | AddressingMode::Absolute_Y => {
+                self.mem_read_u16(self.operand)
+            }
+            AddressingMode::Indirect_Y => {
+                let ptr = self.mem_peek(self.operand);
+                let lo = self.mem_peek(ptr as u16);
+                let hi = self.mem_peek(ptr.wrapping_add(1) as u16);
+                (hi as u16) << 8 | (lo as u16)
+            }
+            _ => return false,
+        };
+        let addr = self.get_absolute_address(&opcode.address_mode, self.operand);
+        base & 0xFF00 != addr & 0xFF00
+    }
+
+    fn write_reg(&mut self, mode: &AddressingMode, reg: u8) {
+        let addr = self.get_operand_address(mode, true);
+        self.mem_write(addr, reg);
+    }
+
+    fn add_to_reg_a(&mut self, value: u8) {
+        let mut sum: u16 = (self.register_a as u16)
+            + (value as u16)
+            + (if self.status & 0b0000_0001 != 0 { 1 } else { 0 });
+
+        if sum > 0xFF {
+            sum = sum - 256;
+            self.status = self.status | 0b0000_0001; //add carry flag
+        } else {
+            self.status = self.status & 0b1111_1110; //remove carry flag
+        }
+
+        if (value ^ (sum as u8)) & ((sum as u8) ^ self.register_a) & 0x80 != 0 {
+            self.status = self.status | 0b0100_0000; //add overflow flag
+        } else {
+            self.status = self.status & 0b1011_1111; //remove overflow flag
+        }
+
+        self.register_a = sum as u8;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn branch(&mut self, cond: bool) {
+        let value = self.cycle_read(self.operand) as i8; //get the jump ammount from next line
+        if cond {
+            let next_addr = self.program_counter;
+            let jump_addr = next_addr.wrapping_add(value as u16);
+
+            //taken branches cost a cycle, plus one more across a page,
+            //each reading at the address as fixed so far
+            self.cycles += 1;
+            self.cycle_read(next_addr);
+            if next_addr & 0xFF00 != jump_addr & 0xFF00 {
+                self.cycles += 1;
+                self.cycle_read(next_addr & 0xFF00 | jump_addr & 0x00FF);
+            }
+
+            self.program_counter = jump_addr;
+        }
+    }
+    //set_flag(0b0000_0001)
+    fn set_flag(&mut self, flag: u8) {
+        self.status = self.status | flag;
+    }
+
+    fn rem_flag(&mut self, flag: u8) {
+        self.status = self.status & flag;
+    }
+
+    fn compare(&mut self, reg: u8, mode: &AddressingMode) {
+        let value = self.get_value(mode);
+        let res = reg.wrapping_sub(value);
+        if reg >= value {
+            //set carry if >=
+            self.status = self.status | 0b0000_0001; //add carry flag
+        } else {
+            self.status = self.status & 0b1111_1110; //remove carry flag
+        }
+        if reg == value {
+            self.status = self.status | 0b0000_0010;
+        } else {
+            self.status = self.status & 0b1111_1101;
+        }
+
+        if res & 0b1000_0000 != 0 {
+            self.status = self.status | 0b1000_0000;
+        } else {
+            self.status = self.status & 0b0111_1111;
+        }
+    }
+
+    //the stack pointer points at the next free byte
+    fn push_stack(&mut self, data: u8) {
+        self.mem_write(0x0100 + (self.stack_ptr as u16), data);
+        self.stack_ptr = self.stack_ptr.wrapping_sub(1);
+    }
+
+    fn pull_stack(&mut self) -> u8 {
+        self.stack_ptr = self.stack_ptr.wrapping_add(1);
+        self.mem_read(0x0100 + (self.stack_ptr as u16))
+    }
+
+    //the cycle spent incrementing S before the first pull reads the stack
+    fn stack_dummy_read(&mut self) {
+        self.cycle_read(0x0100 + (self.stack_ptr as u16));
+    }
+
+    fn bit(&mut self, mode: &AddressingMode) {
+        let value = self.get_value(mode);
+        let res = self.register_a & value;
+        //check Z flag
+        if res == 0 {
+            self.status = self.status | 0b0000_0010;
+        } else {
+            self.status = self.status & 0b1111_1101;
+        }
+        //check N flag
+        if value & 0b1000_0000 != 0 {
+            self.status = self.status | 0b1000_0000;
+        } else {
+            self.status = self.status & 0b0111_1111;
+        }
+        //check V flag
+        if value & 0b0100_0000 != 0 {
+            self.status = self.status | 0b0100_0000;
+        } else {
+            self.status = self.status & 0b1011_1111;
+        }
+    }
+
+    fn adc(&mut self, mode: &AddressingMode) {
+        let value = self.get_value(mode);
+        self.add_to_reg_a(value);
+    }
+
+    fn sbc(&mut self, mode: &AddressingMode) {
+        let value = self.get_value(mode);
+        self.add_to_reg_a((value as i8).wrapping_neg().wrapping_sub(1) as u8);
+    }
+
+    fn lda(&mut self, mode: &AddressingMode) {
+        let value = self.get_value(mode);
+        self.register_a = value;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn ldx(&mut self, mode: &AddressingMode) {
+        let value = self.get_value(mode);
+        self.register_x = value;
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    fn ldy(&mut self, mode: &AddressingMode) {
+        let value = self.get_value(mode);
+        self.register_y = value;
+        self.update_zero_and_negative_flags(self.register_y);
+    }
+
+    fn tax(&mut self) {
+        self.register_x = self.register_a;
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    fn txa(&mut self) {
+        self.register_a = self.register_x;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn inx(&mut self) {
+        if self.register_x == 255 {
+            self.register_x = 0;
+        } else {
+            self.register_x += 1;
+        }
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    fn dex(&mut self) {
+        if self.register_x == 0 {
+            self.register_x = 255;
+        } else {
+            self.register_x -= 1;
+        }
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    fn tay(&mut self) {
+        self.register_y = self.register_a;
+        self.update_zero_and_negative_flags(self.register_y);
+    }
+
+    fn tya(&mut self) {
+        self.register_a = self.register_y;
+        self.update_zero_and_negative_flags(self.register_y);
+    }
+
+    fn iny(&mut self) {
+        if self.register_y == 255 {
+            self.register_y = 0;
+        } else {
+            self.register_y += 1;
+        }
+        self.update_zero_and_negative_flags(self.register_y);
+    }
+
+    fn dey(&mut self) {
+        if self.register_y == 0 {
+            self.register_y = 255;
+        } else {
+            self.register_y -= 1;
+        }
+        self.update_zero_and_negative_flags(self.register_y);
+    }
+
+    fn and(&mut self, mode: &AddressingMode) {
+        let value = self.get_value(mode);
+        self.register_a = self.register_a & value;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn ora(&mut self, mode: &AddressingMode) {
+        let value = self.get_value(mode);
+        self.register_a = self.register_a | value;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn eor(&mut self, mode: &AddressingMode) {
+        let value = self.get_value(mode);
+        self.register_a = self.register_a ^ value;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn inc(&mut self, mode: &AddressingMode) {
+        let (addr, value) = self.read_modify(mode);
+        let value = value.wrapping_add(1);
+        self.mem_write(addr, value);
+        self.update_zero_and_negative_flags(value);
+    }
+
+    fn dec(&mut self, mode: &AddressingMode) {
+        let (addr, value) = self.read_modify(mode);
+        let value = value.wrapping_sub(1);
+        self.mem_write(addr, value);
+        self.update_zero_and_negative_flags(value);
+    }
+
+    fn asl(&mut self, mode: &AddressingMode) {
+        let old_val: u8;
+        let new_val: u8;
+        if mode == &AddressingMode::NoneAddressing {
+            old_val = self.register_a;
+            self.register_a = old_val << 1;
+            new_val = self.register_a;
+        } else {
+            let (addr, value) = self.read_modify(mode);
+            old_val = value;
+            new_val = old_val << 1;
+            self.mem_write(addr, new_val);
+        }
+
+        self.update_zero_and_negative_flags(new_val);
+
+        if old_val & 0b1000_0000 != 0 {
+            self.status = self.status | 0b0000_0001;
+        } else {
+            self.status = self.status & 0b1111_1110;
+        }
+    }
+
+    fn jmp(&mut self, mode: &AddressingMode) {
+        if mode == &AddressingMode::Absolute {
+            let mem_address = self.get_operand_address(mode, false);
+            self.program_counter = mem_address;
+        } else {
+            let mem_address = self.get_operand_address(&AddressingMode::Absolute, false);
+
+            //the pointer's high byte comes from the same page
+            let lo = self.cycle_read(mem_address);
+            let hi = self.cycle_read(mem_address & 0xFF00 | mem_address.wrapping_add(1) & 0x00FF);
+            let indirect_ref = (hi as u16) << 8 | (lo as u16);
+
+            self.program_counter = indirect_ref;
+        }
+    }
+
+    fn tsx(&mut self) {
+        self.register_x = self.stack_ptr;
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    fn pla(&mut self) {
+        self.stack_dummy_read();
+        self.register_a = self.pull_stack();
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn plp(&mut self) {
+        self.stack_dummy_read();
+        self.status = self.pull_stack();
+        self.status &= 0b1110_1111;
+        self.status |= 0b0010_0000;
+    }
+
+    //the target's high byte is fetched only after the return address is
+    //pushed
+    fn jsr(&mut self) {
+        let target_lo = self.cycle_read(self.operand) as u16;
+        self.stack_dummy_read();
+        let addr = self.operand.wrapping_add(1); //the last byte of the instruction
+        let hi = (addr >> 8) as u8;
+        let lo = (addr & 0xff) as u8;
+        self.push_stack(hi);
+        self.push_stack(lo);
+        let target_hi = self.cycle_read(addr) as u16;
+        self.program_counter = (target_hi << 8) | target_lo;
+        if self.profiler.is_some() {
+            self.profile_call();
+        }
+    }
+
+    fn rts(&mut self) {
+        if self.profiler.is_some() {
+            self.profile_return();
+        }
+        self.stack_dummy_read();
+        let lo = self.pull_stack() as u16;
+        let hi = self.pull_stack() as u16;
+        //read while the return address is incremented
+        self.cycle_read((hi << 8) | lo);
+        self.program_counter = ((hi << 8) | (lo as u16)).wrapping_add(1);
+    }
+
+    fn rol_val(&mut self, val: u8) -> u8 {
+        let mut carry = 0b0000_0000;
+        if self.status & 0b0000_0001 != 0 {
+            carry = 0b0000_0001;
+        }
+
+        if val & 0b1000_0000 != 0 {
+            self.status = self.status | 0b0000_0001;
+        } else {
+            self.status = self.status & 0b1111_1110;
+        }
+        let shifted = val << 1 | carry;
+        self.update_zero_and_negative_flags(shifted);
+        return shifted;
+    }
+
+    fn ror_val(&mut self, val: u8) -> u8 {
+        let mut carry = 0b0000_0000;
+        if self.status & 0b0000_0001 != 0 {
+            carry = 0b1000_0000;
+        }
+
+        if val & 0b0000_0001 != 0 {
+            self.status = self.status | 0b0000_0001;
+        } else {
+            self.status = self.status & 0b1111_1110;
+        }
+        let shifted = val >> 1 | carry;
+        self.update_zero_and_negative_flags(shifted);
+        return shifted;
+    }
+
+    fn lsr_val(&mut self, val: u8) -> u8 {
+        if val & 0b0000_0001 != 0 {
+            self.status = self.status | 0b0000_0001;
+        } else {
+            self.status = self.status & 0b1111_1110;
+        }
+        let shifted = val >> 1;
+        self.update_zero_and_negative_flags(shifted);
+        return shifted;
+    }
+
+    //the padding byte after BRK is skipped on return
+    fn brk(&mut self) {
+        let ret = self.operand.wrapping_add(1);
+        self.push_stack((ret >> 8) as u8);
+        self.push_stack((ret & 0xff) as u8);
+        self.push_stack(self.status | 0b0011_0000);
+        self.status |= 0b0000_0100;
+        let lo = self.cycle_read(0xFFFE) as u16;
+        let hi = self.cycle_read(0xFFFF) as u16;
+        self.program_counter = (hi << 8) | lo;
+        if self.profiler.is_some() {
+            self.profile_call();
+        }
+    }
+
+    fn rti(&mut self) {
+        if self.profiler.is_some() {
+            self.profile_return();
+        }
+        self.stack_dummy_read();
+        self.status = self.pull_stack();
+        self.status = self.status & 0b1110_1111;
+        self.status = self.status | 0b0010_0000;
+
+        let lo = self.pull_stack() as u16;
+        let hi = self.pull_stack() as u16;
+
+        self.program_counter = hi << 8 | lo;
+    }
+
+    fn update_zero_and_negative_flags(&mut self, result: u8) {
+        if result == 0 {
+            self.status = self.status | 0b0000_0010;
+        } else {
+            self.status = self.status & 0b1111_1101;
+        }
+
+        if result & 0b1000_0000 != 0 {
+            self.status = self.status | 0b1000_0000;
+        } else {
+            self.status = self.status & 0b0111_1111;
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use crate::cpu::CPU;
+use crate::expr::Expr;
+use crate::prelude::*;
+use crate::symbols::SymbolTable;
+use crate::trace::{byte_line, disassemble};
+
+/// Why a debugger run or step returned control to the caller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested step, step-over, step-out or run-to completed.
+    Step,
+    /// Execution reached an address with a breakpoint.
+    Breakpoint,
+    /// An instruction touched a watched address.
+    Watchpoint,
+    /// The program executed BRK.
+    Brk,
+    /// The CPU jammed on an opcode it does not implement.
+    Jam,
+    /// The instruction limit ran out first.
+    Limit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    fn matches(&self, addr: u16, access: Access) -> bool {
+        let wanted = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        };
+        wanted && addr >= self.start && addr <= self.end
+    }
+}
+
+/// A memory access: the one that triggered a watchpoint, or an entry in
+/// the bus log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: u16,
+    pub value: u8,
+    pub access: Access,
+}
+
+/// An execution breakpoint. Without an address the condition is checked
+/// before every instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: Option<u16>,
+    pub condition: Option<Expr>,
+    /// Times execution has reached the breakpoint, whether or not the
+    /// condition held.
+    pub hits: u32,
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    hit: Option<WatchHit>,
+    last_hit: Option<WatchHit>,
+    symbols: SymbolTable,
+    bus_log: Option<Vec<WatchHit>>,
+    // where and when the last breakpoint stop happened, as PC and cycle
+    // count; the next run steps off it without stopping again
+    resume_from: Option<(u16, u64)>,
+}
+
+impl Debugger {
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        if !self.has_breakpoint(addr) {
+            self.breakpoints.push(Breakpoint {
+                addr: Some(addr),
+                condition: None,
+                hits: 0,
+            });
+        }
+    }
+
+    /// Adds a breakpoint that only stops when `condition` holds, see
+    /// [`Expr`] for the syntax.
+    pub fn add_conditional_breakpoint(
+        &mut self,
+        addr: Option<u16>,
+        condition: &str,
+    ) -> Result<(), String> {
+        let condition = Expr::parse_with_symbols(condition, &self.symbols)?;
+        self.breakpoints.push(Breakpoint {
+            addr,
+            condition: Some(condition),
+            hits: 0,
+        });
+        Ok(())
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.retain(|b| b.addr != Some(addr));
+    }
+
+    pub fn has_breakpoint(&self, addr: u16) -> bool {
+        self.breakpoints.iter().any(|b| b.addr == Some(addr))
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Watches `start..=end`, which may cover RAM as well as PPU, APU or
+    /// mapper registers.
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, read: bool, write: bool) {
+        self.watchpoints.push(Watchpoint {
+            start,
+            end,
+            read,
+            write,
+        });
+    }
+
+    pub fn remove_watchpoint(&mut self, start: u16, end: u16) {
+        self.watchpoints
+            .retain(|w| !(w.start == start && w.end == end));
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.hit = None;
+        self.last_hit = None;
+        self.resume_from = None;
+    }
+
+    pub fn last_hit(&self) -> Option<WatchHit> {
+        self.last_hit
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn symbols_mut(&mut self) -> &mut SymbolTable {
+        &mut self.symbols
+    }
+
+    /// Adds a breakpoint on a label, e.g. `reset_handler`.
+    pub fn add_label_breakpoint(&mut self, label: &str) -> Result<(), String> {
+        let addr = self
+            .symbols
+            .lookup(label)
+            .map(|s| s.addr)
+            .ok_or_else(|| format!("unknown label '{}'", label))?;
+        self.add_breakpoint(addr);
+        Ok(())
+    }
+
+    /// Starts or stops recording every bus cycle: opcode and operand
+    /// fetches, dummy accesses and the program's reads and writes.
+    pub fn log_bus(&mut self, enabled: bool) {
+        self.bus_log = if enabled { Some(vec![]) } else { None };
+    }
+
+    pub fn take_bus_log(&mut self) -> Vec<WatchHit> {
+        self.bus_log.as_mut().map(core::mem::take).unwrap_or_default()
+    }
+
+    pub(crate) fn log_cycle(&mut self, addr: u16, value: u8, access: Access) {
+        if let Some(log) = self.bus_log.as_mut() {
+            log.push(WatchHit {
+                addr,
+                value,
+                access,
+            });
+        }
+    }
+
+    pub(crate) fn check_access(&mut self, addr: u16, value: u8, access: Access) {
+        self.log_cycle(addr, value, access);
+        if self.hit.is_none() && self.watchpoints.iter().any(|w| w.matches(addr, access)) {
+            self.hit = Some(WatchHit {
+                addr,
+                value,
+                access,
+            });
+        }
+    }
+}
+
+impl CPU {
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Counts a hit on every breakpoint at the program counter and reports
+    /// whether any of them should stop execution.
+    fn breakpoint_hit(&mut self) -> bool {
+        let mut stop = false;
+        for i in 0..self.debugger.breakpoints.len() {
+            let bp = &self.debugger.breakpoints[i];
+            if bp.addr.is_some() && bp.addr != Some(self.program_counter) {
+                continue;
+            }
+            let hits = bp.hits + 1;
+            let holds = match &bp.condition {
+                Some(condition) => condition.eval(self, hits) != 0,
+                None => true,
+            };
+            self.debugger.breakpoints[i].hits = hits;
+            stop |= holds;
+        }
+        stop
+    }
+
+    /// Executes one instruction and reports whether it stopped on BRK,
+    /// jammed or hit a watchpoint, along with the opcode that ran.
+    fn debug_step(&mut self) -> (u8, Option<StopReason>) {
+        let code = self.mem_peek(self.program_counter);
+        self.debugger.hit = None;
+        self.debugger.last_hit = None;
+        if self.next() {
+            let reason = if code == 0x00 {
+                StopReason::Brk
+            } else {
+                StopReason::Jam
+            };
+            return (code, Some(reason));
+        }
+        if let Some(hit) = self.debugger.hit.take() {
+            self.debugger.last_hit = Some(hit);
+            return (code, Some(StopReason::Watchpoint));
+        }
+        (code, None)
+    }
+
+    /// Runs until `done` returns true after an instruction, stopping early
+    /// on breakpoints, watchpoints, BRK or after `max_instructions`.
+    pub fn run_until<F>(&mut self, max_instructions: u32, mut done: F) -> StopReason
+    where
+        F: FnMut(&CPU, u8) -> bool,
+    {
+        for _ in 0..max_instructions {
+            let here = (self.program_counter, self.cycles);
+            //resuming from a breakpoint stop runs the instruction it stopped on
+            let resuming = self.debugger.resume_from.take() == Some(here);
+            if !resuming && self.breakpoint_hit() {
+                self.debugger.resume_from = Some(here);
+                return StopReason::Breakpoint;
+            }
+            let (code, stop) = self.debug_step();
+            if let Some(reason) = stop {
+                return reason;
+            }
+            if done(self, code) {
+                return StopReason::Step;
+            }
+        }
+        StopReason::Limit
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.debugger.add_breakpoint(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.debugger.remove_breakpoint(addr);
+    }
+
+    pub fn add_conditional_breakpoint(&mut self, addr: u16, condition: &str) -> Result<(), String> {
+        self.debugger
+            .add_conditional_breakpoint(Some(addr), condition)
+    }
+
+    /// Adds a condition checked before every instruction, e.g.
+    /// `[$00FE] == $0F && FRAME > 10`.
+    pub fn add_break_condition(&mut self, condition: &str) -> Result<(), String> {
+        self.debugger.add_conditional_breakpoint(None, condition)
+    }
+
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, read: bool, write: bool) {
+        self.debugger.add_watchpoint(start, end, read, write);
+    }
+
+    pub fn remove_watchpoint(&mut self, start: u16, end: u16) {
+        self.debugger.remove_watchpoint(start, end);
+    }
+
+    pub fn add_label_breakpoint(&mut self, label: &str) -> Result<(), String> {
+        self.debugger.add_label_breakpoint(label)
+    }
+
+    pub fn clear_debugger(&mut self) {
+        self.debugger.clear();
+    }
+
+    /// Loads an FCEUX `.nl` file; `bank` is the PRG bank from its file
+    /// name, or undefined for `.ram.nl`.
+    pub fn load_nl(&mut self, text: &str, bank: Option<u8>) -> Result<usize, String> {
+        self.debugger.symbols.load_nl(text, bank)
+    }
+
+    /// Loads the labels of an ld65 `--dbgfile`.
+    pub fn load_dbg(&mut self, text: &str) -> Result<usize, String> {
+        self.debugger.symbols.load_dbg(text)
+    }
+
+    pub fn clear_symbols(&mut self) {
+        self.debugger.symbols.clear();
+    }
+
+    pub fn label_at(&self, addr: u16) -> Option<String> {
+        self.debugger
+            .symbols
+            .label_at(addr, self.prg_bank(addr))
+            .map(|s| s.to_string())
+    }
+
+    pub fn symbol_addr(&self, label: &str) -> Option<u16> {
+        self.debugger.symbols.lookup(label).map(|s| s.addr)
+    }
+
+    /// Disassembles `count` instructions from `addr`, one per line, with a
+    /// `label:` line before each labelled address. Bytes the code/data
+    /// logger saw only as data are listed as `.byte` lines.
+    pub fn disassemble(&self, addr: u16, count: u32) -> String {
+        let mut lines = vec![];
+        let mut addr = addr;
+        for _ in 0..count {
+            if let Some(label) = self.label_at(addr) {
+                lines.push(format!("{}:", label));
+            }
+            if self.is_logged_data(addr) {
+                lines.push(byte_line(addr, self.mem_peek(addr)));
+                addr = addr.wrapping_add(1);
+                continue;
+            }
+            lines.push(disassemble(self, addr));
+            addr = addr.wrapping_add(self.instruction_len(addr) as u16);
+        }
+        lines.join("\n")
+    }
+
+    /// The access that stopped the last step with `StopReason::Watchpoint`.
+    pub fn last_watch_hit(&self) -> Option<WatchHit> {
+        self.debugger.last_hit
+    }
+
+    pub fn step_into(&mut self) -> StopReason {
+        self.run_until(1, |_, _| true)
+    }
+
+    /// Steps over JSR by running until the call returns to the next
+    /// instruction; any other instruction is a single step.
+    pub fn step_over(&mut self, max_instructions: u32) -> StopReason {
+        if self.mem_peek(self.program_counter) != 0x20 {
+            return self.step_into();
+        }
+        let return_addr = self.program_counter.wrapping_add(3);
+        let stack_ptr = self.stack_ptr;
+        self.run_until(max_instructions, |cpu, _| {
+            cpu.program_counter == return_addr && cpu.stack_ptr == stack_ptr
+        })
+    }
+
+    /// Runs until the RTS or RTI that leaves the current subroutine.
+    pub fn step_out(&mut self, max_instructions: u32) -> StopReason {
+        let stack_ptr = self.stack_ptr;
+        self.run_until(max_instructions, |cpu, code| {
+            (code == 0x60 || code == 0x40) && (cpu.stack_ptr.wrapping_sub(stack_ptr) as i8) > 0
+        })
+    }
+
+    pub fn run_to(&mut self, addr: u16, max_instructions: u32) -> StopReason {
+        self.run_until(max_instructions, |cpu, _| cpu.program_counter == addr)
+    }
+
+    /// Continues until a breakpoint, watchpoint or BRK.
+    pub fn resume(&mut self, max_instructions: u32) -> StopReason {
+        self.run_until(max_instructions, |_, _| false)
+    }
+
+    /// Goes back one rewind snapshot, which is one frame with an interval
+    /// of 1. Needs `enable_rewind`; false once the buffer is used up.
+    pub fn step_back(&mut self) -> bool {
+        self.debugger.last_hit = None;
+        self.rewind()
+    }
+}
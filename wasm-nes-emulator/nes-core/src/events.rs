@@ -0,0 +1,196 @@
+//! The event viewer: every write to the PPU, APU and mapper registers and
+//! every NMI in a frame, tagged with the scanline and dot it happened on,
+//! as a list or as markers on a 341x262 image of the frame.
+
+use crate::cpu::CPU;
+use crate::machine::Machine;
+use crate::prelude::*;
+
+pub const DOTS: usize = 341;
+pub const SCANLINES: usize = 262;
+
+const PPU_REGISTERS: [&str; 8] = [
+    "PPUCTRL",
+    "PPUMASK",
+    "PPUSTATUS",
+    "OAMADDR",
+    "OAMDATA",
+    "PPUSCROLL",
+    "PPUADDR",
+    "PPUDATA",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// A write to $2000-$3FFF or OAM DMA at $4014.
+    PpuWrite,
+    /// A write to the sound registers at $4000-$4013, $4015 or $4017.
+    ApuWrite,
+    /// A write to $8000-$FFFF, where mappers keep their registers.
+    MapperWrite,
+    Nmi,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    /// The register written, with PPU mirrors folded to $2000-$2007, or
+    /// the vector for interrupts.
+    pub addr: u16,
+    pub value: u8,
+    pub scanline: u16,
+    pub dot: u16,
+    /// The instruction that caused it, or where the interrupt struck.
+    pub pc: u16,
+}
+
+impl Event {
+    /// Each PPU register gets its own colour, since those are the writes
+    /// raster effects hinge on.
+    pub fn color(&self) -> [u8; 3] {
+        match (self.kind, self.addr) {
+            (EventKind::PpuWrite, 0x2000) => [0xff, 0x40, 0x40],
+            (EventKind::PpuWrite, 0x2001) => [0xff, 0xa0, 0x00],
+            (EventKind::PpuWrite, 0x2003) => [0xc0, 0x80, 0xff],
+            (EventKind::PpuWrite, 0x2004) => [0x80, 0x40, 0xff],
+            (EventKind::PpuWrite, 0x2005) => [0x40, 0xff, 0x40],
+            (EventKind::PpuWrite, 0x2006) => [0x40, 0xa0, 0xff],
+            (EventKind::PpuWrite, 0x2007) => [0x00, 0xff, 0xff],
+            (EventKind::PpuWrite, _) => [0xff, 0x40, 0xff],
+            (EventKind::ApuWrite, _) => [0xff, 0xff, 0x40],
+            (EventKind::MapperWrite, _) => [0xff, 0xff, 0xff],
+            (EventKind::Nmi, _) => [0x80, 0x80, 0x80],
+        }
+    }
+
+    fn describe(&self) -> String {
+        let what = match self.kind {
+            EventKind::PpuWrite if self.addr == 0x4014 => "OAMDMA".to_string(),
+            EventKind::PpuWrite => PPU_REGISTERS[(self.addr & 7) as usize].to_string(),
+            EventKind::ApuWrite => "APU".to_string(),
+            EventKind::MapperWrite => "mapper".to_string(),
+            EventKind::Nmi => return "NMI".to_string(),
+        };
+        format!("{} ${:04X} = ${:02X}", what, self.addr, self.value)
+    }
+}
+
+fn classify(addr: u16) -> Option<(EventKind, u16)> {
+    match addr {
+        0x2000..=0x3fff => Some((EventKind::PpuWrite, 0x2000 | (addr & 7))),
+        0x4014 => Some((EventKind::PpuWrite, addr)),
+        0x4000..=0x4013 | 0x4015 | 0x4017 => Some((EventKind::ApuWrite, addr)),
+        0x8000..=0xffff => Some((EventKind::MapperWrite, addr)),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+pub struct EventLog {
+    frame: Vec<Event>,
+    last_frame: Vec<Event>,
+}
+
+impl EventLog {
+    /// The frame in progress so far.
+    pub fn frame(&self) -> &[Event] {
+        &self.frame
+    }
+
+    pub fn last_frame(&self) -> &[Event] {
+        &self.last_frame
+    }
+
+    fn events(&self, current: bool) -> &[Event] {
+        if current {
+            &self.frame
+        } else {
+            &self.last_frame
+        }
+    }
+}
+
+impl CPU {
+    pub fn event_log(&self) -> Option<&EventLog> {
+        self.events.as_ref()
+    }
+
+    pub(crate) fn log_event(&mut self, kind: EventKind, addr: u16, value: u8) {
+        let (scanline, dot) = self.ppu_position();
+        // mid-instruction the opcode is just before the operand
+        let pc = match kind {
+            EventKind::Nmi => self.program_counter,
+            _ => self.operand.wrapping_sub(1),
+        };
+        if let Some(log) = self.events.as_mut() {
+            log.frame.push(Event {
+                kind,
+                addr,
+                value,
+                scanline: scanline as u16,
+                dot: dot as u16,
+                pc,
+            });
+        }
+    }
+
+    pub(crate) fn log_write(&mut self, addr: u16, data: u8) {
+        if self.machine != Machine::Nes {
+            return;
+        }
+        if let Some((kind, addr)) = classify(addr) {
+            self.log_event(kind, addr, data);
+        }
+    }
+
+    pub(crate) fn events_frame_done(&mut self) {
+        if let Some(log) = self.events.as_mut() {
+            log.last_frame = core::mem::take(&mut log.frame);
+        }
+    }
+
+    pub fn start_event_log(&mut self) {
+        self.events = Some(EventLog::default());
+    }
+
+    pub fn stop_event_log(&mut self) {
+        self.events = None;
+    }
+
+    /// The events of the last whole frame, or of the frame in progress if
+    /// `current`, one per line: scanline, dot, PC and what happened.
+    pub fn event_list(&self, current: bool) -> String {
+        let events = match self.events.as_ref() {
+            Some(log) => log.events(current),
+            None => return String::new(),
+        };
+        events
+            .iter()
+            .map(|e| {
+                format!(
+                    "{:3} {:3}  ${:04X}  {}",
+                    e.scanline,
+                    e.dot,
+                    e.pc,
+                    e.describe()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The same events as a 341x262 RGBA image, one dot per pixel, with a
+    /// coloured marker on each event and transparency elsewhere, to lay
+    /// over the picture.
+    pub fn event_overlay_rgba(&self, current: bool) -> Vec<u8> {
+        let mut rgba = vec![0; DOTS * SCANLINES * 4];
+        if let Some(log) = self.events.as_ref() {
+            for event in log.events(current) {
+                let i = (event.scanline as usize * DOTS + event.dot as usize) * 4;
+                let [r, g, b] = event.color();
+                rgba[i..i + 4].copy_from_slice(&[r, g, b, 0xff]);
+            }
+        }
+        rgba
+    }
+}
@@ -0,0 +1,361 @@
+use crate::cpu::CPU;
+use crate::prelude::*;
+use crate::symbols::SymbolTable;
+
+/// A parsed breakpoint condition such as `X == 3 && [$00FE] == $0F`.
+///
+/// Values are integers; comparisons and logical operators yield 1 or 0 and
+/// a condition holds when it evaluates to anything but 0.
+///
+/// - numbers: `42`, `$2A`, `0x2A`
+/// - registers: `A`, `X`, `Y`, `P`, `SP`, `PC`
+/// - flags: `C`, `Z`, `I`, `D`, `B`, `V`, `N` (0 or 1)
+/// - timing: `SCANLINE`, `DOT`, `FRAME`, `CYCLES`
+/// - `HITS`: how many times the breakpoint has been reached, this time included
+/// - labels: any loaded symbol name stands for its address; `@name` is
+///   always a label, for labels named like a register or timing value
+/// - memory: `[addr]` reads a byte
+/// - operators: `( )`, `! - ~`, `* /`, `+ -`, `<< >>`, `&`, `^`, `|`,
+///   `== != < <= > >=`, `&&`, `||`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Var(Var),
+    Memory(Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Var {
+    A,
+    X,
+    Y,
+    P,
+    SP,
+    PC,
+    Flag(u8),
+    Scanline,
+    Dot,
+    Frame,
+    Cycles,
+    Hits,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+    BitNot,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    BitAnd,
+    BitXor,
+    BitOr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Label(String),
+    Op(&'static str),
+}
+
+// longest operators first so "<=" is not read as "<"
+const OPERATORS: [&str; 24] = [
+    "<<", ">>", "==", "!=", "<=", ">=", "&&", "||", "(", ")", "[", "]", "!", "-", "~", "*", "/",
+    "+", "&", "^", "|", "<", ">", "=",
+];
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '$' || c.is_ascii_digit() {
+            let (radix, start) = if c == '$' {
+                (16, i + 1)
+            } else if c == '0'
+                && i + 1 < chars.len()
+                && (chars[i + 1] == 'x' || chars[i + 1] == 'X')
+            {
+                (16, i + 2)
+            } else {
+                (10, i)
+            };
+            let mut end = start;
+            while end < chars.len() && chars[end].is_digit(radix) {
+                end += 1;
+            }
+            let digits: String = chars[start..end].iter().collect();
+            let value = i64::from_str_radix(&digits, radix)
+                .map_err(|_| format!("invalid number at position {}", i))?;
+            tokens.push(Token::Number(value));
+            i = end;
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '@' {
+            let start = if c == '@' { i + 1 } else { i };
+            let mut end = start;
+            while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_') {
+                end += 1;
+            }
+            let name: String = chars[start..end].iter().collect();
+            if c == '@' {
+                if name.is_empty() {
+                    return Err(format!("expected a label after '@' at position {}", i));
+                }
+                tokens.push(Token::Label(name));
+            } else {
+                tokens.push(Token::Ident(name));
+            }
+            i = end;
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            let op: &'static str = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| format!("unexpected character '{}' at position {}", c, i))?;
+            // a lone '=' is accepted as '=='
+            tokens.push(Token::Op(if op == "=" { "==" } else { op }));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+fn variable(name: &str) -> Option<Var> {
+    let var = match name.to_ascii_uppercase().as_str() {
+        "A" => Var::A,
+        "X" => Var::X,
+        "Y" => Var::Y,
+        "P" => Var::P,
+        "SP" => Var::SP,
+        "PC" => Var::PC,
+        "C" => Var::Flag(0b0000_0001),
+        "Z" => Var::Flag(0b0000_0010),
+        "I" => Var::Flag(0b0000_0100),
+        "D" => Var::Flag(0b0000_1000),
+        "B" => Var::Flag(0b0001_0000),
+        "V" => Var::Flag(0b0100_0000),
+        "N" => Var::Flag(0b1000_0000),
+        "SCANLINE" => Var::Scanline,
+        "DOT" => Var::Dot,
+        "FRAME" => Var::Frame,
+        "CYCLES" => Var::Cycles,
+        "HITS" => Var::Hits,
+        _ => return None,
+    };
+    Some(var)
+}
+
+// binary operator levels, loosest first
+const LEVELS: [&[(&str, BinaryOp)]; 9] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[
+        ("==", BinaryOp::Eq),
+        ("!=", BinaryOp::Ne),
+        ("<=", BinaryOp::Le),
+        (">=", BinaryOp::Ge),
+        ("<", BinaryOp::Lt),
+        (">", BinaryOp::Gt),
+    ],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[("*", BinaryOp::Mul), ("/", BinaryOp::Div)],
+];
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    symbols: Option<&'a SymbolTable>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected '{}'", op))
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = self.peek_op() {
+            let found = LEVELS[level].iter().find(|(name, _)| *name == op);
+            match found {
+                Some((_, bin)) => {
+                    self.pos += 1;
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary(*bin, Box::new(lhs), Box::new(rhs));
+                }
+                None => break,
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn label(&self, name: &str) -> Option<Expr> {
+        let symbol = self.symbols?.lookup(name)?;
+        Some(Expr::Number(symbol.addr as i64))
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let op = match self.peek_op() {
+            Some("!") => UnaryOp::Not,
+            Some("-") => UnaryOp::Neg,
+            Some("~") => UnaryOp::BitNot,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| "unexpected end of expression".to_string())?;
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Ident(name) => variable(&name)
+                .map(Expr::Var)
+                .or_else(|| self.label(&name))
+                .ok_or_else(|| format!("unknown name '{}'", name)),
+            Token::Label(name) => self
+                .label(&name)
+                .ok_or_else(|| format!("unknown label '{}'", name)),
+            Token::Op("(") => {
+                let inner = self.binary(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Token::Op("[") => {
+                let inner = self.binary(0)?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(inner)))
+            }
+            Token::Op(op) => Err(format!("unexpected '{}'", op)),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(src: &str) -> Result<Expr, String> {
+        Self::parse_symbols(src, None)
+    }
+
+    /// Parses `src`, resolving names that are not registers or timing
+    /// values, and any name written `@name`, as labels from `symbols`.
+    pub fn parse_with_symbols(src: &str, symbols: &SymbolTable) -> Result<Expr, String> {
+        Self::parse_symbols(src, Some(symbols))
+    }
+
+    fn parse_symbols(src: &str, symbols: Option<&SymbolTable>) -> Result<Expr, String> {
+        let mut parser = Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+            symbols,
+        };
+        let expr = parser.binary(0)?;
+        if parser.pos != parser.tokens.len() {
+            return Err(format!("unexpected {:?}", parser.tokens[parser.pos]));
+        }
+        Ok(expr)
+    }
+
+    /// Evaluates against the current CPU state without side effects.
+    pub fn eval(&self, cpu: &CPU, hits: u32) -> i64 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Var(var) => match var {
+                Var::A => cpu.register_a as i64,
+                Var::X => cpu.register_x as i64,
+                Var::Y => cpu.register_y as i64,
+                Var::P => cpu.status as i64,
+                Var::SP => cpu.stack_ptr as i64,
+                Var::PC => cpu.program_counter as i64,
+                Var::Flag(mask) => (cpu.status & mask != 0) as i64,
+                Var::Scanline => cpu.ppu_position().0 as i64,
+                Var::Dot => cpu.ppu_position().1 as i64,
+                Var::Frame => cpu.frame() as i64,
+                Var::Cycles => cpu.cycles as i64,
+                Var::Hits => hits as i64,
+            },
+            Expr::Memory(addr) => cpu.mem_peek(addr.eval(cpu, hits) as u16) as i64,
+            Expr::Unary(op, inner) => {
+                let value = inner.eval(cpu, hits);
+                match op {
+                    UnaryOp::Not => (value == 0) as i64,
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::BitNot => !value,
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let l = lhs.eval(cpu, hits);
+                // short-circuit so memory behind a false guard is not read
+                match op {
+                    BinaryOp::And if l == 0 => return 0,
+                    BinaryOp::Or if l != 0 => return 1,
+                    _ => {}
+                }
+                let r = rhs.eval(cpu, hits);
+                match op {
+                    BinaryOp::Mul => l.wrapping_mul(r),
+                    BinaryOp::Div => l.checked_div(r).unwrap_or(0),
+                    BinaryOp::Add => l.wrapping_add(r),
+                    BinaryOp::Sub => l.wrapping_sub(r),
+                    BinaryOp::Shl => l.wrapping_shl(r as u32),
+                    BinaryOp::Shr => l.wrapping_shr(r as u32),
+                    BinaryOp::BitAnd => l & r,
+                    BinaryOp::BitXor => l ^ r,
+                    BinaryOp::BitOr => l | r,
+                    BinaryOp::Eq => (l == r) as i64,
+                    BinaryOp::Ne => (l != r) as i64,
+                    BinaryOp::Lt => (l < r) as i64,
+                    BinaryOp::Le => (l <= r) as i64,
+                    BinaryOp::Gt => (l > r) as i64,
+                    BinaryOp::Ge => (l >= r) as i64,
+                    BinaryOp::And | BinaryOp::Or => (r != 0) as i64,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,420 @@
+//! A GDB remote serial protocol stub for the 6502 core.
+//!
+//! Registers are exposed in the order `a x y p sp pc` (8-bit, with a
+//! 16-bit little-endian `pc`) and described to the client through
+//! `qXfer:features:read:target.xml`. Software breakpoints map onto the
+//! debugger's breakpoints and `Z2`-`Z4` onto its watchpoints.
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::cpu::CPU;
+use crate::debugger::{Access, StopReason};
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.nes.6502.core">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>"#;
+
+/// Instructions run between checks for a client interrupt while continuing.
+const POLL_INTERVAL: u32 = 10_000;
+
+/// The largest packet we accept, advertised in `qSupported`.
+const PACKET_SIZE: u32 = 0x1000;
+// each byte read takes two hex digits in the reply
+const MAX_READ: u32 = PACKET_SIZE / 2;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+/// A byte stream to a GDB client.
+pub trait Connection: Read + Write {
+    /// Returns true, without blocking, if the client sent an interrupt
+    /// (`0x03`) while the target was running.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+impl Connection for TcpStream {
+    fn interrupted(&mut self) -> io::Result<bool> {
+        let mut buf = [0u8; 1];
+        self.set_nonblocking(true)?;
+        let peeked = self.peek(&mut buf);
+        self.set_nonblocking(false)?;
+        match peeked {
+            Ok(1) if buf[0] == 0x03 => {
+                self.read_exact(&mut buf)?;
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+pub struct GdbStub<'a, C: Connection> {
+    cpu: &'a mut CPU,
+    conn: C,
+    no_ack: bool,
+}
+
+impl<'a, C: Connection> GdbStub<'a, C> {
+    pub fn new(cpu: &'a mut CPU, conn: C) -> Self {
+        GdbStub {
+            cpu,
+            conn,
+            no_ack: false,
+        }
+    }
+
+    /// Serves packets until the client detaches, kills the target or
+    /// closes the connection.
+    pub fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle(&packet) {
+                Some(reply) => self.send_packet(&reply)?,
+                None => {
+                    if packet == "D" {
+                        self.send_packet("OK")?;
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn into_connection(self) -> C {
+        self.conn
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut buf = [0u8; 1];
+        match self.conn.read(&mut buf)? {
+            0 => Ok(None),
+            _ => Ok(Some(buf[0])),
+        }
+    }
+
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let byte = match self.read_byte()? {
+                Some(byte) => byte,
+                None => return Ok(None),
+            };
+            match byte {
+                b'$' => {}
+                // an interrupt while stopped just reports the stop again
+                0x03 => return Ok(Some("?".to_string())),
+                // acks and noise between packets
+                _ => continue,
+            }
+
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let mut checksum = [0u8; 2];
+            self.conn.read_exact(&mut checksum)?;
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+            let sum = data.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+            if expected != Some(sum) {
+                if !self.no_ack {
+                    self.conn.write_all(b"-")?;
+                }
+                continue;
+            }
+            if !self.no_ack {
+                self.conn.write_all(b"+")?;
+            }
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let sum = data.bytes().fold(0u8, |acc, b| acc.wrapping_add(b));
+        write!(self.conn, "${}#{:02x}", data, sum)?;
+        self.conn.flush()
+    }
+
+    /// Returns the reply to send, or `None` when the session is over.
+    fn handle(&mut self, packet: &str) -> Option<String> {
+        let (cmd, args) = packet.split_at(packet.len().min(1));
+        let reply = match cmd {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(args),
+            "p" => self.read_register(args),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "Z" => self.breakpoint(args, true),
+            "z" => self.breakpoint(args, false),
+            "s" => {
+                let reason = self.cpu.step_into();
+                self.stop_reply(reason)
+            }
+            "c" => self.resume(),
+            "H" => "OK".to_string(),
+            "q" => self.query(args),
+            "Q" if args == "StartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "v" if args == "Cont?" => "vCont;c;C;s;S".to_string(),
+            "v" if args.starts_with("Cont;") => self.resume_action(&args[5..]),
+            "D" | "k" => return None,
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn query(&mut self, args: &str) -> String {
+        if args.starts_with("Supported") {
+            format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;swbreak+",
+                PACKET_SIZE
+            )
+        } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            let (offset, length) = match parse_pair(range, ',') {
+                Some(pair) => pair,
+                None => return "E01".to_string(),
+            };
+            let xml = TARGET_XML.as_bytes();
+            let start = (offset as usize).min(xml.len());
+            let end = (start + length as usize).min(xml.len());
+            let marker = if end == xml.len() { 'l' } else { 'm' };
+            format!("{}{}", marker, String::from_utf8_lossy(&xml[start..end]))
+        } else {
+            match args {
+                "Attached" => "1".to_string(),
+                "C" => "QC1".to_string(),
+                "fThreadInfo" => "m1".to_string(),
+                "sThreadInfo" => "l".to_string(),
+                _ => String::new(),
+            }
+        }
+    }
+
+    fn registers(&self) -> [u8; 7] {
+        let pc = self.cpu.program_counter;
+        [
+            self.cpu.register_a,
+            self.cpu.register_x,
+            self.cpu.register_y,
+            self.cpu.status,
+            self.cpu.stack_ptr,
+            (pc & 0xff) as u8,
+            (pc >> 8) as u8,
+        ]
+    }
+
+    fn read_registers(&self) -> String {
+        to_hex(&self.registers())
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        match from_hex(args) {
+            Some(regs) if regs.len() == 7 => {
+                self.cpu.register_a = regs[0];
+                self.cpu.register_x = regs[1];
+                self.cpu.register_y = regs[2];
+                self.cpu.status = regs[3];
+                self.cpu.stack_ptr = regs[4];
+                self.cpu.program_counter = (regs[6] as u16) << 8 | regs[5] as u16;
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        let regs = self.registers();
+        match usize::from_str_radix(args, 16) {
+            Ok(n) if n < 5 => to_hex(&regs[n..n + 1]),
+            Ok(5) => to_hex(&regs[5..7]),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let (reg, value) = match args.split_once('=') {
+            Some(pair) => pair,
+            None => return "E01".to_string(),
+        };
+        let bytes = match (usize::from_str_radix(reg, 16), from_hex(value)) {
+            (Ok(reg), Some(bytes)) if !bytes.is_empty() => (reg, bytes),
+            _ => return "E01".to_string(),
+        };
+        match bytes {
+            (0, b) => self.cpu.register_a = b[0],
+            (1, b) => self.cpu.register_x = b[0],
+            (2, b) => self.cpu.register_y = b[0],
+            (3, b) => self.cpu.status = b[0],
+            (4, b) => self.cpu.stack_ptr = b[0],
+            (5, b) if b.len() == 2 => self.cpu.program_counter = (b[1] as u16) << 8 | b[0] as u16,
+            _ => return "E01".to_string(),
+        }
+        "OK".to_string()
+    }
+
+    /// Longer reads than fit in a packet are cut short, which clients
+    /// handle by asking for the rest.
+    fn read_memory(&self, args: &str) -> String {
+        match parse_pair(args, ',') {
+            Some((addr, len)) if addr <= 0xffff => {
+                let addr = addr as u16;
+                let bytes: Vec<u8> = (0..len.min(MAX_READ) as u16)
+                    .map(|i| self.cpu.mem_peek(addr.wrapping_add(i)))
+                    .collect();
+                to_hex(&bytes)
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let (range, data) = match args.split_once(':') {
+            Some(pair) => pair,
+            None => return "E01".to_string(),
+        };
+        match (parse_pair(range, ','), from_hex(data)) {
+            (Some((addr, len)), Some(bytes)) if addr <= 0xffff && bytes.len() == len as usize => {
+                for (i, byte) in bytes.iter().enumerate() {
+                    self.cpu
+                        .mem_write((addr as u16).wrapping_add(i as u16), *byte);
+                }
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// `Z type,addr,kind`: 0/1 are execution breakpoints, 2/3/4 are write,
+    /// read and access watchpoints over `kind` bytes.
+    fn breakpoint(&mut self, args: &str, insert: bool) -> String {
+        let parts: Vec<&str> = args.split(',').collect();
+        if parts.len() != 3 {
+            return "E01".to_string();
+        }
+        let (addr, len) = match (
+            u16::from_str_radix(parts[1], 16),
+            u16::from_str_radix(parts[2], 16),
+        ) {
+            (Ok(addr), Ok(len)) => (addr, len.max(1)),
+            _ => return "E01".to_string(),
+        };
+        let end = addr.saturating_add(len - 1);
+        let debugger = self.cpu.debugger_mut();
+        match (parts[0], insert) {
+            ("0", true) | ("1", true) => debugger.add_breakpoint(addr),
+            ("0", false) | ("1", false) => debugger.remove_breakpoint(addr),
+            ("2", true) => debugger.add_watchpoint(addr, end, false, true),
+            ("3", true) => debugger.add_watchpoint(addr, end, true, false),
+            ("4", true) => debugger.add_watchpoint(addr, end, true, true),
+            ("2", false) | ("3", false) | ("4", false) => debugger.remove_watchpoint(addr, end),
+            _ => return String::new(),
+        }
+        "OK".to_string()
+    }
+
+    /// Runs the first action of a `vCont`, such as `c`, `s:1` or `C05:p1.1`.
+    /// There is only one thread, so thread ids and signals are ignored.
+    fn resume_action(&mut self, actions: &str) -> String {
+        let action = actions.split(';').next().unwrap_or("");
+        match action.chars().next() {
+            Some('c') | Some('C') => self.resume(),
+            Some('s') | Some('S') => {
+                let reason = self.cpu.step_into();
+                self.stop_reply(reason)
+            }
+            _ => String::new(),
+        }
+    }
+
+    fn resume(&mut self) -> String {
+        let conn = &mut self.conn;
+        let mut count = 0u32;
+        let mut interrupted = false;
+        let reason = self.cpu.run_until(u32::MAX, |_, _| {
+            count += 1;
+            if count == POLL_INTERVAL {
+                count = 0;
+                interrupted = conn.interrupted().unwrap_or(true);
+            }
+            interrupted
+        });
+        if interrupted {
+            return format!("S{:02x}", SIGINT);
+        }
+        self.stop_reply(reason)
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::Watchpoint => match self.cpu.last_watch_hit() {
+                Some(hit) => {
+                    let kind = match hit.access {
+                        Access::Read => "rwatch",
+                        Access::Write => "watch",
+                    };
+                    format!("T{:02x}{}:{:04x};", SIGTRAP, kind, hit.addr)
+                }
+                None => format!("S{:02x}", SIGTRAP),
+            },
+            StopReason::Breakpoint => format!("T{:02x}swbreak:;", SIGTRAP),
+            // BRK ends the program, as it does for CPU::run
+            StopReason::Brk => "W00".to_string(),
+            StopReason::Jam => format!("S{:02x}", SIGILL),
+            StopReason::Step | StopReason::Limit => format!("S{:02x}", SIGTRAP),
+        }
+    }
+}
+
+/// Waits for one GDB client on `addr` (e.g. `127.0.0.1:2345`) and serves
+/// it until it detaches.
+pub fn serve(cpu: &mut CPU, addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    GdbStub::new(cpu, stream).run()
+}
+
+fn parse_pair(args: &str, sep: char) -> Option<(u32, u32)> {
+    let (a, b) = args.split_once(sep)?;
+    Some((
+        u32::from_str_radix(a, 16).ok()?,
+        u32::from_str_radix(b, 16).ok()?,
+    ))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,44 @@
+//! Standard NES controllers, read serially through $4016 and $4017.
+
+// bit order matches the order the buttons are shifted out
+pub const BUTTON_A: u8 = 0b0000_0001;
+pub const BUTTON_B: u8 = 0b0000_0010;
+pub const SELECT: u8 = 0b0000_0100;
+pub const START: u8 = 0b0000_1000;
+pub const UP: u8 = 0b0001_0000;
+pub const DOWN: u8 = 0b0010_0000;
+pub const LEFT: u8 = 0b0100_0000;
+pub const RIGHT: u8 = 0b1000_0000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Joypad {
+    pub strobe: bool,
+    pub button_index: u8,
+    pub buttons: u8,
+}
+
+impl Joypad {
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+        if self.strobe {
+            self.button_index = 0
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        let response = self.peek();
+        if !self.strobe && self.button_index <= 7 {
+            self.button_index += 1;
+        }
+        response
+    }
+
+    /// The bit the next read returns, without shifting.
+    pub fn peek(&self) -> u8 {
+        // after all eight buttons an official controller returns 1
+        if self.button_index > 7 {
+            return 1;
+        }
+        (self.buttons >> self.button_index) & 1
+    }
+}
@@ -0,0 +1,42 @@
+//! The emulator core: the 6502, the NES and easy6502 machines and the
+//! debugging tools built on them. Without the `std` feature it is
+//! `#![no_std]` and needs only `alloc`.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod asm;
+#[cfg(feature = "std")]
+pub mod capture;
+pub mod cartridge;
+pub mod cdl;
+pub mod cheats;
+pub mod cpu;
+pub mod debugger;
+pub mod events;
+pub mod expr;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod gdb;
+pub mod joypad;
+pub mod machine;
+pub mod movie;
+pub mod opcodes;
+pub mod ppu;
+pub mod ppu_view;
+pub mod profiler;
+pub mod ram_search;
+pub mod rewind;
+pub mod savestate;
+pub mod screenshot;
+pub mod symbols;
+#[cfg(feature = "std")]
+pub mod testrom;
+pub mod trace;
+
+// The parts of std's prelude the core uses, so it also builds as no_std.
+mod prelude {
+    pub use alloc::boxed::Box;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+    pub use alloc::{format, vec};
+}
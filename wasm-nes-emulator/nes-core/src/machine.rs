@@ -0,0 +1,140 @@
+//! The machines the CPU can sit in. Each one is a memory map over the
+//! CPU's 64K of memory: easy6502's fantasy console, which the tutorials
+//! are written for, the NES, and a bare 6502 for CPU test suites.
+
+use crate::cartridge::{PRG_RAM_SIZE, PRG_RAM_START};
+use crate::cpu::CPU;
+
+/// Where easy6502 puts a new random byte before every instruction.
+pub const RANDOM_PORT: u16 = 0x00fe;
+/// The ASCII code of the last key pressed.
+pub const KEY_PORT: u16 = 0x00ff;
+/// The 32x32 display, one byte per pixel.
+pub const DISPLAY: core::ops::RangeInclusive<u16> = 0x0200..=0x05ff;
+/// Where `load_pro` puts an easy6502 program.
+pub const PROGRAM_START: u16 = 0x0600;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Machine {
+    /// Flat 64K of RAM with the random byte, key and display ports.
+    Easy6502,
+    /// 2K of RAM mirrored to $1FFF, the PPU's registers at $2000-$3FFF,
+    /// the controllers at $4016/$4017 and the cartridge at $6000 up.
+    Nes,
+    /// Flat 64K of RAM and nothing else, as CPU test suites expect.
+    Bare,
+}
+
+/// A machine's memory map.
+pub(crate) trait Bus {
+    fn peek(cpu: &CPU, addr: u16) -> u8;
+    /// A CPU read, for ports that change when read.
+    fn read(cpu: &mut CPU, addr: u16) -> u8 {
+        Self::peek(cpu, addr)
+    }
+    fn write(cpu: &mut CPU, addr: u16, data: u8);
+    fn before_instruction(_cpu: &mut CPU) {}
+}
+
+pub(crate) struct Easy6502;
+
+impl Bus for Easy6502 {
+    fn peek(cpu: &CPU, addr: u16) -> u8 {
+        cpu.memory[addr as usize]
+    }
+
+    fn write(cpu: &mut CPU, addr: u16, data: u8) {
+        cpu.memory[addr as usize] = data;
+        if DISPLAY.contains(&addr) {
+            cpu.update = true;
+        }
+    }
+
+    fn before_instruction(cpu: &mut CPU) {
+        if let Some(state) = cpu.random.as_mut() {
+            *state ^= *state << 13;
+            *state ^= *state >> 17;
+            *state ^= *state << 5;
+            cpu.memory[RANDOM_PORT as usize] = *state as u8;
+        }
+    }
+}
+
+pub(crate) struct Bare;
+
+impl Bus for Bare {
+    fn peek(cpu: &CPU, addr: u16) -> u8 {
+        cpu.memory[addr as usize]
+    }
+
+    fn write(cpu: &mut CPU, addr: u16, data: u8) {
+        cpu.memory[addr as usize] = data;
+    }
+}
+
+pub(crate) struct Nes;
+
+impl Nes {
+    fn mirror(addr: u16) -> usize {
+        match addr {
+            0x0000..=0x1fff => (addr & 0x07ff) as usize,
+            _ => addr as usize,
+        }
+    }
+}
+
+impl Bus for Nes {
+    fn peek(cpu: &CPU, addr: u16) -> u8 {
+        match addr {
+            0x2000..=0x3fff => cpu.ppu.peek_register(addr),
+            0x4016 => cpu.joypads[0].peek(),
+            0x4017 => cpu.joypads[1].peek(),
+            _ => cpu.memory[Self::mirror(addr)],
+        }
+    }
+
+    fn read(cpu: &mut CPU, addr: u16) -> u8 {
+        match addr {
+            0x2000..=0x3fff => cpu.ppu.read_register(addr),
+            0x4016 => cpu.joypads[0].read(),
+            0x4017 => cpu.joypads[1].read(),
+            _ => Self::peek(cpu, addr),
+        }
+    }
+
+    fn write(cpu: &mut CPU, addr: u16, data: u8) {
+        match addr {
+            0x2000..=0x3fff => return cpu.ppu.write_register(addr, data),
+            0x4014 => return cpu.oam_dma(data),
+            //controller strobe
+            0x4016 => {
+                cpu.joypads[0].write(data);
+                cpu.joypads[1].write(data);
+                return;
+            }
+            _ => {}
+        }
+        //cartridge ROM is read only
+        if addr >= 0x8000 && cpu.cartridge.is_some() {
+            return;
+        }
+        cpu.memory[Self::mirror(addr)] = data;
+        if (PRG_RAM_START..PRG_RAM_START + PRG_RAM_SIZE as u16).contains(&addr) && cpu.has_battery()
+        {
+            cpu.save_ram_dirty = true;
+        }
+    }
+}
+
+impl CPU {
+    /// Puts a fresh random byte at $FE before every instruction on the
+    /// easy6502 machine. A seed of 0 turns it off.
+    pub fn set_random_seed(&mut self, seed: u32) {
+        self.random = if seed == 0 { None } else { Some(seed) };
+    }
+
+    /// Tells an easy6502 program a key was pressed.
+    pub fn key_press(&mut self, key: u8) {
+        self.memory[KEY_PORT as usize] = key;
+    }
+}
@@ -0,0 +1,410 @@
+//! Input movies: per-frame controller input recorded from power-on or a
+//! save state, in FCEUX's FM2 text format.
+//!
+//! Frame `i` of a movie holds the buttons held during the `i`th frame after
+//! the start and, optionally, a hash of internal RAM ($0000-$07FF) at the
+//! end of that frame. FM2 has no field for the hash, so it is written as
+//! extra `ramHash <frame> <hash>` header lines, which FCEUX ignores.
+//!
+//! Movies that start from a save state embed it, so only states saved by
+//! this emulator load; FCEUX's own are refused.
+use crate::cpu::CPU;
+use crate::prelude::*;
+use crate::savestate::read_chunks;
+
+const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Start {
+    PowerOn,
+    SaveState(Vec<u8>),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    /// FM2 command bits: 1 soft reset, 2 power cycle.
+    pub commands: u8,
+    pub pads: [u8; 2],
+    pub ram_hash: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub start: Start,
+    pub frames: Vec<Frame>,
+    pub rerecords: u32,
+    /// Other FM2 header lines, kept so exported movies round-trip.
+    pub header: Vec<(String, String)>,
+}
+
+impl Movie {
+    pub fn new(start: Start) -> Self {
+        Movie {
+            start,
+            frames: vec![],
+            rerecords: 0,
+            header: vec![],
+        }
+    }
+
+    pub fn parse_fm2(text: &str) -> Result<Movie, String> {
+        let mut movie = Movie::new(Start::PowerOn);
+        let mut hashes = vec![];
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            let error = |what: &str| format!("line {}: {}", number + 1, what);
+
+            if line.starts_with('|') {
+                movie
+                    .frames
+                    .push(fm2_frame(line).ok_or_else(|| error("bad input"))?);
+                continue;
+            }
+            let (key, value) = match line.split_once(' ') {
+                Some((key, value)) => (key, value.trim()),
+                None => (line, ""),
+            };
+            match key {
+                "" => {}
+                "version" => {
+                    if value != "3" {
+                        return Err(error("only FM2 version 3 is supported"));
+                    }
+                }
+                "rerecordCount" => {
+                    movie.rerecords = value.parse().map_err(|_| error("bad count"))?
+                }
+                "savestate" => {
+                    let data = value
+                        .strip_prefix("base64:")
+                        .and_then(base64_decode)
+                        .ok_or_else(|| error("bad savestate"))?;
+                    if read_chunks(&data).is_err() {
+                        return Err(error("unsupported FCEUX savestate"));
+                    }
+                    movie.start = Start::SaveState(data);
+                }
+                "ramHash" => {
+                    let (frame, hash) = value.split_once(' ').ok_or_else(|| error("bad hash"))?;
+                    let frame: usize = frame.parse().map_err(|_| error("bad hash frame"))?;
+                    let hash = u32::from_str_radix(hash, 16).map_err(|_| error("bad hash"))?;
+                    hashes.push((frame, hash));
+                }
+                _ => movie.header.push((key.to_string(), value.to_string())),
+            }
+        }
+
+        for (frame, hash) in hashes {
+            if let Some(frame) = movie.frames.get_mut(frame) {
+                frame.ram_hash = Some(hash);
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut out = String::new();
+        out.push_str("version 3\n");
+        out.push_str(&format!("rerecordCount {}\n", self.rerecords));
+        let defaults = [
+            ("emuVersion", "22020"),
+            ("palFlag", "0"),
+            ("romFilename", ""),
+            ("romChecksum", "base64:AAAAAAAAAAAAAAAAAAAAAA=="),
+            ("guid", "00000000-0000-0000-0000-000000000000"),
+            ("fourscore", "0"),
+            ("microphone", "0"),
+            ("port0", "1"),
+            ("port1", "1"),
+            ("port2", "0"),
+            ("FDS", "0"),
+            ("NewPPU", "0"),
+        ];
+        for (key, value) in defaults.iter() {
+            if !self.header.iter().any(|(k, _)| k == key) {
+                out.push_str(&format!("{} {}\n", key, value));
+            }
+        }
+        for (key, value) in &self.header {
+            out.push_str(&format!("{} {}\n", key, value));
+        }
+        if let Start::SaveState(state) = &self.start {
+            out.push_str(&format!("savestate base64:{}\n", base64_encode(state)));
+        }
+        for (i, frame) in self.frames.iter().enumerate() {
+            if let Some(hash) = frame.ram_hash {
+                out.push_str(&format!("ramHash {} {:08x}\n", i, hash));
+            }
+        }
+        for frame in &self.frames {
+            out.push_str(&format!(
+                "|{}|{}|{}||\n",
+                frame.commands,
+                fm2_pad(frame.pads[0]),
+                fm2_pad(frame.pads[1])
+            ));
+        }
+        out
+    }
+}
+
+fn fm2_pad(buttons: u8) -> String {
+    FM2_BUTTONS
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| {
+            if buttons & (0x80 >> i) != 0 {
+                c as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+// "|0|RLDUTSBA|........||"
+fn fm2_frame(line: &str) -> Option<Frame> {
+    let mut fields = line.split('|').skip(1);
+    let commands = fields.next()?.parse().ok()?;
+    let mut pads = [0; 2];
+    for pad in pads.iter_mut() {
+        let field = fields.next().unwrap_or("");
+        if !field.is_empty() && field.len() != 8 {
+            return None;
+        }
+        for (i, c) in field.chars().enumerate() {
+            if c != '.' && c != ' ' {
+                *pad |= 0x80 >> i;
+            }
+        }
+    }
+    Some(Frame {
+        commands,
+        pads,
+        ram_hash: None,
+    })
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    let mut n = 0u32;
+    let mut bits = 0;
+    for c in text.bytes().filter(|&c| c != b'=') {
+        let value = BASE64.iter().position(|&b| b == c)? as u32;
+        n = n << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// FNV-1a of internal RAM, the value compared to detect desyncs.
+pub fn ram_hash(cpu: &CPU) -> u32 {
+    cpu.memory[..0x800].iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+pub(crate) struct MovieState {
+    movie: Movie,
+    recording: bool,
+    hash_ram: bool,
+    start_frame: u64,
+    desync: Option<usize>,
+}
+
+impl CPU {
+    /// Power-on for movies: clears internal RAM and resets. Without a
+    /// cartridge the program lives in RAM, so only the reset happens.
+    fn movie_power_on(&mut self) {
+        if self.cartridge.is_some() {
+            self.memory[..0x800].fill(0);
+        }
+        self.joypads = Default::default();
+        self.reset();
+    }
+
+    // resets asked for by FM2 commands keep the cycle count, so the frame
+    // the movie is on does not jump
+    fn movie_reset(&mut self, power: bool) {
+        if power && self.cartridge.is_some() {
+            self.memory[..0x800].fill(0);
+        }
+        self.soft_reset();
+    }
+
+    fn movie_start(&mut self, start: &Start) -> Result<(), String> {
+        match start {
+            Start::PowerOn => self.movie_power_on(),
+            Start::SaveState(state) => self.load_state(state)?,
+        }
+        Ok(())
+    }
+
+    /// Starts recording the buttons set with `set_buttons` each frame.
+    /// Recording after loading an earlier state or rewinding re-records
+    /// from that frame on.
+    pub fn record_movie(&mut self, start: Start, hash_ram: bool) -> Result<(), String> {
+        self.movie_start(&start)?;
+        self.movie = Some(MovieState {
+            movie: Movie::new(start),
+            recording: true,
+            hash_ram,
+            start_frame: self.frame(),
+            desync: None,
+        });
+        Ok(())
+    }
+
+    /// Restarts the machine the way the movie says and plays its input
+    /// back, frame by frame.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), String> {
+        self.movie_start(&movie.start)?;
+        let mut state = MovieState {
+            movie,
+            recording: false,
+            hash_ram: false,
+            start_frame: self.frame(),
+            desync: None,
+        };
+        if let Some(first) = state.movie.frames.first().copied() {
+            self.movie_input(first);
+        }
+        state.hash_ram = state.movie.frames.iter().any(|f| f.ram_hash.is_some());
+        self.movie = Some(state);
+        Ok(())
+    }
+
+    pub fn movie(&self) -> Option<&Movie> {
+        self.movie.as_ref().map(|m| &m.movie)
+    }
+
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(|m| m.movie)
+    }
+
+    pub(crate) fn movie_frame_done(&mut self) {
+        let hash = match &self.movie {
+            Some(m) if m.hash_ram => Some(ram_hash(self)),
+            _ => None,
+        };
+        let frame = self.frame();
+        let pads = [self.joypads[0].buttons, self.joypads[1].buttons];
+        let m = match self.movie.as_mut() {
+            Some(m) => m,
+            None => return,
+        };
+        // the frame that just ended, counted from the movie start
+        let index = match (frame - 1).checked_sub(m.start_frame) {
+            Some(index) => index as usize,
+            None => return,
+        };
+
+        if m.recording {
+            if index < m.movie.frames.len() {
+                m.movie.frames.truncate(index);
+                m.movie.rerecords += 1;
+            }
+            if index == m.movie.frames.len() {
+                m.movie.frames.push(Frame {
+                    commands: 0,
+                    pads,
+                    ram_hash: hash,
+                });
+            }
+            return;
+        }
+
+        if let Some(expected) = m.movie.frames.get(index).and_then(|f| f.ram_hash) {
+            if m.desync.is_none() && hash != Some(expected) {
+                m.desync = Some(index);
+            }
+        }
+        if let Some(next) = m.movie.frames.get(index + 1).copied() {
+            self.movie_input(next);
+        }
+    }
+
+    // the buttons and commands of a frame, applied as it begins
+    fn movie_input(&mut self, frame: Frame) {
+        self.joypads[0].buttons = frame.pads[0];
+        self.joypads[1].buttons = frame.pads[1];
+        if frame.commands & 0b11 != 0 {
+            self.movie_reset(frame.commands & 0b10 != 0);
+        }
+    }
+
+    /// Sets the buttons held on controller `pad` (0 or 1), one bit per
+    /// button from A (bit 0) to Right (bit 7).
+    pub fn set_buttons(&mut self, pad: usize, buttons: u8) {
+        if let Some(joypad) = self.joypads.get_mut(pad) {
+            joypad.buttons = buttons;
+        }
+    }
+
+    /// Records from power-on, or from the current state when
+    /// `from_save_state` is set. With `hash_ram` each frame stores a RAM
+    /// hash so playback can spot desyncs.
+    pub fn start_recording(&mut self, from_save_state: bool, hash_ram: bool) {
+        let start = if from_save_state {
+            Start::SaveState(self.save_state())
+        } else {
+            Start::PowerOn
+        };
+        // starting from our own state cannot fail
+        let _ = self.record_movie(start, hash_ram);
+    }
+
+    pub fn play_fm2(&mut self, text: &str) -> Result<(), String> {
+        self.play_movie(Movie::parse_fm2(text)?)
+    }
+
+    /// The movie being recorded or played, as FM2 text.
+    pub fn export_fm2(&self) -> Option<String> {
+        self.movie().map(|m| m.to_fm2())
+    }
+
+    pub fn close_movie(&mut self) {
+        self.movie = None;
+    }
+
+    pub fn movie_recording(&self) -> bool {
+        self.movie.as_ref().is_some_and(|m| m.recording)
+    }
+
+    /// True once playback has applied the movie's last frame of input.
+    pub fn movie_finished(&self) -> bool {
+        match &self.movie {
+            Some(m) if !m.recording => self.frame() >= m.start_frame + m.movie.frames.len() as u64,
+            _ => false,
+        }
+    }
+
+    /// The first movie frame whose RAM hash did not match on playback.
+    pub fn movie_desync(&self) -> Option<u32> {
+        self.movie.as_ref()?.desync.map(|frame| frame as u32)
+    }
+}
@@ -1,5 +1,4 @@
 use crate::cpu::AddressingMode;
-use std::collections::HashMap;
 
 pub struct OpCode {
     pub code: u8,
@@ -10,14 +9,14 @@ pub struct OpCode {
 }
 
 impl OpCode {
-    pub fn new(code: u8, name: &'static str, bytes: u8, cycles: u8, address_mode: AddressingMode) -> Self {
+    #[allow(clippy::redundant_field_names)]
+    pub const fn new(code: u8, name: &'static str, bytes: u8, cycles: u8, address_mode: AddressingMode) -> Self {
         OpCode {code: code, name: name, bytes: bytes, cycles: cycles, address_mode: address_mode}
     }
 }
 
 
-lazy_static! {
-pub static ref CPU_OPS_CODES: Vec<OpCode> = vec![
+pub static CPU_OPS_CODES: &[OpCode] = &[
 OpCode::new(0x00, "BRK", 1, 7, AddressingMode::NoneAddressing),
 
 OpCode::new(0x85, "STA", 2, 3, AddressingMode::ZeroPage),
@@ -33,7 +32,7 @@ OpCode::new(0x96, "STX", 2, 4, AddressingMode::ZeroPage_Y),
 OpCode::new(0x8E, "STX", 3, 4, AddressingMode::Absolute),
 
 OpCode::new(0x84, "STY", 2, 3, AddressingMode::ZeroPage),
-OpCode::new(0x94, "STY", 2, 4, AddressingMode::ZeroPage_Y),
+OpCode::new(0x94, "STY", 2, 4, AddressingMode::ZeroPage_X),
 OpCode::new(0x8C, "STY", 3, 4, AddressingMode::Absolute),
 
 OpCode::new(0xA9, "LDA", 2, 2, AddressingMode::Immediate),
@@ -82,7 +81,8 @@ OpCode::new(0x45, "EOR", 2, 3, AddressingMode::ZeroPage),
 OpCode::new(0x55, "EOR", 2, 4, AddressingMode::ZeroPage_X),
 OpCode::new(0x4D, "EOR", 3, 4, AddressingMode::Absolute),
 OpCode::new(0x5D, "EOR", 3, 4, AddressingMode::Absolute_X),
-OpCode::new(0x59, "EOR", 3, 4, AddressingMode::Indirect_X),
+OpCode::new(0x59, "EOR", 3, 4, AddressingMode::Absolute_Y),
+OpCode::new(0x41, "EOR", 2, 6, AddressingMode::Indirect_X),
 OpCode::new(0x51, "EOR", 2, 5, AddressingMode::Indirect_Y),
 
 OpCode::new(0x09, "ORA", 2, 2, AddressingMode::Immediate),
@@ -146,13 +146,13 @@ OpCode::new(0xD9, "CMP", 3, 4, AddressingMode::Absolute_Y),
 OpCode::new(0xC1, "CMP", 2, 6, AddressingMode::Indirect_X),
 OpCode::new(0xD1, "CMP", 2, 5, AddressingMode::Indirect_Y),
 
-OpCode::new(0xE0, "CMX", 2, 2, AddressingMode::Immediate),
-OpCode::new(0xE4, "CMX", 2, 3, AddressingMode::ZeroPage),
-OpCode::new(0xEC, "CMX", 3, 4, AddressingMode::Absolute),
+OpCode::new(0xE0, "CPX", 2, 2, AddressingMode::Immediate),
+OpCode::new(0xE4, "CPX", 2, 3, AddressingMode::ZeroPage),
+OpCode::new(0xEC, "CPX", 3, 4, AddressingMode::Absolute),
 
-OpCode::new(0xC0, "CMY", 2, 2, AddressingMode::Immediate),
-OpCode::new(0xC4, "CMY", 2, 3, AddressingMode::ZeroPage),
-OpCode::new(0xCC, "CMY", 3, 4, AddressingMode::Absolute),
+OpCode::new(0xC0, "CPY", 2, 2, AddressingMode::Immediate),
+OpCode::new(0xC4, "CPY", 2, 3, AddressingMode::ZeroPage),
+OpCode::new(0xCC, "CPY", 3, 4, AddressingMode::Absolute),
 
 OpCode::new(0xC6, "DEC", 2, 5, AddressingMode::ZeroPage),
 OpCode::new(0xD6, "DEC", 2, 6, AddressingMode::ZeroPage_X),
@@ -200,11 +200,17 @@ OpCode::new(0x5E, "LSR", 3, 7, AddressingMode::Absolute_X),
 OpCode::new(0x40, "RTI", 1, 6, AddressingMode::NoneAddressing),
 ];
 
-pub static ref OPSCODES_MAP: HashMap<u8, &'static OpCode> = {
-    let mut map = HashMap::new();
-    for op in &*CPU_OPS_CODES {
-        map.insert(op.code, op);
+// indexed by opcode
+pub static OPSCODES_MAP: [Option<&OpCode>; 256] = {
+    let mut map = [None; 256];
+    let mut i = 0;
+    while i < CPU_OPS_CODES.len() {
+        map[CPU_OPS_CODES[i].code as usize] = Some(&CPU_OPS_CODES[i]);
+        i += 1;
     }
     map
 };
+
+pub fn opcode(code: u8) -> Option<&'static OpCode> {
+    OPSCODES_MAP[code as usize]
 }
\ No newline at end of file
@@ -0,0 +1,341 @@
+//! The picture processor's memory and registers: pattern tables,
+//! nametables, palette RAM and OAM behind $2000-$2007 and OAM DMA at
+//! $4014. The vblank flag and NMI follow the scanline derived from the
+//! cycle count. The picture is drawn a whole frame at a time from the
+//! current state, not dot by dot.
+use crate::cartridge::Mirroring;
+use crate::cpu::CPU;
+use crate::ppu_view::{NAMETABLES_HEIGHT, NAMETABLES_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::prelude::*;
+
+pub const CHR_SIZE: usize = 0x2000;
+const NAMETABLE_SIZE: u16 = 0x400;
+pub const VBLANK_SCANLINE: u64 = 241;
+pub const PRE_RENDER_SCANLINE: u64 = 261;
+
+// PPUCTRL
+const CTRL_NMI: u8 = 0b1000_0000;
+const CTRL_INCREMENT_32: u8 = 0b0000_0100;
+pub(crate) const CTRL_SPRITE_TABLE: u8 = 0b0000_1000;
+pub(crate) const CTRL_BACKGROUND_TABLE: u8 = 0b0001_0000;
+pub(crate) const CTRL_TALL_SPRITES: u8 = 0b0010_0000;
+// PPUMASK
+const MASK_GREYSCALE: u8 = 0b0000_0001;
+const MASK_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const MASK_SPRITES_LEFT: u8 = 0b0000_0100;
+const MASK_BACKGROUND: u8 = 0b0000_1000;
+const MASK_SPRITES: u8 = 0b0001_0000;
+// PPUSTATUS
+const STATUS_VBLANK: u8 = 0b1000_0000;
+
+#[derive(Clone)]
+pub struct Ppu {
+    /// Pattern tables: the cartridge's CHR ROM, or 8K of CHR RAM.
+    pub chr: Vec<u8>,
+    pub(crate) chr_ram: bool,
+    pub mirroring: Mirroring,
+    /// Room for four nametables; only four-screen carts use all of it.
+    pub vram: [u8; 0x1000],
+    pub palette: [u8; 32],
+    pub oam: [u8; 256],
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub oam_addr: u8,
+    /// The current and temporary VRAM addresses, fine X scroll and the
+    /// write toggle shared by $2005 and $2006.
+    pub v: u16,
+    pub t: u16,
+    pub fine_x: u8,
+    pub w: bool,
+    pub(crate) read_buffer: u8,
+    // an NMI the CPU has yet to take
+    pub(crate) nmi: bool,
+}
+
+impl Ppu {
+    /// An empty `chr` gives the board CHR RAM.
+    pub fn new(chr: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_ram = chr.is_empty();
+        Ppu {
+            chr: if chr_ram { vec![0; CHR_SIZE] } else { chr },
+            chr_ram,
+            mirroring,
+            vram: [0; 0x1000],
+            palette: [0; 32],
+            oam: [0; 256],
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam_addr: 0,
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            w: false,
+            read_buffer: 0,
+            nmi: false,
+        }
+    }
+
+    fn nametable_index(&self, addr: u16) -> usize {
+        let offset = (addr - 0x2000) & 0x0fff;
+        let table = offset / NAMETABLE_SIZE;
+        let table = match self.mirroring {
+            Mirroring::Vertical => table & 1,
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::FourScreen => table,
+        };
+        (table * NAMETABLE_SIZE + offset % NAMETABLE_SIZE) as usize
+    }
+
+    fn palette_index(addr: u16) -> usize {
+        let index = (addr & 0x1f) as usize;
+        // the sprite palettes' first entries mirror the background's
+        if index >= 16 && index & 3 == 0 {
+            index - 16
+        } else {
+            index
+        }
+    }
+
+    /// Reads the PPU's address space, $0000-$3FFF.
+    pub fn read(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3fff;
+        match addr {
+            0x0000..=0x1fff => self.chr[addr as usize % self.chr.len()],
+            0x2000..=0x3eff => self.vram[self.nametable_index(addr)],
+            _ => self.palette[Self::palette_index(addr)],
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        let addr = addr & 0x3fff;
+        match addr {
+            0x0000..=0x1fff => {
+                if self.chr_ram {
+                    let len = self.chr.len();
+                    self.chr[addr as usize % len] = data;
+                }
+            }
+            0x2000..=0x3eff => {
+                let index = self.nametable_index(addr);
+                self.vram[index] = data;
+            }
+            _ => self.palette[Self::palette_index(addr)] = data,
+        }
+    }
+
+    fn increment(&self) -> u16 {
+        if self.ctrl & CTRL_INCREMENT_32 != 0 {
+            32
+        } else {
+            1
+        }
+    }
+
+    /// What a read of register `addr` would return, without its side
+    /// effects. Write-only registers read as 0.
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        match addr & 7 {
+            2 => self.status,
+            4 => self.oam[self.oam_addr as usize],
+            7 if self.v & 0x3fff >= 0x3f00 => self.read(self.v),
+            7 => self.read_buffer,
+            _ => 0,
+        }
+    }
+
+    pub fn read_register(&mut self, addr: u16) -> u8 {
+        let data = self.peek_register(addr);
+        match addr & 7 {
+            2 => {
+                self.status &= !STATUS_VBLANK;
+                self.w = false;
+            }
+            7 => {
+                // palette reads are immediate but still refill the buffer
+                // from the nametable underneath
+                let vram_addr = self.v & 0x3fff;
+                self.read_buffer = if vram_addr >= 0x3f00 {
+                    self.read(vram_addr - 0x1000)
+                } else {
+                    self.read(vram_addr)
+                };
+                self.v = self.v.wrapping_add(self.increment()) & 0x7fff;
+            }
+            _ => {}
+        }
+        data
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr & 7 {
+            0 => {
+                // enabling NMI during vblank raises one straight away
+                if self.ctrl & CTRL_NMI == 0
+                    && data & CTRL_NMI != 0
+                    && self.status & STATUS_VBLANK != 0
+                {
+                    self.nmi = true;
+                }
+                self.ctrl = data;
+                self.t = (self.t & !0x0c00) | ((data as u16 & 3) << 10);
+            }
+            1 => self.mask = data,
+            3 => self.oam_addr = data,
+            4 => {
+                self.oam[self.oam_addr as usize] = data;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            5 => {
+                if !self.w {
+                    self.t = (self.t & !0x001f) | (data as u16 >> 3);
+                    self.fine_x = data & 7;
+                } else {
+                    self.t = (self.t & !0x73e0)
+                        | ((data as u16 & 7) << 12)
+                        | ((data as u16 & 0xf8) << 2);
+                }
+                self.w = !self.w;
+            }
+            6 => {
+                if !self.w {
+                    self.t = (self.t & 0x00ff) | ((data as u16 & 0x3f) << 8);
+                } else {
+                    self.t = (self.t & 0xff00) | data as u16;
+                    self.v = self.t;
+                }
+                self.w = !self.w;
+            }
+            7 => {
+                self.write(self.v, data);
+                self.v = self.v.wrapping_add(self.increment()) & 0x7fff;
+            }
+            _ => {}
+        }
+    }
+
+    /// The picture as 256x240 system colours, 0 to 63, row by row. The
+    /// frame is drawn from the state now, so anything changed mid-frame,
+    /// such as a split scroll, shows as it ended up.
+    pub fn frame(&self) -> Vec<u8> {
+        let mut pixels = vec![self.palette_entry(0, 0); SCREEN_WIDTH * SCREEN_HEIGHT];
+        // opaque background pixels hide sprites behind the background
+        let mut opaque = vec![false; pixels.len()];
+
+        if self.mask & MASK_BACKGROUND != 0 {
+            let table = self.background_table();
+            let (sx, sy) = self.scroll();
+            for y in 0..SCREEN_HEIGHT {
+                for x in 0..SCREEN_WIDTH {
+                    if x < 8 && self.mask & MASK_BACKGROUND_LEFT == 0 {
+                        continue;
+                    }
+                    let nx = (sx + x) % NAMETABLES_WIDTH;
+                    let ny = (sy + y) % NAMETABLES_HEIGHT;
+                    let (palette, pixel) = self.background_pixel(table, nx, ny);
+                    if pixel != 0 {
+                        let i = y * SCREEN_WIDTH + x;
+                        pixels[i] = self.palette_entry(palette, pixel);
+                        opaque[i] = true;
+                    }
+                }
+            }
+        }
+
+        if self.mask & MASK_SPRITES != 0 {
+            let height = if self.tall_sprites() { 16 } else { 8 };
+            let mut on_line = [0u8; SCREEN_HEIGHT];
+            let mut covered = vec![false; pixels.len()];
+            // the lowest OAM index with an opaque pixel owns it, even when
+            // that sprite is behind the background and a later one is not
+            for sprite in self.sprites() {
+                for row in 0..height {
+                    let y = sprite.y as usize + 1 + row;
+                    // only the first eight sprites on a line are drawn
+                    if y >= SCREEN_HEIGHT || on_line[y] == 8 {
+                        continue;
+                    }
+                    on_line[y] += 1;
+                    let row = if sprite.flip_vertical {
+                        height - 1 - row
+                    } else {
+                        row
+                    };
+                    let tile = if height == 16 {
+                        sprite.tile & 0xfe | (row / 8) as u8
+                    } else {
+                        sprite.tile
+                    };
+                    for col in 0..8 {
+                        let x = sprite.x as usize + col;
+                        if x >= SCREEN_WIDTH || x < 8 && self.mask & MASK_SPRITES_LEFT == 0 {
+                            continue;
+                        }
+                        let col = if sprite.flip_horizontal { 7 - col } else { col };
+                        let pixel = self.tile_pixel(sprite.pattern_table, tile, col, row % 8);
+                        let i = y * SCREEN_WIDTH + x;
+                        if pixel == 0 || covered[i] {
+                            continue;
+                        }
+                        covered[i] = true;
+                        if !(sprite.behind_background && opaque[i]) {
+                            pixels[i] = self.palette_entry(4 + sprite.palette, pixel);
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.mask & MASK_GREYSCALE != 0 {
+            for pixel in &mut pixels {
+                *pixel &= 0x30;
+            }
+        }
+        pixels
+    }
+
+    /// Called when a scanline begins.
+    fn start_scanline(&mut self, scanline: u64) {
+        match scanline {
+            VBLANK_SCANLINE => {
+                self.status |= STATUS_VBLANK;
+                if self.ctrl & CTRL_NMI != 0 {
+                    self.nmi = true;
+                }
+            }
+            // vblank, sprite 0 hit and sprite overflow
+            PRE_RENDER_SCANLINE => self.status &= !0b1110_0000,
+            _ => {}
+        }
+    }
+}
+
+impl CPU {
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    /// Copies a page of CPU memory to OAM, stalling the CPU.
+    pub(crate) fn oam_dma(&mut self, page: u8) {
+        let start = (page as u16) << 8;
+        for i in 0..256 {
+            let data = self.mem_peek(start + i);
+            self.ppu.write_register(0x2004, data);
+        }
+        self.cycles += 513 + self.cycles % 2;
+    }
+
+    /// Runs the scanline events between `dots_before` and now.
+    pub(crate) fn ppu_catch_up(&mut self, dots_before: u64) {
+        let first = dots_before / 341 + 1;
+        let last = self.cycles * 3 / 341;
+        for line in first..=last {
+            let scanline = line % 262;
+            self.ppu.start_scanline(scanline);
+            if self.ppu_viewer.is_some() {
+                self.ppu_viewer_scanline(scanline);
+            }
+        }
+    }
+}
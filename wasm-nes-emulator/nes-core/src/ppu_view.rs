@@ -0,0 +1,319 @@
+//! Debugging views of the PPU as RGBA images: the four nametables with
+//! the scroll window outlined, both pattern tables, the palette RAM and
+//! the decoded OAM. The views can show the PPU as it was when a chosen
+//! scanline began, so mid-frame changes are visible.
+
+use crate::cpu::CPU;
+use crate::ppu::{Ppu, CTRL_BACKGROUND_TABLE, CTRL_SPRITE_TABLE, CTRL_TALL_SPRITES};
+use crate::prelude::*;
+
+/// The 2C02's 64 colours.
+pub const SYSTEM_PALETTE: [[u8; 3]; 64] = [
+    [0x80, 0x80, 0x80],
+    [0x00, 0x3d, 0xa6],
+    [0x00, 0x12, 0xb0],
+    [0x44, 0x00, 0x96],
+    [0xa1, 0x00, 0x5e],
+    [0xc7, 0x00, 0x28],
+    [0xba, 0x06, 0x00],
+    [0x8c, 0x17, 0x00],
+    [0x5c, 0x2f, 0x00],
+    [0x10, 0x45, 0x00],
+    [0x05, 0x4a, 0x00],
+    [0x00, 0x47, 0x2e],
+    [0x00, 0x41, 0x66],
+    [0x00, 0x00, 0x00],
+    [0x05, 0x05, 0x05],
+    [0x05, 0x05, 0x05],
+    [0xc7, 0xc7, 0xc7],
+    [0x00, 0x77, 0xff],
+    [0x21, 0x55, 0xff],
+    [0x82, 0x37, 0xfa],
+    [0xeb, 0x2f, 0xb5],
+    [0xff, 0x29, 0x50],
+    [0xff, 0x22, 0x00],
+    [0xd6, 0x32, 0x00],
+    [0xc4, 0x62, 0x00],
+    [0x35, 0x80, 0x00],
+    [0x05, 0x8f, 0x00],
+    [0x00, 0x8a, 0x55],
+    [0x00, 0x99, 0xcc],
+    [0x21, 0x21, 0x21],
+    [0x09, 0x09, 0x09],
+    [0x09, 0x09, 0x09],
+    [0xff, 0xff, 0xff],
+    [0x0f, 0xd7, 0xff],
+    [0x69, 0xa2, 0xff],
+    [0xd4, 0x80, 0xff],
+    [0xff, 0x45, 0xf3],
+    [0xff, 0x61, 0x8b],
+    [0xff, 0x88, 0x33],
+    [0xff, 0x9c, 0x12],
+    [0xfa, 0xbc, 0x20],
+    [0x9f, 0xe3, 0x0e],
+    [0x2b, 0xf0, 0x35],
+    [0x0c, 0xf0, 0xa4],
+    [0x05, 0xfb, 0xff],
+    [0x5e, 0x5e, 0x5e],
+    [0x0d, 0x0d, 0x0d],
+    [0x0d, 0x0d, 0x0d],
+    [0xff, 0xff, 0xff],
+    [0xa6, 0xfc, 0xff],
+    [0xb3, 0xec, 0xff],
+    [0xda, 0xab, 0xeb],
+    [0xff, 0xa8, 0xf9],
+    [0xff, 0xab, 0xb3],
+    [0xff, 0xd2, 0xb0],
+    [0xff, 0xef, 0xa6],
+    [0xff, 0xf7, 0x9c],
+    [0xd7, 0xe8, 0x95],
+    [0xa6, 0xed, 0xaf],
+    [0xa2, 0xf2, 0xda],
+    [0x99, 0xff, 0xfc],
+    [0xdd, 0xdd, 0xdd],
+    [0x11, 0x11, 0x11],
+    [0x11, 0x11, 0x11],
+];
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+/// The nametables sit two by two: $2000 and $2400 on top.
+pub const NAMETABLES_WIDTH: usize = SCREEN_WIDTH * 2;
+pub const NAMETABLES_HEIGHT: usize = SCREEN_HEIGHT * 2;
+/// The pattern tables sit side by side, 16x16 tiles each.
+pub const PATTERN_TABLES_WIDTH: usize = 256;
+pub const PATTERN_TABLES_HEIGHT: usize = 128;
+
+/// Where the views come from: the PPU when `scanline` last began.
+pub struct PpuViewer {
+    scanline: u64,
+    snapshot: Option<Box<Ppu>>,
+}
+
+/// One OAM entry, decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sprite {
+    pub index: u8,
+    pub x: u8,
+    /// The scanline above the sprite's top row, as stored in OAM.
+    pub y: u8,
+    pub tile: u8,
+    /// Which sprite palette, 0 to 3.
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    /// The pattern table of the top tile; 8x16 sprites pick it with bit 0
+    /// of `tile`.
+    pub pattern_table: u16,
+}
+
+impl Ppu {
+    /// The 2-bit colour of pixel (`x`, `y`) in a tile.
+    pub(crate) fn tile_pixel(&self, table: u16, tile: u8, x: usize, y: usize) -> u8 {
+        let addr = table + tile as u16 * 16 + y as u16;
+        let lo = self.read(addr) >> (7 - x) & 1;
+        let hi = self.read(addr + 8) >> (7 - x) & 1;
+        hi << 1 | lo
+    }
+
+    /// The system colour of `pixel` in palette `palette`; 0 to 3 are the
+    /// background palettes and 4 to 7 the sprites'.
+    pub(crate) fn palette_entry(&self, palette: u8, pixel: u8) -> u8 {
+        let entry = if pixel == 0 {
+            self.palette[0]
+        } else {
+            self.palette[palette as usize * 4 + pixel as usize]
+        };
+        entry & 0x3f
+    }
+
+    fn color(&self, palette: u8, pixel: u8) -> [u8; 3] {
+        SYSTEM_PALETTE[self.palette_entry(palette, pixel) as usize]
+    }
+
+    /// The palette and 2-bit colour of pixel (`x`, `y`) of the four
+    /// nametables laid out as in `nametables_rgba`.
+    pub(crate) fn background_pixel(&self, table: u16, x: usize, y: usize) -> (u8, u8) {
+        let base = 0x2000 + (y / SCREEN_HEIGHT * 2 + x / SCREEN_WIDTH) as u16 * 0x400;
+        let (col, row) = (x % SCREEN_WIDTH / 8, y % SCREEN_HEIGHT / 8);
+        let tile = self.read(base + (row * 32 + col) as u16);
+        let attribute = self.read(base + 0x3c0 + (row / 4 * 8 + col / 4) as u16);
+        let shift = (row % 4 / 2) * 4 + (col % 4 / 2) * 2;
+        let palette = attribute >> shift & 3;
+        (palette, self.tile_pixel(table, tile, x % 8, y % 8))
+    }
+
+    pub(crate) fn background_table(&self) -> u16 {
+        if self.ctrl & CTRL_BACKGROUND_TABLE != 0 {
+            0x1000
+        } else {
+            0
+        }
+    }
+
+    pub(crate) fn tall_sprites(&self) -> bool {
+        self.ctrl & CTRL_TALL_SPRITES != 0
+    }
+
+    /// The scroll position the next frame starts from, in nametable
+    /// pixels.
+    pub fn scroll(&self) -> (usize, usize) {
+        let t = self.t as usize;
+        let x = ((t & 0x1f) << 3 | self.fine_x as usize) + (t >> 10 & 1) * SCREEN_WIDTH;
+        let y = (t >> 5 & 0x1f) << 3 | (t >> 12 & 7);
+        (x, y % SCREEN_HEIGHT + (t >> 11 & 1) * SCREEN_HEIGHT)
+    }
+
+    pub fn nametables_rgba(&self) -> Vec<u8> {
+        let mut rgba = vec![0; NAMETABLES_WIDTH * NAMETABLES_HEIGHT * 4];
+        let table = self.background_table();
+        for y in 0..NAMETABLES_HEIGHT {
+            for x in 0..NAMETABLES_WIDTH {
+                let (palette, pixel) = self.background_pixel(table, x, y);
+                let color = self.color(palette, pixel);
+                put(&mut rgba, NAMETABLES_WIDTH, x, y, color);
+            }
+        }
+
+        // outline the screen, wrapping around the edges, by inverting it
+        let (sx, sy) = self.scroll();
+        let mut invert = |x: usize, y: usize| {
+            let i = ((y % NAMETABLES_HEIGHT) * NAMETABLES_WIDTH + x % NAMETABLES_WIDTH) * 4;
+            for c in &mut rgba[i..i + 3] {
+                *c = !*c;
+            }
+        };
+        for dx in 0..SCREEN_WIDTH {
+            invert(sx + dx, sy);
+            invert(sx + dx, sy + SCREEN_HEIGHT - 1);
+        }
+        for dy in 1..SCREEN_HEIGHT - 1 {
+            invert(sx, sy + dy);
+            invert(sx + SCREEN_WIDTH - 1, sy + dy);
+        }
+        rgba
+    }
+
+    pub fn pattern_tables_rgba(&self, palette: u8) -> Vec<u8> {
+        let mut rgba = vec![0; PATTERN_TABLES_WIDTH * PATTERN_TABLES_HEIGHT * 4];
+        for y in 0..PATTERN_TABLES_HEIGHT {
+            for x in 0..PATTERN_TABLES_WIDTH {
+                let table = (x / 128) as u16 * 0x1000;
+                let tile = (y / 8 * 16 + x % 128 / 8) as u8;
+                let color = self.color(palette, self.tile_pixel(table, tile, x % 8, y % 8));
+                put(&mut rgba, PATTERN_TABLES_WIDTH, x, y, color);
+            }
+        }
+        rgba
+    }
+
+    /// The 32 palette entries as a 16x2 image, backgrounds on top.
+    pub fn palette_rgba(&self) -> Vec<u8> {
+        (0..32)
+            .flat_map(|i| {
+                let [r, g, b] = SYSTEM_PALETTE[(self.read(0x3f00 + i) & 0x3f) as usize];
+                [r, g, b, 0xff]
+            })
+            .collect()
+    }
+
+    pub fn sprites(&self) -> Vec<Sprite> {
+        self.oam
+            .chunks(4)
+            .enumerate()
+            .map(|(index, entry)| {
+                let attributes = entry[2];
+                let pattern_table = if self.tall_sprites() {
+                    (entry[1] as u16 & 1) * 0x1000
+                } else if self.ctrl & CTRL_SPRITE_TABLE != 0 {
+                    0x1000
+                } else {
+                    0
+                };
+                Sprite {
+                    index: index as u8,
+                    x: entry[3],
+                    y: entry[0],
+                    tile: entry[1],
+                    palette: attributes & 3,
+                    behind_background: attributes & 0x20 != 0,
+                    flip_horizontal: attributes & 0x40 != 0,
+                    flip_vertical: attributes & 0x80 != 0,
+                    pattern_table,
+                }
+            })
+            .collect()
+    }
+}
+
+fn put(rgba: &mut [u8], width: usize, x: usize, y: usize, [r, g, b]: [u8; 3]) {
+    let i = (y * width + x) * 4;
+    rgba[i..i + 4].copy_from_slice(&[r, g, b, 0xff]);
+}
+
+impl CPU {
+    /// The PPU the views show: the snapshot from the chosen scanline, or
+    /// the live PPU until that scanline comes round.
+    pub fn viewed_ppu(&self) -> &Ppu {
+        self.ppu_viewer
+            .as_ref()
+            .and_then(|viewer| viewer.snapshot.as_deref())
+            .unwrap_or(&self.ppu)
+    }
+
+    pub(crate) fn ppu_viewer_scanline(&mut self, scanline: u64) {
+        if let Some(viewer) = self.ppu_viewer.as_mut() {
+            if viewer.scanline == scanline {
+                viewer.snapshot = Some(Box::new(self.ppu.clone()));
+            }
+        }
+    }
+
+    /// Makes the views show the PPU as it was when `scanline` began,
+    /// from the next time it does. 241 is the start of vblank.
+    pub fn set_ppu_view_scanline(&mut self, scanline: u16) -> Result<(), String> {
+        if scanline > 261 {
+            return Err(format!("scanline must be 0 to 261, not {}", scanline));
+        }
+        self.ppu_viewer = Some(PpuViewer {
+            scanline: scanline as u64,
+            snapshot: None,
+        });
+        Ok(())
+    }
+
+    /// Makes the views show the PPU as it is now.
+    pub fn clear_ppu_view_scanline(&mut self) {
+        self.ppu_viewer = None;
+    }
+
+    /// The four nametables as a 512x480 RGBA image, with the scroll
+    /// window outlined.
+    pub fn nametables_rgba(&self) -> Vec<u8> {
+        self.viewed_ppu().nametables_rgba()
+    }
+
+    /// Both pattern tables as a 256x128 RGBA image, coloured with one of
+    /// the eight palettes.
+    pub fn pattern_tables_rgba(&self, palette: u8) -> Result<Vec<u8>, String> {
+        if palette > 7 {
+            return Err(format!("palette must be 0 to 7, not {}", palette));
+        }
+        Ok(self.viewed_ppu().pattern_tables_rgba(palette))
+    }
+
+    /// The palette RAM as a 16x2 RGBA image.
+    pub fn palette_rgba(&self) -> Vec<u8> {
+        self.viewed_ppu().palette_rgba()
+    }
+
+    /// OAM entry `index`, 0 to 63, decoded.
+    pub fn sprite(&self, index: u8) -> Result<Sprite, String> {
+        self.viewed_ppu()
+            .sprites()
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| format!("sprite must be 0 to 63, not {}", index))
+    }
+}
@@ -0,0 +1,294 @@
+//! The profiler: charges every CPU cycle to the subroutine running it,
+//! following JSR/RTS and interrupts/RTI, and reports inclusive and
+//! exclusive cycles per routine, overall or for the last frame, or the
+//! whole call tree as folded stacks for flame graph tools.
+use alloc::collections::BTreeMap;
+
+use crate::cpu::CPU;
+use crate::prelude::*;
+
+// deeper calls, such as JSRs that never return, are charged to the
+// deepest routine
+const MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Copy, Default)]
+struct Counts {
+    calls: u64,
+    cycles: u64,
+}
+
+/// A routine reached along one particular call path.
+struct Node {
+    addr: u16,
+    parent: usize,
+    children: Vec<usize>,
+    depth: usize,
+    total: Counts,
+    frame: Counts,
+    last_frame: Counts,
+}
+
+impl Node {
+    fn counts(&self, last_frame: bool) -> Counts {
+        if last_frame {
+            self.last_frame
+        } else {
+            self.total
+        }
+    }
+}
+
+/// One routine's share of the cycles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoutineProfile {
+    pub addr: u16,
+    pub name: String,
+    pub calls: u64,
+    /// Cycles in the routine and everything it called.
+    pub inclusive: u64,
+    /// Cycles in the routine's own instructions.
+    pub exclusive: u64,
+}
+
+pub struct Profiler {
+    // node 0 is whatever was running when profiling started
+    nodes: Vec<Node>,
+    current: usize,
+    // calls past MAX_DEPTH that have not returned yet
+    overflow: usize,
+    charged_to: u64,
+}
+
+impl Profiler {
+    fn new(entry: u16, cycles: u64) -> Self {
+        Profiler {
+            nodes: vec![Node {
+                addr: entry,
+                parent: 0,
+                children: vec![],
+                depth: 0,
+                total: Counts::default(),
+                frame: Counts::default(),
+                last_frame: Counts::default(),
+            }],
+            current: 0,
+            overflow: 0,
+            charged_to: cycles,
+        }
+    }
+
+    fn charge(&mut self, cycles: u64) {
+        let spent = cycles.saturating_sub(self.charged_to);
+        self.charged_to = cycles;
+        let node = &mut self.nodes[self.current];
+        node.total.cycles += spent;
+        node.frame.cycles += spent;
+    }
+
+    fn call(&mut self, addr: u16) {
+        if self.nodes[self.current].depth + 1 >= MAX_DEPTH {
+            self.overflow += 1;
+            return;
+        }
+        let parent = self.current;
+        let found = self.nodes[parent]
+            .children
+            .iter()
+            .copied()
+            .find(|&child| self.nodes[child].addr == addr);
+        self.current = match found {
+            Some(child) => child,
+            None => {
+                let child = self.nodes.len();
+                self.nodes.push(Node {
+                    addr,
+                    parent,
+                    children: vec![],
+                    depth: self.nodes[parent].depth + 1,
+                    total: Counts::default(),
+                    frame: Counts::default(),
+                    last_frame: Counts::default(),
+                });
+                self.nodes[parent].children.push(child);
+                child
+            }
+        };
+        let node = &mut self.nodes[self.current];
+        node.total.calls += 1;
+        node.frame.calls += 1;
+    }
+
+    /// Returning from the first routine is ignored, since profiling may
+    /// have started inside a subroutine.
+    fn ret(&mut self) {
+        if self.overflow > 0 {
+            self.overflow -= 1;
+        } else {
+            self.current = self.nodes[self.current].parent;
+        }
+    }
+
+    fn end_frame(&mut self) {
+        for node in self.nodes.iter_mut() {
+            node.last_frame = core::mem::take(&mut node.frame);
+        }
+    }
+
+    /// The addresses from the first routine down to `node`.
+    fn path(&self, mut node: usize) -> Vec<u16> {
+        let mut path = vec![self.nodes[node].addr];
+        while node != 0 {
+            node = self.nodes[node].parent;
+            path.push(self.nodes[node].addr);
+        }
+        path.reverse();
+        path
+    }
+
+    /// (addr, calls, inclusive, exclusive) per routine, busiest first;
+    /// `last_frame` limits it to the last whole frame.
+    fn routines(&self, last_frame: bool) -> Vec<(u16, u64, u64, u64)> {
+        // addr -> (calls, inclusive, exclusive)
+        let mut routines: BTreeMap<u16, (u64, u64, u64)> = BTreeMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let counts = node.counts(last_frame);
+            let entry = routines.entry(node.addr).or_default();
+            entry.0 += counts.calls;
+            entry.2 += counts.cycles;
+            // recursive routines count once per path
+            let mut path = self.path(i);
+            path.sort_unstable();
+            path.dedup();
+            for addr in path {
+                routines.entry(addr).or_default().1 += counts.cycles;
+            }
+        }
+        let mut routines: Vec<_> = routines
+            .into_iter()
+            .filter(|(_, (calls, inclusive, _))| *calls > 0 || *inclusive > 0)
+            .map(|(addr, (calls, inclusive, exclusive))| (addr, calls, inclusive, exclusive))
+            .collect();
+        routines.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        routines
+    }
+}
+
+impl CPU {
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    fn routine_name(&self, addr: u16) -> String {
+        self.label_at(addr)
+            .unwrap_or_else(|| format!("${:04X}", addr))
+    }
+
+    /// The profile per routine, busiest first, named from the loaded
+    /// symbols.
+    pub fn profile(&self, last_frame: bool) -> Vec<RoutineProfile> {
+        let profiler = match self.profiler.as_ref() {
+            Some(profiler) => profiler,
+            None => return vec![],
+        };
+        profiler
+            .routines(last_frame)
+            .into_iter()
+            .map(|(addr, calls, inclusive, exclusive)| RoutineProfile {
+                addr,
+                name: self.routine_name(addr),
+                calls,
+                inclusive,
+                exclusive,
+            })
+            .collect()
+    }
+
+    /// Charges the cycles since the last charge to the running routine.
+    pub(crate) fn profile_charge(&mut self) {
+        let cycles = self.cycles;
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.charge(cycles);
+        }
+    }
+
+    /// Called once JSR or an interrupt has jumped to the new routine.
+    pub(crate) fn profile_call(&mut self) {
+        self.profile_charge();
+        let addr = self.program_counter;
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.call(addr);
+        }
+    }
+
+    /// Called by RTS and RTI.
+    pub(crate) fn profile_return(&mut self) {
+        self.profile_charge();
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.ret();
+        }
+    }
+
+    pub(crate) fn profile_frame_done(&mut self) {
+        self.profile_charge();
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.end_frame();
+        }
+    }
+
+    /// Starts profiling from scratch, charging to the code running now
+    /// until the first JSR.
+    pub fn start_profiler(&mut self) {
+        self.profiler = Some(Profiler::new(self.program_counter, self.cycles));
+    }
+
+    pub fn stop_profiler(&mut self) {
+        self.profiler = None;
+    }
+
+    /// A table of calls and inclusive and exclusive cycles per routine,
+    /// busiest first, over the whole run or the last whole frame.
+    pub fn profile_report(&self, last_frame: bool) -> String {
+        let routines = self.profile(last_frame);
+        let total: u64 = routines.iter().map(|r| r.exclusive).sum();
+        let mut lines = vec![format!(
+            "{:<24} {:>8} {:>12} {:>12} {:>6}",
+            "routine", "calls", "inclusive", "exclusive", "%"
+        )];
+        for r in routines {
+            let percent = if total == 0 {
+                0.0
+            } else {
+                r.inclusive as f64 * 100.0 / total as f64
+            };
+            lines.push(format!(
+                "{:<24} {:>8} {:>12} {:>12} {:>6.1}",
+                r.name, r.calls, r.inclusive, r.exclusive, percent
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// The call tree in the folded stacks format that flamegraph.pl and
+    /// speedscope read: `main;update;draw 1234` per call path, with the
+    /// cycles spent in its last routine.
+    pub fn profile_folded(&self, last_frame: bool) -> String {
+        let profiler = match self.profiler.as_ref() {
+            Some(profiler) => profiler,
+            None => return String::new(),
+        };
+        let mut lines = vec![];
+        for (i, node) in profiler.nodes.iter().enumerate() {
+            let cycles = node.counts(last_frame).cycles;
+            if cycles == 0 {
+                continue;
+            }
+            let names: Vec<String> = profiler
+                .path(i)
+                .into_iter()
+                .map(|addr| self.routine_name(addr))
+                .collect();
+            lines.push(format!("{} {}", names.join(";"), cycles));
+        }
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,227 @@
+//! RAM search: narrows the work RAM and PRG-RAM down to the addresses
+//! whose values behave like a game variable, such as lives or score.
+
+use crate::cartridge::{PRG_RAM_SIZE, PRG_RAM_START};
+use crate::cpu::CPU;
+use crate::prelude::*;
+
+const WORK_RAM_SIZE: u16 = 0x0800;
+
+/// The searched regions: the 2K of work RAM, then PRG-RAM.
+pub const REGIONS: [core::ops::Range<u16>; 2] = [
+    0x0000..WORK_RAM_SIZE,
+    PRG_RAM_START..PRG_RAM_START + PRG_RAM_SIZE as u16,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchSize {
+    Byte,
+    /// Little endian, starting at the candidate address.
+    Word,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchFormat {
+    Unsigned,
+    Signed,
+    /// Two decimal digits per byte; other bytes never match.
+    Bcd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchCompare {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+}
+
+impl SearchCompare {
+    fn test(self, value: i32, target: i32) -> bool {
+        match self {
+            SearchCompare::Equal => value == target,
+            SearchCompare::NotEqual => value != target,
+            SearchCompare::Greater => value > target,
+            SearchCompare::Less => value < target,
+        }
+    }
+}
+
+pub struct RamSearch {
+    // the regions' bytes as of the last snapshot or filter
+    previous: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl RamSearch {
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+}
+
+fn offset(addr: u16) -> Option<usize> {
+    let mut base = 0;
+    for region in REGIONS.iter() {
+        if region.contains(&addr) {
+            return Some(base + (addr - region.start) as usize);
+        }
+        base += region.len();
+    }
+    None
+}
+
+fn bcd(byte: u8) -> Option<i32> {
+    let (hi, lo) = (byte >> 4, byte & 0x0f);
+    if hi > 9 || lo > 9 {
+        return None;
+    }
+    Some((hi * 10 + lo) as i32)
+}
+
+/// Interprets the byte at `addr` and, for words, the next one. `byte`
+/// returns `None` outside the searched regions.
+fn decode<F>(addr: u16, size: SearchSize, format: SearchFormat, byte: F) -> Option<i32>
+where
+    F: Fn(u16) -> Option<u8>,
+{
+    let lo = byte(addr)?;
+    match size {
+        SearchSize::Byte => match format {
+            SearchFormat::Unsigned => Some(lo as i32),
+            SearchFormat::Signed => Some(lo as i8 as i32),
+            SearchFormat::Bcd => bcd(lo),
+        },
+        SearchSize::Word => {
+            let hi = byte(addr.checked_add(1)?)?;
+            match format {
+                SearchFormat::Unsigned => Some(u16::from_le_bytes([lo, hi]) as i32),
+                SearchFormat::Signed => Some(i16::from_le_bytes([lo, hi]) as i32),
+                SearchFormat::Bcd => Some(bcd(hi)? * 100 + bcd(lo)?),
+            }
+        }
+    }
+}
+
+impl CPU {
+    pub fn ram_search(&self) -> Option<&RamSearch> {
+        self.ram_search.as_ref()
+    }
+
+    fn snapshot_regions(&self) -> Vec<u8> {
+        REGIONS
+            .iter()
+            .flat_map(|region| region.clone().map(|addr| self.mem_peek(addr)))
+            .collect()
+    }
+
+    fn search_value(&self, addr: u16, size: SearchSize, format: SearchFormat) -> Option<i32> {
+        decode(addr, size, format, |a| offset(a).map(|_| self.mem_peek(a)))
+    }
+
+    /// Keeps the candidates whose current value compares true against
+    /// `value`, or against their previous value if `value` is `None`.
+    /// Returns how many are left.
+    pub fn ram_search_filter(
+        &mut self,
+        size: SearchSize,
+        format: SearchFormat,
+        compare: SearchCompare,
+        value: Option<i32>,
+    ) -> Result<usize, String> {
+        let search = self
+            .ram_search
+            .as_ref()
+            .ok_or_else(|| "no RAM search is running".to_string())?;
+        let previous = &search.previous;
+        let candidates: Vec<u16> = search
+            .candidates
+            .iter()
+            .copied()
+            .filter(|&addr| {
+                let current = match self.search_value(addr, size, format) {
+                    Some(current) => current,
+                    None => return false,
+                };
+                let target = match value {
+                    Some(value) => Some(value),
+                    None => decode(addr, size, format, |a| offset(a).map(|i| previous[i])),
+                };
+                target.is_some_and(|target| compare.test(current, target))
+            })
+            .collect();
+
+        let previous = self.snapshot_regions();
+        let search = self.ram_search.as_mut().unwrap();
+        search.candidates = candidates;
+        search.previous = previous;
+        Ok(search.candidates.len())
+    }
+
+    /// Starts a search with every address as a candidate and snapshots
+    /// their values.
+    pub fn ram_search_start(&mut self) {
+        self.ram_search = Some(RamSearch {
+            previous: self.snapshot_regions(),
+            candidates: REGIONS.iter().flat_map(|region| region.clone()).collect(),
+        });
+    }
+
+    pub fn ram_search_stop(&mut self) {
+        self.ram_search = None;
+    }
+
+    /// Compares each candidate with its value at the last search.
+    pub fn ram_search_previous(
+        &mut self,
+        size: SearchSize,
+        format: SearchFormat,
+        compare: SearchCompare,
+    ) -> Result<usize, String> {
+        self.ram_search_filter(size, format, compare, None)
+    }
+
+    /// Compares each candidate with a constant.
+    pub fn ram_search_constant(
+        &mut self,
+        size: SearchSize,
+        format: SearchFormat,
+        compare: SearchCompare,
+        value: i32,
+    ) -> Result<usize, String> {
+        self.ram_search_filter(size, format, compare, Some(value))
+    }
+
+    /// The addresses still in the running, in ascending order.
+    pub fn ram_search_candidates(&self) -> Vec<u16> {
+        self.ram_search
+            .as_ref()
+            .map_or(vec![], |search| search.candidates.clone())
+    }
+
+    pub fn ram_search_count(&self) -> usize {
+        self.ram_search
+            .as_ref()
+            .map_or(0, |search| search.candidates.len())
+    }
+
+    /// The value at `addr` now, for listing candidates.
+    pub fn ram_search_read(
+        &self,
+        addr: u16,
+        size: SearchSize,
+        format: SearchFormat,
+    ) -> Option<i32> {
+        self.search_value(addr, size, format)
+    }
+
+    /// The value at `addr` as of the last search.
+    pub fn ram_search_read_previous(
+        &self,
+        addr: u16,
+        size: SearchSize,
+        format: SearchFormat,
+    ) -> Option<i32> {
+        let previous = &self.ram_search.as_ref()?.previous;
+        decode(addr, size, format, |a| offset(a).map(|i| previous[i]))
+    }
+}
@@ -0,0 +1,251 @@
+//! Rewind buffer: a ring of periodic save states.
+//!
+//! Only the newest snapshot is kept whole. Each older one is stored as the
+//! XOR against the snapshot taken after it, with runs of zero bytes (the
+//! unchanged ones) collapsed to a count and everything else stored as is.
+//! That is not general compression, but a frame that changed a few bytes
+//! of RAM costs a few bytes. Rewinding walks back from the newest
+//! snapshot, undoing one delta at a time.
+use alloc::collections::VecDeque;
+
+use crate::cpu::CPU;
+use crate::prelude::*;
+
+struct Delta {
+    cycles: u64,
+    len: usize,
+    data: Vec<u8>,
+}
+
+pub struct Rewind {
+    // oldest first; each delta turns the snapshot after it into this one
+    deltas: VecDeque<Delta>,
+    newest: Vec<u8>,
+    newest_cycles: u64,
+    budget: usize,
+    interval: u32,
+    frames: u32,
+    size: usize,
+}
+
+impl Rewind {
+    /// Keeps snapshots in at most `budget` bytes, taking one every
+    /// `interval` frames.
+    pub fn new(budget: usize, interval: u32) -> Self {
+        Rewind {
+            deltas: VecDeque::new(),
+            newest: vec![],
+            newest_cycles: 0,
+            budget,
+            interval: interval.max(1),
+            frames: 0,
+            size: 0,
+        }
+    }
+
+    /// Number of snapshots held.
+    pub fn len(&self) -> usize {
+        if self.newest.is_empty() {
+            0
+        } else {
+            self.deltas.len() + 1
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_empty()
+    }
+
+    /// Bytes used by the snapshots.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+        self.newest.clear();
+        self.size = 0;
+        self.frames = 0;
+    }
+
+    /// Counts a finished frame; true when it is time for a snapshot.
+    fn frame_done(&mut self) -> bool {
+        self.frames += 1;
+        if self.frames < self.interval {
+            return false;
+        }
+        self.frames = 0;
+        true
+    }
+
+    pub fn push(&mut self, state: Vec<u8>, cycles: u64) {
+        if !self.newest.is_empty() {
+            let data = encode(&xor(&self.newest, &state));
+            self.size += data.len();
+            self.size -= self.newest.len();
+            self.deltas.push_back(Delta {
+                cycles: self.newest_cycles,
+                len: self.newest.len(),
+                data,
+            });
+        }
+        self.size += state.len();
+        self.newest = state;
+        self.newest_cycles = cycles;
+
+        while self.size > self.budget {
+            match self.deltas.pop_front() {
+                Some(oldest) => self.size -= oldest.data.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Drops the newest snapshot, making the one before it the newest.
+    fn pop(&mut self) {
+        self.size -= self.newest.len();
+        match self.deltas.pop_back() {
+            Some(delta) => {
+                self.size -= delta.data.len();
+                let mut previous = xor(&self.newest, &decode(&delta.data));
+                previous.truncate(delta.len);
+                self.size += previous.len();
+                self.newest = previous;
+                self.newest_cycles = delta.cycles;
+            }
+            None => self.newest.clear(),
+        }
+    }
+
+    /// The newest snapshot taken before `cycles`, dropping any later ones.
+    /// It stays in the buffer so stepping back again goes further, and the
+    /// next snapshot comes a full interval after it.
+    pub fn before(&mut self, cycles: u64) -> Option<&[u8]> {
+        while !self.newest.is_empty() && self.newest_cycles >= cycles {
+            self.pop();
+        }
+        self.frames = 0;
+        if self.newest.is_empty() {
+            None
+        } else {
+            Some(&self.newest)
+        }
+    }
+}
+
+// states of different lengths XOR as if the shorter one were zero padded
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = vec![0; a.len().max(b.len())];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = a.get(i).unwrap_or(&0) ^ b.get(i).unwrap_or(&0);
+    }
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some(&byte) = data.get(*pos) {
+        *pos += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
+
+/// Encodes a delta as (zero run, literal count, literals) groups. Only
+/// zero runs shrink; other bytes are copied through.
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    write_varint(&mut out, data.len());
+    let mut i = 0;
+    while i < data.len() {
+        let zeros = data[i..].iter().take_while(|&&b| b == 0).count();
+        i += zeros;
+        let start = i;
+        // a single zero between changes is cheaper kept as a literal
+        while i < data.len() && (data[i] != 0 || data.get(i + 1).is_some_and(|&b| b != 0)) {
+            i += 1;
+        }
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, i - start);
+        out.extend_from_slice(&data[start..i]);
+    }
+    out
+}
+
+pub fn decode(data: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(data, &mut pos);
+    let mut out = Vec::with_capacity(len);
+    while out.len() < len && pos < data.len() {
+        let zeros = read_varint(data, &mut pos);
+        out.resize(out.len() + zeros, 0);
+        let literals = read_varint(data, &mut pos);
+        out.extend_from_slice(&data[pos..pos + literals]);
+        pos += literals;
+    }
+    out.resize(len, 0);
+    out
+}
+
+impl CPU {
+    pub fn rewind_buffer(&self) -> Option<&Rewind> {
+        self.rewind.as_ref()
+    }
+
+    /// Called at the start of each frame while rewind is on.
+    pub(crate) fn record_rewind(&mut self) {
+        let take = self.rewind.as_mut().is_some_and(|r| r.frame_done());
+        if take {
+            let state = self.save_state();
+            let cycles = self.cycles;
+            if let Some(rewind) = self.rewind.as_mut() {
+                rewind.push(state, cycles);
+            }
+        }
+    }
+
+    /// Starts keeping a snapshot every `interval` frames in at most
+    /// `budget` bytes. The current state is the first snapshot.
+    pub fn enable_rewind(&mut self, budget: usize, interval: u32) {
+        let mut rewind = Rewind::new(budget, interval);
+        rewind.push(self.save_state(), self.cycles);
+        self.rewind = Some(rewind);
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    /// Goes back to the latest snapshot before now. Called once per frame
+    /// while the rewind button is held; false when the buffer runs out.
+    pub fn rewind(&mut self) -> bool {
+        let cycles = self.cycles;
+        let state = match self.rewind.as_mut().and_then(|r| r.before(cycles)) {
+            Some(state) => state.to_vec(),
+            None => return false,
+        };
+        self.load_state(&state).is_ok()
+    }
+
+    pub fn rewind_len(&self) -> usize {
+        self.rewind.as_ref().map_or(0, |r| r.len())
+    }
+
+    /// Bytes held by the rewind buffer.
+    pub fn rewind_size(&self) -> usize {
+        self.rewind.as_ref().map_or(0, |r| r.size())
+    }
+}
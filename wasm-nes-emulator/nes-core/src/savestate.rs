@@ -0,0 +1,333 @@
+//! Versioned binary save states.
+//!
+//! A state is the magic `NESS`, a little-endian `u16` format version and a
+//! list of chunks, each a 4 byte tag, a `u32` length and the payload.
+//! Loaders skip chunks they do not know and ignore trailing bytes in the
+//! ones they do, so components can add chunks or append fields without
+//! breaking older builds; fields missing from older states keep their
+//! current value.
+//!
+//! Those additions leave `VERSION` alone. It is only bumped when a change
+//! would be misread by older builds, so states from any version up to
+//! `VERSION` load and newer ones are refused.
+//!
+//! `APU ` is reserved for the sound chip and empty until there is one.
+//! `MAP ` is written with a cartridge: the mapper number, then whatever
+//! registers the mapper has, which for NROM is none. A state only loads
+//! over the machine it was saved on, with a cartridge of the same mapper
+//! for the NES.
+use crate::cpu::CPU;
+use crate::machine::Machine;
+use crate::prelude::*;
+
+const MAGIC: &[u8; 4] = b"NESS";
+pub const VERSION: u16 = 1;
+
+const CPU_CHUNK: &[u8; 4] = b"CPU ";
+const RAM_CHUNK: &[u8; 4] = b"RAM ";
+const JOYPAD_CHUNK: &[u8; 4] = b"JOY ";
+const PPU_CHUNK: &[u8; 4] = b"PPU ";
+const APU_CHUNK: &[u8; 4] = b"APU ";
+const MAPPER_CHUNK: &[u8; 4] = b"MAP ";
+
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut buf = MAGIC.to_vec();
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+        StateWriter { buf }
+    }
+
+    /// Writes one chunk whose payload is produced by `body`.
+    pub fn chunk<F: FnOnce(&mut StateWriter)>(&mut self, tag: &[u8; 4], body: F) {
+        self.buf.extend_from_slice(tag);
+        let len_pos = self.buf.len();
+        self.buf.extend_from_slice(&[0; 4]);
+        body(self);
+        let len = (self.buf.len() - len_pos - 4) as u32;
+        self.buf[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads the payload of one chunk.
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, pos: 0 }
+    }
+
+    /// True while fields remain, for fields appended in later versions.
+    pub fn has_more(&self) -> bool {
+        self.pos < self.data.len()
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < len {
+            return Err("save state chunk is truncated".to_string());
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+/// A chunk tag and its payload.
+pub type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+/// Splits a save state into its version and chunks.
+pub fn read_chunks(data: &[u8]) -> Result<(u16, Vec<Chunk<'_>>), String> {
+    if data.len() < 6 || &data[0..4] != MAGIC {
+        return Err("not a save state".to_string());
+    }
+    let version = u16::from_le_bytes([data[4], data[5]]);
+
+    let mut chunks = vec![];
+    let mut reader = StateReader::new(&data[6..]);
+    while reader.has_more() {
+        let mut tag = [0; 4];
+        tag.copy_from_slice(reader.bytes(4)?);
+        let len = reader.u32()? as usize;
+        chunks.push((tag, reader.bytes(len)?));
+    }
+    Ok((version, chunks))
+}
+
+impl CPU {
+    fn save_cpu(&self, w: &mut StateWriter) {
+        w.u8(self.register_a);
+        w.u8(self.register_x);
+        w.u8(self.register_y);
+        w.u8(self.status);
+        w.u8(self.stack_ptr);
+        w.u16(self.program_counter);
+        w.u64(self.cycles);
+        w.bool(self.update);
+        w.u32(self.random.unwrap_or(0));
+        w.u8(self.machine as u8);
+    }
+
+    fn load_cpu(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.register_a = r.u8()?;
+        self.register_x = r.u8()?;
+        self.register_y = r.u8()?;
+        self.status = r.u8()?;
+        self.stack_ptr = r.u8()?;
+        self.program_counter = r.u16()?;
+        self.cycles = r.u64()?;
+        self.update = r.bool()?;
+        if r.has_more() {
+            let seed = r.u32()?;
+            self.set_random_seed(seed);
+        }
+        if r.has_more() {
+            self.machine = match r.u8()? {
+                0 => Machine::Easy6502,
+                1 => Machine::Nes,
+                2 => Machine::Bare,
+                other => return Err(format!("unknown machine {}", other)),
+            };
+        }
+        Ok(())
+    }
+
+    fn save_ppu(&self, w: &mut StateWriter) {
+        let ppu = &self.ppu;
+        w.u8(ppu.ctrl);
+        w.u8(ppu.mask);
+        w.u8(ppu.status);
+        w.u8(ppu.oam_addr);
+        w.u16(ppu.v);
+        w.u16(ppu.t);
+        w.u8(ppu.fine_x);
+        w.bool(ppu.w);
+        w.u8(ppu.read_buffer);
+        w.bytes(&ppu.vram);
+        w.bytes(&ppu.palette);
+        w.bytes(&ppu.oam);
+        if ppu.chr_ram {
+            w.bytes(&ppu.chr);
+        }
+    }
+
+    fn load_ppu(&mut self, r: &mut StateReader) -> Result<(), String> {
+        let ppu = &mut self.ppu;
+        ppu.ctrl = r.u8()?;
+        ppu.mask = r.u8()?;
+        ppu.status = r.u8()?;
+        ppu.oam_addr = r.u8()?;
+        ppu.v = r.u16()?;
+        ppu.t = r.u16()?;
+        ppu.fine_x = r.u8()?;
+        ppu.w = r.bool()?;
+        ppu.read_buffer = r.u8()?;
+        let len = ppu.vram.len();
+        ppu.vram.copy_from_slice(r.bytes(len)?);
+        let len = ppu.palette.len();
+        ppu.palette.copy_from_slice(r.bytes(len)?);
+        let len = ppu.oam.len();
+        ppu.oam.copy_from_slice(r.bytes(len)?);
+        if ppu.chr_ram && r.has_more() {
+            let len = ppu.chr.len();
+            ppu.chr.copy_from_slice(r.bytes(len)?);
+        }
+        Ok(())
+    }
+
+    /// Snapshots the machine; returned to JS as a `Uint8Array`.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.chunk(CPU_CHUNK, |w| self.save_cpu(w));
+        w.chunk(RAM_CHUNK, |w| w.bytes(&self.memory));
+        w.chunk(JOYPAD_CHUNK, |w| {
+            for pad in &self.joypads {
+                w.bool(pad.strobe);
+                w.u8(pad.button_index);
+                w.u8(pad.buttons);
+            }
+        });
+        w.chunk(PPU_CHUNK, |w| self.save_ppu(w));
+        w.chunk(APU_CHUNK, |_| {});
+        if let Some(rom) = &self.cartridge {
+            w.chunk(MAPPER_CHUNK, |w| w.u8(rom.mapper));
+        }
+        w.finish()
+    }
+
+    /// Restores a snapshot from `save_state`. Nothing is changed if the
+    /// state cannot be read, comes from a newer format version or was
+    /// saved on another machine or mapper.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let (version, chunks) = read_chunks(data)?;
+        if version > VERSION {
+            return Err(format!(
+                "save state version {} is newer than supported version {}",
+                version, VERSION
+            ));
+        }
+        let backup = self.save_state();
+        let loaded = self.load_chunks(&chunks).and_then(|_| self.check_machine());
+        if let Err(e) = loaded {
+            let (_, chunks) = read_chunks(&backup)?;
+            self.load_chunks(&chunks)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn load_chunks(&mut self, chunks: &[Chunk]) -> Result<(), String> {
+        for (tag, payload) in chunks {
+            let mut r = StateReader::new(payload);
+            match tag {
+                CPU_CHUNK => self.load_cpu(&mut r)?,
+                RAM_CHUNK => {
+                    let ram = r.bytes(self.memory.len())?;
+                    self.memory.copy_from_slice(ram);
+                }
+                JOYPAD_CHUNK => {
+                    for pad in self.joypads.iter_mut() {
+                        pad.strobe = r.bool()?;
+                        pad.button_index = r.u8()?;
+                        pad.buttons = r.u8()?;
+                    }
+                }
+                PPU_CHUNK => self.load_ppu(&mut r)?,
+                MAPPER_CHUNK => {
+                    let mapper = r.u8()?;
+                    match &self.cartridge {
+                        Some(rom) if rom.mapper == mapper => {}
+                        Some(rom) => {
+                            return Err(format!(
+                                "save state is for mapper {}, the cartridge uses mapper {}",
+                                mapper, rom.mapper
+                            ))
+                        }
+                        None => return Err("save state needs its cartridge loaded".to_string()),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // the NES runs from a cartridge, the other machines never have one
+    fn check_machine(&self) -> Result<(), String> {
+        match (self.machine, self.cartridge.is_some()) {
+            (Machine::Nes, false) => Err("save state needs its cartridge loaded".to_string()),
+            (Machine::Easy6502, true) => {
+                Err("save state is for easy6502, not the loaded cartridge".to_string())
+            }
+            (Machine::Bare, true) => {
+                Err("save state is for a bare 6502, not the loaded cartridge".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Screenshots of the picture, as PNG or as raw palette indices: the
+//! easy6502 display at $0200-$05FF, or on the NES the frame the PPU draws
+//! in the 2C02's 64 colours.
+use crate::cpu::CPU;
+use crate::machine::Machine;
+use crate::ppu_view::{SCREEN_HEIGHT, SCREEN_WIDTH, SYSTEM_PALETTE};
+use crate::prelude::*;
+
+pub const SCREEN_START: u16 = 0x0200;
+pub const WIDTH: usize = 32;
+pub const HEIGHT: usize = 32;
+
+/// The sixteen easy6502 colours; a pixel uses the low nibble of its byte.
+pub const PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], // black
+    [0xff, 0xff, 0xff], // white
+    [0xff, 0x00, 0x00], // red
+    [0x00, 0xff, 0xff], // cyan
+    [0x80, 0x00, 0x80], // purple
+    [0x00, 0x80, 0x00], // green
+    [0x00, 0x00, 0xff], // blue
+    [0xff, 0xff, 0x00], // yellow
+    [0xff, 0xa5, 0x00], // orange
+    [0xa5, 0x2a, 0x2a], // brown
+    [0xff, 0x99, 0x99], // light red
+    [0xa9, 0xa9, 0xa9], // dark grey
+    [0x80, 0x80, 0x80], // grey
+    [0x90, 0xee, 0x90], // light green
+    [0xad, 0xd8, 0xe6], // light blue
+    [0xd3, 0xd3, 0xd3], // light grey
+];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// the most a stored deflate block can hold
+const STORED_BLOCK: usize = 0xffff;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// A zlib stream of uncompressed deflate blocks. The images are tiny, so
+/// compressing them is not worth carrying an encoder.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Encodes 8-bit RGB pixels, row by row, as a PNG.
+pub fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    assert_eq!(rgb.len(), width as usize * height as usize * 3);
+
+    let mut header = vec![];
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits, truecolour, deflate, no filter, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut scanlines = Vec::with_capacity(rgb.len() + height as usize);
+    for row in rgb.chunks(width as usize * 3) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+impl CPU {
+    /// The picture's width and height in pixels.
+    pub fn screen_size(&self) -> (usize, usize) {
+        match self.machine {
+            Machine::Nes => (SCREEN_WIDTH, SCREEN_HEIGHT),
+            _ => (WIDTH, HEIGHT),
+        }
+    }
+
+    /// The picture as RGB triples, each pixel repeated `scale` times in
+    /// both directions.
+    pub fn frame_rgb(&self, scale: usize) -> Vec<u8> {
+        let (width, height) = self.screen_size();
+        let palette: &[[u8; 3]] = match self.machine {
+            Machine::Nes => &SYSTEM_PALETTE,
+            _ => &PALETTE,
+        };
+        let pixels = self.framebuffer();
+        let mut rgb = Vec::with_capacity(width * height * scale * scale * 3);
+        for row in pixels.chunks(width) {
+            for _ in 0..scale {
+                for &index in row {
+                    for _ in 0..scale {
+                        rgb.extend_from_slice(&palette[index as usize]);
+                    }
+                }
+            }
+        }
+        rgb
+    }
+
+    /// The picture's palette indices, one byte per pixel, row by row.
+    /// easy6502 uses its sixteen colours and the NES the 2C02's 64.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        if self.machine == Machine::Nes {
+            return self.ppu.frame();
+        }
+        (0..(WIDTH * HEIGHT) as u16)
+            .map(|i| self.mem_peek(SCREEN_START + i) & 0x0f)
+            .collect()
+    }
+
+    /// The picture as a PNG, scaled up by a whole number.
+    pub fn screenshot_png(&self, scale: u32) -> Result<Vec<u8>, String> {
+        if !(1..=64).contains(&scale) {
+            return Err(format!("scale must be 1 to 64, not {}", scale));
+        }
+        let scale = scale as usize;
+        let side = |n: usize| (n * scale) as u32;
+        let (width, height) = self.screen_size();
+        Ok(encode_png(
+            side(width),
+            side(height),
+            &self.frame_rgb(scale),
+        ))
+    }
+}
@@ -0,0 +1,167 @@
+use alloc::collections::BTreeMap;
+
+use crate::prelude::*;
+
+/// A label at a CPU address. `bank` is the 16K PRG bank the label lives in,
+/// or `None` for RAM and for symbols that apply whatever bank is mapped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u16,
+    pub bank: Option<u8>,
+}
+
+#[derive(Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    by_name: BTreeMap<String, usize>,
+}
+
+impl SymbolTable {
+    pub fn add(&mut self, name: &str, addr: u16, bank: Option<u8>) {
+        self.by_name.insert(name.to_string(), self.symbols.len());
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            addr,
+            bank,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.symbols.clear();
+        self.by_name.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// The label at `addr`. A known `bank` only matches symbols from that
+    /// bank (or bankless ones); `None` matches any bank.
+    pub fn label_at(&self, addr: u16, bank: Option<u8>) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|s| s.addr == addr && (bank.is_none() || s.bank.is_none() || s.bank == bank))
+            .map(|s| s.name.as_str())
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.by_name.get(name).map(|&i| &self.symbols[i])
+    }
+
+    /// Loads an FCEUX name list, one `$C000#name#comment` entry per line.
+    /// FCEUX keeps one file per bank (`game.nes.0.nl`) plus `game.nes.ram.nl`,
+    /// so the caller says which bank the file describes.
+    pub fn load_nl(&mut self, text: &str, bank: Option<u8>) -> Result<usize, String> {
+        let mut count = 0;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            // continuation lines of multi-line comments start with a backslash
+            if !line.starts_with('$') {
+                continue;
+            }
+            let mut fields = line[1..].splitn(3, '#');
+            let addr = fields.next().unwrap_or("");
+            let name = fields.next().unwrap_or("").trim();
+            if name.is_empty() {
+                continue;
+            }
+            let addr = u16::from_str_radix(addr, 16)
+                .map_err(|_| format!("line {}: bad address '{}'", number + 1, addr))?;
+            self.add(name, addr, bank);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Loads the labels from an ld65 `--dbgfile`. Symbols in segments that
+    /// were written to the ROM image get the 16K PRG bank of their file
+    /// offset, skipping the 16 byte iNES header.
+    pub fn load_dbg(&mut self, text: &str) -> Result<usize, String> {
+        let mut segment_banks: BTreeMap<String, Option<u8>> = BTreeMap::new();
+        let mut labels = vec![];
+
+        for (number, line) in text.lines().enumerate() {
+            let (kind, rest) = match line.split_once(|c: char| c.is_whitespace()) {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let fields = dbg_fields(rest.trim());
+            let get = |key: &str| {
+                fields
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.as_str())
+            };
+
+            match kind {
+                "seg" => {
+                    let id = get("id").unwrap_or("").to_string();
+                    let bank = match (get("oname"), get("ooffs")) {
+                        (Some(_), Some(offset)) => {
+                            let offset = dbg_number(offset)
+                                .ok_or_else(|| format!("line {}: bad ooffs", number + 1))?;
+                            Some((offset.saturating_sub(16) / 0x4000) as u8)
+                        }
+                        _ => None,
+                    };
+                    segment_banks.insert(id, bank);
+                }
+                "sym" => {
+                    if get("type") != Some("lab") {
+                        continue;
+                    }
+                    let name = get("name").unwrap_or("").to_string();
+                    let value = get("val")
+                        .and_then(dbg_number)
+                        .ok_or_else(|| format!("line {}: bad val", number + 1))?;
+                    let segment = get("seg").map(|s| s.to_string());
+                    labels.push((name, value as u16, segment));
+                }
+                _ => {}
+            }
+        }
+
+        let count = labels.len();
+        for (name, addr, segment) in labels {
+            let bank = match addr {
+                // RAM and registers are not banked
+                0x0000..=0x7fff => None,
+                _ => segment.and_then(|s| segment_banks.get(&s).cloned().flatten()),
+            };
+            self.add(&name, addr, bank);
+        }
+        Ok(count)
+    }
+}
+
+/// Splits `key=value,key="quoted, value"` into pairs.
+fn dbg_fields(text: &str) -> Vec<(String, String)> {
+    let mut fields = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in text.chars().chain(core::iter::once(',')) {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                if let Some((key, value)) = current.split_once('=') {
+                    fields.push((key.to_string(), value.to_string()));
+                }
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+    fields
+}
+
+fn dbg_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
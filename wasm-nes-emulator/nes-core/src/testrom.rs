@@ -0,0 +1,243 @@
+//! Runs standard 6502/NES test ROMs and reads back their verdict using each
+//! suite's own reporting protocol.
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::cartridge::Rom;
+use crate::cpu::CPU;
+use crate::machine::Machine;
+
+const CYCLES_PER_FRAME: u64 = 29781;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// nestest.nes in automation mode: start at $C000, run to the final
+    /// RTS at $C66E and read the error codes it leaves in $02 and $03.
+    Nestest,
+    /// blargg's tests: $6000 holds $80 while running, $81 when the reset
+    /// button should be pressed, then the result code; $6004 holds text.
+    /// A result only counts once $80 has been seen.
+    Blargg,
+    /// Klaus Dormann's functional test, a 64K image started at $0400 that
+    /// traps in a `JMP *` loop; only the trap at `success` is a pass. The
+    /// address depends on how the test was assembled, so it comes from the
+    /// fixture's configuration. It tests BRK too, so it runs on a bare
+    /// 6502, where BRK goes through $FFFE.
+    Klaus { success: u16 },
+}
+
+impl Protocol {
+    /// Guesses the protocol from a fixture's file name. Klaus images need
+    /// their success address, see [`Protocol::klaus_config`].
+    pub fn for_file(name: &str) -> Option<Protocol> {
+        let name = name.to_ascii_lowercase();
+        if name.starts_with("nestest") && name.ends_with(".nes") {
+            Some(Protocol::Nestest)
+        } else if name.ends_with(".nes") {
+            Some(Protocol::Blargg)
+        } else {
+            None
+        }
+    }
+
+    /// Reads the Klaus images of a fixture set, one per line as the file
+    /// name and the success address in hex, e.g.
+    /// `6502_functional_test.bin $3469`. Blank lines and `#` comments are
+    /// skipped.
+    pub fn klaus_config(text: &str) -> Result<Vec<(String, Protocol)>, String> {
+        let mut images = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = || format!("line {}: expected '<file> <success address>'", number + 1);
+            let (name, addr) = line.rsplit_once(char::is_whitespace).ok_or_else(error)?;
+            let addr = addr.trim_start_matches('$').trim_start_matches("0x");
+            let success = u16::from_str_radix(addr, 16).map_err(|_| error())?;
+            images.push((name.trim().to_string(), Protocol::Klaus { success }));
+        }
+        Ok(images)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    Timeout,
+    /// The emulator panicked, usually on an opcode it does not implement.
+    Crash(String),
+    /// The ROM needs hardware the emulator lacks, such as a mapper.
+    Unsupported(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "pass"),
+            Outcome::Fail(why) => write!(f, "FAIL: {}", why),
+            Outcome::Timeout => write!(f, "timeout"),
+            Outcome::Crash(why) => write!(f, "crash: {}", why),
+            Outcome::Unsupported(why) => write!(f, "unsupported: {}", why),
+        }
+    }
+}
+
+/// Runs a test ROM for at most `max_frames` frames of CPU time.
+pub fn run_test_rom(data: &[u8], protocol: Protocol, max_frames: u64) -> Outcome {
+    let max_cycles = max_frames * CYCLES_PER_FRAME;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match protocol {
+        Protocol::Nestest => nestest(data, max_cycles),
+        Protocol::Blargg => blargg(data, max_cycles),
+        Protocol::Klaus { success } => klaus(data, success, max_cycles),
+    }));
+    result.unwrap_or_else(|e| {
+        let message = e
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_default();
+        Outcome::Crash(message)
+    })
+}
+
+/// Loads a test cartridge. Most of blargg's ROMs need mapper 1, so a
+/// valid image that `load_rom` refuses is unsupported, not a failure.
+fn load(data: &[u8]) -> Result<CPU, Outcome> {
+    Rom::new(data).map_err(Outcome::Fail)?;
+    let mut cpu = CPU::new();
+    cpu.load_rom(data).map_err(Outcome::Unsupported)?;
+    Ok(cpu)
+}
+
+fn nestest(data: &[u8], max_cycles: u64) -> Outcome {
+    let mut cpu = match load(data) {
+        Ok(cpu) => cpu,
+        Err(outcome) => return outcome,
+    };
+    cpu.program_counter = 0xc000;
+    cpu.status = 0x24;
+    cpu.stack_ptr = 0xfd;
+
+    let mut finished = false;
+    while cpu.cycles < max_cycles && !finished {
+        if cpu.next() {
+            return jammed(&cpu);
+        }
+        finished = cpu.program_counter == 0xc66e;
+    }
+    match (cpu.mem_peek(0x02), cpu.mem_peek(0x03)) {
+        (0, 0) if finished => Outcome::Pass,
+        (0, 0) => Outcome::Timeout,
+        (official, unofficial) => {
+            Outcome::Fail(format!("error codes ${:02X} ${:02X}", official, unofficial))
+        }
+    }
+}
+
+fn blargg_text(cpu: &CPU) -> String {
+    let text: Vec<u8> = (0x6004..0x7000)
+        .map(|addr| cpu.mem_peek(addr))
+        .take_while(|&b| b != 0)
+        .collect();
+    String::from_utf8_lossy(&text).trim().to_string()
+}
+
+fn blargg(data: &[u8], max_cycles: u64) -> Outcome {
+    let mut cpu = match load(data) {
+        Ok(cpu) => cpu,
+        Err(outcome) => return outcome,
+    };
+    let signature = |cpu: &CPU| {
+        (0x6001..0x6004)
+            .map(|a| cpu.mem_peek(a))
+            .eq([0xde, 0xb0, 0x61])
+    };
+    let mut reset_at = None;
+    // whatever $6000 held before the test started is not a result
+    let mut running = false;
+
+    while cpu.cycles < max_cycles {
+        if cpu.next() {
+            return jammed(&cpu);
+        }
+        if !signature(&cpu) {
+            continue;
+        }
+        match cpu.mem_peek(0x6000) {
+            0x80 => running = true,
+            // the tests want reset pressed no sooner than 100ms later
+            0x81 => match reset_at {
+                None => reset_at = Some(cpu.cycles + 6 * CYCLES_PER_FRAME),
+                Some(at) if cpu.cycles >= at => {
+                    reset_at = None;
+                    cpu.soft_reset();
+                }
+                Some(_) => {}
+            },
+            _ if !running => {}
+            0 => return Outcome::Pass,
+            code => {
+                return Outcome::Fail(format!("result {}: {}", code, blargg_text(&cpu)));
+            }
+        }
+    }
+    Outcome::Timeout
+}
+
+fn klaus(data: &[u8], success: u16, max_cycles: u64) -> Outcome {
+    if data.len() != 0x10000 {
+        return Outcome::Fail("expected a 64K memory image".to_string());
+    }
+    let mut cpu = CPU::new();
+    cpu.machine = Machine::Bare;
+    for (addr, &byte) in data.iter().enumerate() {
+        cpu.mem_write(addr as u16, byte);
+    }
+    cpu.program_counter = 0x0400;
+    cpu.stack_ptr = 0xfd;
+
+    while cpu.cycles < max_cycles {
+        let pc = cpu.program_counter;
+        if cpu.next() {
+            return jammed(&cpu);
+        }
+        if cpu.program_counter == pc {
+            return if pc == success {
+                Outcome::Pass
+            } else {
+                Outcome::Fail(format!("trapped at ${:04X}", pc))
+            };
+        }
+    }
+    Outcome::Timeout
+}
+
+// the NES and a bare 6502 only stop on opcodes the core lacks
+fn jammed(cpu: &CPU) -> Outcome {
+    let pc = cpu.program_counter;
+    let code = cpu.mem_peek(pc);
+    Outcome::Crash(format!("jammed on ${:02X} at ${:04X}", code, pc))
+}
+
+/// A Markdown table of results, one row per ROM.
+pub fn compatibility_table(results: &[(String, Outcome)]) -> String {
+    let passed = results.iter().filter(|(_, o)| *o == Outcome::Pass).count();
+    let unsupported = results
+        .iter()
+        .filter(|(_, o)| matches!(o, Outcome::Unsupported(_)))
+        .count();
+    let mut out = String::from("| ROM | Result |\n|-----|--------|\n");
+    for (name, outcome) in results {
+        let outcome = outcome.to_string().replace('|', "\\|").replace('\n', " ");
+        out.push_str(&format!("| {} | {} |\n", name, outcome));
+    }
+    out.push_str(&format!(
+        "\n{} of {} passed, {} unsupported\n",
+        passed,
+        results.len(),
+        unsupported
+    ));
+    out
+}
@@ -0,0 +1,173 @@
+use crate::cpu::AddressingMode;
+use crate::cpu::CPU;
+use crate::opcodes;
+use crate::prelude::*;
+
+/// Formats an operand address, using its label when symbols are loaded.
+fn operand(cpu: &CPU, addr: u16, zero_page: bool) -> String {
+    let symbols = cpu.debugger().symbols();
+    match symbols.label_at(addr, cpu.prg_bank(addr)) {
+        Some(label) => label.to_string(),
+        None if zero_page => format!("${:02X}", addr),
+        None => format!("${:04X}", addr),
+    }
+}
+
+/// A byte listed as data rather than decoded.
+pub fn byte_line(addr: u16, byte: u8) -> String {
+    format!("{:04X}  {:02X}        .byte ${:02X}", addr, byte, byte)
+}
+
+/// Disassembles the instruction at `begin` in nestest.log layout, e.g.
+/// `C000  4C F5 C5  JMP $C5F5`, with memory operands annotated with the
+/// address and value they resolve to under the current registers.
+pub fn disassemble(cpu: &CPU, begin: u16) -> String {
+    let code = cpu.mem_peek(begin);
+    let ops = match opcodes::opcode(code) {
+        Some(ops) => ops,
+        // unofficial opcodes and data
+        None => return byte_line(begin, code),
+    };
+
+    let mut hex_dump = vec![code];
+
+    let (mem_addr, stored_value) = match ops.address_mode {
+        AddressingMode::Immediate | AddressingMode::NoneAddressing => (0, 0),
+        _ => {
+            let addr = cpu.get_absolute_address(&ops.address_mode, begin.wrapping_add(1));
+            (addr, cpu.mem_peek(addr))
+        }
+    };
+
+    let tmp = match ops.bytes {
+        1 => match ops.code {
+            0x0A | 0x4A | 0x2A | 0x6A => "A ".to_string(),
+            _ => String::new(),
+        },
+        2 => {
+            let address = cpu.mem_peek(begin.wrapping_add(1));
+            hex_dump.push(address);
+
+            match ops.address_mode {
+                AddressingMode::Immediate => format!("#${:02X}", address),
+                AddressingMode::ZeroPage => {
+                    format!("{} = {:02X}", operand(cpu, mem_addr, true), stored_value)
+                }
+                AddressingMode::ZeroPage_X => format!(
+                    "{},X @ {:02X} = {:02X}",
+                    operand(cpu, address as u16, true),
+                    mem_addr,
+                    stored_value
+                ),
+                AddressingMode::ZeroPage_Y => format!(
+                    "{},Y @ {:02X} = {:02X}",
+                    operand(cpu, address as u16, true),
+                    mem_addr,
+                    stored_value
+                ),
+                AddressingMode::Indirect_X => format!(
+                    "({},X) @ {:02X} = {:04X} = {:02X}",
+                    operand(cpu, address as u16, true),
+                    address.wrapping_add(cpu.register_x),
+                    mem_addr,
+                    stored_value
+                ),
+                AddressingMode::Indirect_Y => format!(
+                    "({}),Y = {:04X} @ {:04X} = {:02X}",
+                    operand(cpu, address as u16, true),
+                    mem_addr.wrapping_sub(cpu.register_y as u16),
+                    mem_addr,
+                    stored_value
+                ),
+                AddressingMode::NoneAddressing => {
+                    //branches: show the target of the relative jump
+                    let address = begin.wrapping_add(2).wrapping_add((address as i8) as u16);
+                    operand(cpu, address, false)
+                }
+                _ => panic!(
+                    "unexpected addressing mode {:?} has ops-len 2. code {:02x}",
+                    ops.address_mode, ops.code
+                ),
+            }
+        }
+        3 => {
+            let address_lo = cpu.mem_peek(begin.wrapping_add(1));
+            let address_hi = cpu.mem_peek(begin.wrapping_add(2));
+            hex_dump.push(address_lo);
+            hex_dump.push(address_hi);
+
+            let address = cpu.mem_read_u16(begin.wrapping_add(1));
+
+            match ops.address_mode {
+                AddressingMode::NoneAddressing => {
+                    if ops.code == 0x6C {
+                        //jmp indirect, including the page wrap bug
+                        let jmp_addr = if address & 0x00FF == 0x00FF {
+                            let lo = cpu.mem_peek(address);
+                            let hi = cpu.mem_peek(address & 0xFF00);
+                            (hi as u16) << 8 | (lo as u16)
+                        } else {
+                            cpu.mem_read_u16(address)
+                        };
+                        format!("({}) = {:04X}", operand(cpu, address, false), jmp_addr)
+                    } else {
+                        operand(cpu, address, false)
+                    }
+                }
+                AddressingMode::Absolute => match ops.code {
+                    0x4C | 0x20 => operand(cpu, mem_addr, false),
+                    _ => format!("{} = {:02X}", operand(cpu, mem_addr, false), stored_value),
+                },
+                AddressingMode::Absolute_X => format!(
+                    "{},X @ {:04X} = {:02X}",
+                    operand(cpu, address, false),
+                    mem_addr,
+                    stored_value
+                ),
+                AddressingMode::Absolute_Y => format!(
+                    "{},Y @ {:04X} = {:02X}",
+                    operand(cpu, address, false),
+                    mem_addr,
+                    stored_value
+                ),
+                _ => panic!(
+                    "unexpected addressing mode {:?} has ops-len 3. code {:02x}",
+                    ops.address_mode, ops.code
+                ),
+            }
+        }
+        _ => String::new(),
+    };
+
+    let hex_str = hex_dump
+        .iter()
+        .map(|z| format!("{:02X}", z))
+        .collect::<Vec<String>>()
+        .join(" ");
+    format!("{:04X}  {:8} {: >4} {}", begin, hex_str, ops.name, tmp)
+        .trim()
+        .to_string()
+}
+
+/// Formats the instruction at the program counter as a nestest.log line:
+///
+/// `C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7`
+///
+/// Only reads memory, so it is safe to call between any two instructions.
+pub fn trace(cpu: &CPU) -> String {
+    let asm_str = disassemble(cpu, cpu.program_counter);
+
+    let (scanline, dot) = cpu.ppu_position();
+    format!(
+        "{:47} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:3},{:3} CYC:{}",
+        asm_str,
+        cpu.register_a,
+        cpu.register_x,
+        cpu.register_y,
+        cpu.status,
+        cpu.stack_ptr,
+        scanline,
+        dot,
+        cpu.cycles
+    )
+}
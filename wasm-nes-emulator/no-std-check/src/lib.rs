@@ -0,0 +1,67 @@
+//! Builds the emulator core as `#![no_std]` on the host: this crate is
+//! `no_std` itself and uses the core without its `std` feature, so
+//! `cargo no-std-check` (an alias for `cargo test -p no-std-check`) fails if
+//! the core reaches for std. It has to be built on its own; a `--workspace`
+//! build unifies features and turns std back on through the facade crate.
+#![no_std]
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use nes_core::cpu::{FrameStatus, CPU};
+
+/// Assembles and runs an easy6502 program to its BRK and returns X.
+pub fn run_asm(source: &str) -> Result<u8, String> {
+    let mut cpu = CPU::new();
+    cpu.load_asm(source)?;
+    cpu.run();
+    Ok(cpu.register_x)
+}
+
+/// Runs an iNES image for `frames` frames and returns a save state.
+pub fn run_rom(rom: &[u8], frames: u32) -> Result<Vec<u8>, String> {
+    let mut cpu = CPU::new();
+    cpu.load_rom(rom)?;
+    for _ in 0..frames {
+        if cpu.run_frame() != FrameStatus::Complete {
+            return Err(String::from("the ROM stopped"));
+        }
+    }
+    Ok(cpu.save_state())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn runs_a_program() {
+        assert_eq!(
+            run_asm("ldx #0\nloop:\n inx\n cpx #5\n bne loop\n brk"),
+            Ok(5)
+        );
+        assert!(run_asm("lda").is_err());
+    }
+
+    #[test]
+    fn runs_a_cartridge() {
+        // NROM-128 looping on `jmp $8000`, with the reset vector at $8000
+        let mut rom = vec![b'N', b'E', b'S', 0x1a, 1, 1, 0, 0];
+        rom.resize(16, 0);
+        let mut prg = vec![0; 0x4000];
+        prg[..3].copy_from_slice(&[0x4c, 0x00, 0x80]);
+        prg[0x3ffd] = 0x80;
+        rom.extend(prg);
+        rom.extend(vec![0; 0x2000]);
+
+        let state = run_rom(&rom, 2).unwrap();
+        let mut cpu = CPU::new();
+        cpu.load_rom(&rom).unwrap();
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.frame(), 2);
+    }
+}
@@ -0,0 +1,317 @@
+//! Runs the emulator without a browser, for CI and scripts.
+//!
+//! headless [options] <rom.nes | program.bin | program.asm>
+extern crate wasm_nes_emulator;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use wasm_nes_emulator::capture::Sink;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::expr::Expr;
+use wasm_nes_emulator::machine::Machine;
+use wasm_nes_emulator::trace::trace;
+
+const USAGE: &str = "usage: headless [options] <rom.nes | program.bin | program.asm>
+
+Loads an iNES ROM, an easy6502 program's source, or a raw easy6502
+program at $0600, and runs it.
+
+options:
+  --frames <n>        stop after n frames
+  --until <expr>      stop once a condition holds, e.g. \"[$6000] != $80\"
+  --max-frames <n>    give up after n frames (default 3600)
+  --movie <file.fm2>  play controller input from a movie
+  --symbols <file>    load labels from an ld65 .dbg or FCEUX .nl file
+  --trace <file>      write a nestest-style trace log
+  --ram-dump <file>   write $0000-$07FF when done
+  --screenshot <file> write the display as a PNG when done
+  --scale <n>         scale the screenshot up n times (default 8)
+  --framebuffer <file> write the display's palette indices when done
+  --video <file.y4m>  record every frame, scaled like --scale
+  --audio <file.wav>  record the audio track: silence on easy6502, which
+                      has no sound, and refused on the NES until there is
+                      an APU
+  --seed <n>          seed for the easy6502 random byte at $FE
+  --cdl <file.cdl>    log code and data in FCEUX's format, adding to the
+                      file if it exists
+  --profile <file>    write cycles per call path as folded stacks, for
+                      flamegraph.pl or speedscope
+
+Without --frames it runs until BRK ends an easy6502 program, the CPU jams
+on an unknown opcode, the --until condition holds or the movie ends,
+giving up after --max-frames unless a movie is playing without --until.
+Exits with 2 when it gives up and 3 when the CPU jams.";
+
+struct Options {
+    rom: PathBuf,
+    frames: Option<u64>,
+    until: Option<String>,
+    max_frames: u64,
+    movie: Option<PathBuf>,
+    symbols: Option<PathBuf>,
+    trace: Option<PathBuf>,
+    ram_dump: Option<PathBuf>,
+    screenshot: Option<PathBuf>,
+    scale: u32,
+    framebuffer: Option<PathBuf>,
+    video: Option<PathBuf>,
+    audio: Option<PathBuf>,
+    seed: u32,
+    cdl: Option<PathBuf>,
+    profile: Option<PathBuf>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom: PathBuf::new(),
+        frames: None,
+        until: None,
+        max_frames: 3600,
+        movie: None,
+        symbols: None,
+        trace: None,
+        ram_dump: None,
+        screenshot: None,
+        scale: 8,
+        framebuffer: None,
+        video: None,
+        audio: None,
+        seed: 1,
+        cdl: None,
+        profile: None,
+    };
+    let mut rom = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        let number = |text: String| {
+            text.parse::<u64>()
+                .map_err(|_| format!("{}: not a number '{}'", arg, text))
+        };
+        match arg.as_str() {
+            "--frames" => options.frames = Some(number(value()?)?),
+            "--until" => options.until = Some(value()?),
+            "--max-frames" => options.max_frames = number(value()?)?,
+            "--movie" => options.movie = Some(value()?.into()),
+            "--symbols" => options.symbols = Some(value()?.into()),
+            "--trace" => options.trace = Some(value()?.into()),
+            "--ram-dump" => options.ram_dump = Some(value()?.into()),
+            "--screenshot" => options.screenshot = Some(value()?.into()),
+            "--scale" => options.scale = number(value()?)? as u32,
+            "--framebuffer" => options.framebuffer = Some(value()?.into()),
+            "--video" => options.video = Some(value()?.into()),
+            "--audio" => options.audio = Some(value()?.into()),
+            "--seed" => options.seed = (number(value()?)? as u32).max(1),
+            "--cdl" => options.cdl = Some(value()?.into()),
+            "--profile" => options.profile = Some(value()?.into()),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => rom = Some(PathBuf::from(arg)),
+        }
+    }
+    options.rom = rom.ok_or_else(|| USAGE.to_string())?;
+    Ok(options)
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>, String> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<(), String> {
+    std::fs::write(path, data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn load(cpu: &mut CPU, options: &Options) -> Result<(), String> {
+    let extension = options
+        .rom
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("nes") => cpu.load_rom_file(&options.rom)?,
+        Some("asm") | Some("s") => {
+            let source = String::from_utf8_lossy(&read(&options.rom)?).into_owned();
+            cpu.load_asm(&source)?;
+        }
+        _ => cpu.load_pro(read(&options.rom)?),
+    }
+
+    if let Some(path) = &options.symbols {
+        let text = String::from_utf8_lossy(&read(path)?).into_owned();
+        if path.extension().is_some_and(|ext| ext == "dbg") {
+            cpu.load_dbg(&text)?;
+        } else {
+            cpu.load_nl(&text, None)?;
+        }
+    }
+    if let Some(path) = &options.movie {
+        cpu.play_fm2(&String::from_utf8_lossy(&read(path)?))?;
+    }
+    if let Some(path) = &options.cdl {
+        if path.exists() {
+            cpu.load_cdl(&read(path)?)?;
+        } else {
+            cpu.start_cdl()?;
+        }
+    }
+    if options.profile.is_some() {
+        cpu.start_profiler();
+    }
+    if options.video.is_some() || options.audio.is_some() {
+        let video = match &options.video {
+            Some(path) => Some(Box::new(create(path)?) as Box<dyn Write>),
+            None => None,
+        };
+        let audio = match &options.audio {
+            Some(path) => Some(Box::new(create(path)?) as Box<dyn Sink>),
+            None => None,
+        };
+        cpu.start_capture(video, audio, options.scale)?;
+    }
+    Ok(())
+}
+
+enum Stop {
+    Brk,
+    Jam,
+    Frames,
+    Condition,
+    MovieEnd,
+    GaveUp,
+}
+
+fn run(cpu: &mut CPU, options: &Options) -> Result<Stop, String> {
+    let until = match &options.until {
+        Some(src) => Some(Expr::parse_with_symbols(src, cpu.debugger().symbols())?),
+        None => None,
+    };
+    if cpu.machine == Machine::Easy6502 {
+        // the same random byte the web page provides
+        cpu.set_random_seed(options.seed);
+    }
+    // a movie ends by itself, anything else may loop forever
+    let capped = options.frames.is_none() && (until.is_some() || options.movie.is_none());
+    // written as it goes, a long run's trace does not fit in memory
+    let mut log = match &options.trace {
+        Some(path) => Some((path, create(path)?)),
+        None => None,
+    };
+    let start = cpu.frame();
+
+    let stop = loop {
+        let frames = cpu.frame() - start;
+        if options.frames == Some(frames) {
+            break Stop::Frames;
+        }
+        if capped && frames >= options.max_frames {
+            break Stop::GaveUp;
+        }
+        if until.is_none() && options.frames.is_none() && cpu.movie_finished() {
+            break Stop::MovieEnd;
+        }
+
+        if let Some((path, out)) = log.as_mut() {
+            writeln!(out, "{}", trace(cpu)).map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        let code = cpu.mem_peek(cpu.program_counter);
+        if cpu.next() {
+            break if code == 0x00 { Stop::Brk } else { Stop::Jam };
+        }
+        if let Some(condition) = &until {
+            if condition.eval(cpu, 0) != 0 {
+                break Stop::Condition;
+            }
+        }
+    };
+    if let Some((path, mut out)) = log {
+        out.flush()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(stop)
+}
+
+fn write_outputs(cpu: &mut CPU, options: &Options) -> Result<(), String> {
+    if let Some(path) = &options.ram_dump {
+        let ram: Vec<u8> = (0..0x800).map(|addr| cpu.mem_peek(addr)).collect();
+        write(path, &ram)?;
+    }
+    if let Some(path) = &options.screenshot {
+        write(path, &cpu.screenshot_png(options.scale)?)?;
+    }
+    if let Some(path) = &options.framebuffer {
+        write(path, &cpu.framebuffer())?;
+    }
+    if let Some(path) = &options.cdl {
+        write(path, &cpu.export_cdl())?;
+    }
+    if let Some(path) = &options.profile {
+        write(path, (cpu.profile_folded(false) + "\n").as_bytes())?;
+    }
+    cpu.stop_capture()?;
+    cpu.flush_save_ram(&options.rom)?;
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1);
+        }
+    };
+
+    let mut cpu = CPU::new();
+    let result = load(&mut cpu, &options).and_then(|_| run(&mut cpu, &options));
+    let stop = match result {
+        Ok(stop) => stop,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1);
+        }
+    };
+
+    let reason = match stop {
+        Stop::Brk => "BRK",
+        Stop::Jam => "jammed",
+        Stop::Frames => "frame limit",
+        Stop::Condition => "condition",
+        Stop::MovieEnd => "end of movie",
+        Stop::GaveUp if options.until.is_some() => "max frames, condition never held",
+        Stop::GaveUp => "max frames",
+    };
+    println!(
+        "stopped on {} at frame {}, PC=${:04X} CYC:{}",
+        reason,
+        cpu.frame(),
+        cpu.program_counter,
+        cpu.cycles
+    );
+    if let Some(frame) = cpu.movie_desync() {
+        println!("movie desynced at frame {}", frame);
+    }
+
+    if let Err(message) = write_outputs(&mut cpu, &options) {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+
+    match stop {
+        Stop::GaveUp => process::exit(2),
+        Stop::Jam => process::exit(3),
+        _ => {}
+    }
+}
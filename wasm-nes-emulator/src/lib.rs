@@ -1,18 +1,17 @@
-
+#[cfg(feature = "wasm")]
+#[allow(dead_code)]
 mod utils;
-use wasm_bindgen::prelude::*;
-use std::fmt;
-use js_sys::Math;
-
-mod opcodes;
-pub mod cpu;
 
-#[macro_use]
-extern crate lazy_static;
+pub use nes_core::*;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
+#[cfg(feature = "wasm")]
 extern crate web_sys;
 
 // A macro to provide `println!(..)`-style syntax for `console.log` logging.
+#[cfg(feature = "wasm")]
+#[allow(unused_macros)]
 macro_rules! log {
     ( $( $t:tt )* ) => {
         web_sys::console::log_1(&format!( $( $t )* ).into());
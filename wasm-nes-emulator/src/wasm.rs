@@ -0,0 +1,683 @@
+//! The JavaScript bindings, built with the `wasm` feature. `CPU` here
+//! wraps the emulator core and each method forwards to the core method of
+//! the same name, so the core itself stays plain Rust. The enums and small
+//! structs the methods pass are copies of the core's, converted at the
+//! boundary.
+use wasm_bindgen::prelude::*;
+
+use crate::cpu;
+use crate::debugger;
+use crate::machine;
+use crate::ppu_view;
+use crate::ram_search;
+
+#[wasm_bindgen]
+pub struct CPU {
+    cpu: cpu::CPU,
+}
+
+impl CPU {
+    pub fn core(&self) -> &cpu::CPU {
+        &self.cpu
+    }
+
+    pub fn core_mut(&mut self) -> &mut cpu::CPU {
+        &mut self.cpu
+    }
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl CPU {
+    pub fn new() -> Self {
+        CPU {
+            cpu: cpu::CPU::new(),
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn register_a(&self) -> u8 {
+        self.cpu.register_a
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_register_a(&mut self, value: u8) {
+        self.cpu.register_a = value;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn register_x(&self) -> u8 {
+        self.cpu.register_x
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_register_x(&mut self, value: u8) {
+        self.cpu.register_x = value;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn register_y(&self) -> u8 {
+        self.cpu.register_y
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_register_y(&mut self, value: u8) {
+        self.cpu.register_y = value;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn status(&self) -> u8 {
+        self.cpu.status
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_status(&mut self, value: u8) {
+        self.cpu.status = value;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn program_counter(&self) -> u16 {
+        self.cpu.program_counter
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_program_counter(&mut self, value: u16) {
+        self.cpu.program_counter = value;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn stack_ptr(&self) -> u8 {
+        self.cpu.stack_ptr
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_stack_ptr(&mut self, value: u8) {
+        self.cpu.stack_ptr = value;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn cycles(&self) -> u64 {
+        self.cpu.cycles
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_cycles(&mut self, value: u64) {
+        self.cpu.cycles = value;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn machine(&self) -> Machine {
+        self.cpu.machine.into()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_machine(&mut self, value: Machine) {
+        self.cpu.machine = value.into();
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn update(&self) -> bool {
+        self.cpu.update
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_update(&mut self, value: bool) {
+        self.cpu.update = value;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn check(&self) -> bool {
+        self.cpu.check
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_check(&mut self, value: bool) {
+        self.cpu.check = value;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn save_ram_dirty(&self) -> bool {
+        self.cpu.save_ram_dirty
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_save_ram_dirty(&mut self, value: bool) {
+        self.cpu.save_ram_dirty = value;
+    }
+
+    pub fn load_asm(&mut self, source: &str) -> Result<(), String> {
+        self.cpu.load_asm(source)
+    }
+
+    pub fn load_rom(&mut self, raw: &[u8]) -> Result<(), String> {
+        self.cpu.load_rom(raw)
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.cpu.has_battery()
+    }
+
+    pub fn save_ram(&self) -> Vec<u8> {
+        self.cpu.save_ram()
+    }
+
+    pub fn load_save_ram(&mut self, data: &[u8]) -> Result<(), String> {
+        self.cpu.load_save_ram(data)
+    }
+
+    pub fn reset_save_ram_dirty(&mut self) {
+        self.cpu.reset_save_ram_dirty()
+    }
+
+    pub fn start_cdl(&mut self) -> Result<(), String> {
+        self.cpu.start_cdl()
+    }
+
+    pub fn load_cdl(&mut self, data: &[u8]) -> Result<(), String> {
+        self.cpu.load_cdl(data)
+    }
+
+    pub fn stop_cdl(&mut self) {
+        self.cpu.stop_cdl()
+    }
+
+    pub fn export_cdl(&self) -> Vec<u8> {
+        self.cpu.export_cdl()
+    }
+
+    pub fn add_cheat(&mut self, code: &str, name: &str) -> Result<usize, String> {
+        self.cpu.add_cheat(code, name)
+    }
+
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) -> Result<(), String> {
+        self.cpu.set_cheat_enabled(index, enabled)
+    }
+
+    pub fn remove_cheat(&mut self, index: usize) -> Result<(), String> {
+        self.cpu.remove_cheat(index)
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cpu.clear_cheats()
+    }
+
+    pub fn cheat_count(&self) -> usize {
+        self.cpu.cheat_count()
+    }
+
+    pub fn load_cht(&mut self, text: &str) -> Result<usize, String> {
+        self.cpu.load_cht(text)
+    }
+
+    pub fn export_cht(&self) -> String {
+        self.cpu.export_cht()
+    }
+
+    pub fn mem_ptr(&self) -> *const u8 {
+        self.cpu.mem_ptr()
+    }
+
+    pub fn load_pro(&mut self, program: Vec<u8>) {
+        self.cpu.load_pro(program)
+    }
+
+    pub fn mem_write(&mut self, addr: u16, data: u8) {
+        self.cpu.mem_write(addr, data)
+    }
+
+    pub fn reset_update(&mut self) {
+        self.cpu.reset_update()
+    }
+
+    pub fn soft_reset(&mut self) {
+        self.cpu.soft_reset()
+    }
+
+    pub fn enable_trace(&mut self) {
+        self.cpu.enable_trace()
+    }
+
+    pub fn disable_trace(&mut self) {
+        self.cpu.disable_trace()
+    }
+
+    pub fn take_trace(&mut self) -> String {
+        self.cpu.take_trace()
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> bool {
+        self.cpu.next()
+    }
+
+    pub fn run(&mut self) {
+        self.cpu.run()
+    }
+
+    pub fn run_frame(&mut self) -> FrameStatus {
+        self.cpu.run_frame().into()
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.cpu.add_breakpoint(addr)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.cpu.remove_breakpoint(addr)
+    }
+
+    pub fn add_conditional_breakpoint(&mut self, addr: u16, condition: &str) -> Result<(), String> {
+        self.cpu.add_conditional_breakpoint(addr, condition)
+    }
+
+    pub fn add_break_condition(&mut self, condition: &str) -> Result<(), String> {
+        self.cpu.add_break_condition(condition)
+    }
+
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, read: bool, write: bool) {
+        self.cpu.add_watchpoint(start, end, read, write)
+    }
+
+    pub fn remove_watchpoint(&mut self, start: u16, end: u16) {
+        self.cpu.remove_watchpoint(start, end)
+    }
+
+    pub fn add_label_breakpoint(&mut self, label: &str) -> Result<(), String> {
+        self.cpu.add_label_breakpoint(label)
+    }
+
+    pub fn clear_debugger(&mut self) {
+        self.cpu.clear_debugger()
+    }
+
+    pub fn load_nl(&mut self, text: &str, bank: Option<u8>) -> Result<usize, String> {
+        self.cpu.load_nl(text, bank)
+    }
+
+    pub fn load_dbg(&mut self, text: &str) -> Result<usize, String> {
+        self.cpu.load_dbg(text)
+    }
+
+    pub fn clear_symbols(&mut self) {
+        self.cpu.clear_symbols()
+    }
+
+    pub fn label_at(&self, addr: u16) -> Option<String> {
+        self.cpu.label_at(addr)
+    }
+
+    pub fn symbol_addr(&self, label: &str) -> Option<u16> {
+        self.cpu.symbol_addr(label)
+    }
+
+    pub fn disassemble(&self, addr: u16, count: u32) -> String {
+        self.cpu.disassemble(addr, count)
+    }
+
+    pub fn last_watch_hit(&self) -> Option<WatchHit> {
+        self.cpu.last_watch_hit().map(WatchHit::from)
+    }
+
+    pub fn step_into(&mut self) -> StopReason {
+        self.cpu.step_into().into()
+    }
+
+    pub fn step_over(&mut self, max_instructions: u32) -> StopReason {
+        self.cpu.step_over(max_instructions).into()
+    }
+
+    pub fn step_out(&mut self, max_instructions: u32) -> StopReason {
+        self.cpu.step_out(max_instructions).into()
+    }
+
+    pub fn run_to(&mut self, addr: u16, max_instructions: u32) -> StopReason {
+        self.cpu.run_to(addr, max_instructions).into()
+    }
+
+    pub fn resume(&mut self, max_instructions: u32) -> StopReason {
+        self.cpu.resume(max_instructions).into()
+    }
+
+    pub fn step_back(&mut self) -> bool {
+        self.cpu.step_back()
+    }
+
+    pub fn start_event_log(&mut self) {
+        self.cpu.start_event_log()
+    }
+
+    pub fn stop_event_log(&mut self) {
+        self.cpu.stop_event_log()
+    }
+
+    pub fn event_list(&self, current: bool) -> String {
+        self.cpu.event_list(current)
+    }
+
+    pub fn event_overlay_rgba(&self, current: bool) -> Vec<u8> {
+        self.cpu.event_overlay_rgba(current)
+    }
+
+    pub fn set_random_seed(&mut self, seed: u32) {
+        self.cpu.set_random_seed(seed)
+    }
+
+    pub fn key_press(&mut self, key: u8) {
+        self.cpu.key_press(key)
+    }
+
+    pub fn set_buttons(&mut self, pad: usize, buttons: u8) {
+        self.cpu.set_buttons(pad, buttons)
+    }
+
+    pub fn start_recording(&mut self, from_save_state: bool, hash_ram: bool) {
+        self.cpu.start_recording(from_save_state, hash_ram)
+    }
+
+    pub fn play_fm2(&mut self, text: &str) -> Result<(), String> {
+        self.cpu.play_fm2(text)
+    }
+
+    pub fn export_fm2(&self) -> Option<String> {
+        self.cpu.export_fm2()
+    }
+
+    pub fn close_movie(&mut self) {
+        self.cpu.close_movie()
+    }
+
+    pub fn movie_recording(&self) -> bool {
+        self.cpu.movie_recording()
+    }
+
+    pub fn movie_finished(&self) -> bool {
+        self.cpu.movie_finished()
+    }
+
+    pub fn movie_desync(&self) -> Option<u32> {
+        self.cpu.movie_desync()
+    }
+
+    pub fn set_ppu_view_scanline(&mut self, scanline: u16) -> Result<(), String> {
+        self.cpu.set_ppu_view_scanline(scanline)
+    }
+
+    pub fn clear_ppu_view_scanline(&mut self) {
+        self.cpu.clear_ppu_view_scanline()
+    }
+
+    pub fn nametables_rgba(&self) -> Vec<u8> {
+        self.cpu.nametables_rgba()
+    }
+
+    pub fn pattern_tables_rgba(&self, palette: u8) -> Result<Vec<u8>, String> {
+        self.cpu.pattern_tables_rgba(palette)
+    }
+
+    pub fn palette_rgba(&self) -> Vec<u8> {
+        self.cpu.palette_rgba()
+    }
+
+    pub fn sprite(&self, index: u8) -> Result<Sprite, String> {
+        self.cpu.sprite(index).map(Sprite::from)
+    }
+
+    pub fn start_profiler(&mut self) {
+        self.cpu.start_profiler()
+    }
+
+    pub fn stop_profiler(&mut self) {
+        self.cpu.stop_profiler()
+    }
+
+    pub fn profile_report(&self, last_frame: bool) -> String {
+        self.cpu.profile_report(last_frame)
+    }
+
+    pub fn profile_folded(&self, last_frame: bool) -> String {
+        self.cpu.profile_folded(last_frame)
+    }
+
+    pub fn ram_search_start(&mut self) {
+        self.cpu.ram_search_start()
+    }
+
+    pub fn ram_search_stop(&mut self) {
+        self.cpu.ram_search_stop()
+    }
+
+    pub fn ram_search_previous(
+        &mut self,
+        size: SearchSize,
+        format: SearchFormat,
+        compare: SearchCompare,
+    ) -> Result<usize, String> {
+        self.cpu
+            .ram_search_previous(size.into(), format.into(), compare.into())
+    }
+
+    pub fn ram_search_constant(
+        &mut self,
+        size: SearchSize,
+        format: SearchFormat,
+        compare: SearchCompare,
+        value: i32,
+    ) -> Result<usize, String> {
+        self.cpu
+            .ram_search_constant(size.into(), format.into(), compare.into(), value)
+    }
+
+    pub fn ram_search_candidates(&self) -> Vec<u16> {
+        self.cpu.ram_search_candidates()
+    }
+
+    pub fn ram_search_count(&self) -> usize {
+        self.cpu.ram_search_count()
+    }
+
+    pub fn ram_search_read(
+        &self,
+        addr: u16,
+        size: SearchSize,
+        format: SearchFormat,
+    ) -> Option<i32> {
+        self.cpu.ram_search_read(addr, size.into(), format.into())
+    }
+
+    pub fn ram_search_read_previous(
+        &self,
+        addr: u16,
+        size: SearchSize,
+        format: SearchFormat,
+    ) -> Option<i32> {
+        self.cpu
+            .ram_search_read_previous(addr, size.into(), format.into())
+    }
+
+    pub fn enable_rewind(&mut self, budget: usize, interval: u32) {
+        self.cpu.enable_rewind(budget, interval)
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.cpu.disable_rewind()
+    }
+
+    pub fn rewind(&mut self) -> bool {
+        self.cpu.rewind()
+    }
+
+    pub fn rewind_len(&self) -> usize {
+        self.cpu.rewind_len()
+    }
+
+    pub fn rewind_size(&self) -> usize {
+        self.cpu.rewind_size()
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.cpu.save_state()
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        self.cpu.load_state(data)
+    }
+
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.cpu.framebuffer()
+    }
+
+    /// The width of `framebuffer` rows: 32 on easy6502, 256 on the NES.
+    pub fn screen_width(&self) -> usize {
+        self.cpu.screen_size().0
+    }
+
+    pub fn screen_height(&self) -> usize {
+        self.cpu.screen_size().1
+    }
+
+    pub fn screenshot_png(&self, scale: u32) -> Result<Vec<u8>, String> {
+        self.cpu.screenshot_png(scale)
+    }
+}
+
+/// Copies a fieldless core enum into its JavaScript twin, or back, variant
+/// by variant.
+macro_rules! convert {
+    ($from:ty => $to:ty { $($variant:ident),* }) => {
+        impl From<$from> for $to {
+            fn from(value: $from) -> Self {
+                match value {
+                    $(<$from>::$variant => <$to>::$variant,)*
+                }
+            }
+        }
+    };
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Machine {
+    Easy6502,
+    Nes,
+    Bare,
+}
+
+convert!(machine::Machine => Machine { Easy6502, Nes, Bare });
+convert!(Machine => machine::Machine { Easy6502, Nes, Bare });
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameStatus {
+    Complete,
+    Brk,
+    Jam,
+    Breakpoint,
+    Watchpoint,
+}
+
+convert!(cpu::FrameStatus => FrameStatus { Complete, Brk, Jam, Breakpoint, Watchpoint });
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Step,
+    Breakpoint,
+    Watchpoint,
+    Brk,
+    Jam,
+    Limit,
+}
+
+convert!(debugger::StopReason => StopReason { Step, Breakpoint, Watchpoint, Brk, Jam, Limit });
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+convert!(debugger::Access => Access { Read, Write });
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: u16,
+    pub value: u8,
+    pub access: Access,
+}
+
+impl From<debugger::WatchHit> for WatchHit {
+    fn from(hit: debugger::WatchHit) -> Self {
+        WatchHit {
+            addr: hit.addr,
+            value: hit.value,
+            access: hit.access.into(),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sprite {
+    pub index: u8,
+    pub x: u8,
+    pub y: u8,
+    pub tile: u8,
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    pub pattern_table: u16,
+}
+
+impl From<ppu_view::Sprite> for Sprite {
+    fn from(sprite: ppu_view::Sprite) -> Self {
+        Sprite {
+            index: sprite.index,
+            x: sprite.x,
+            y: sprite.y,
+            tile: sprite.tile,
+            palette: sprite.palette,
+            behind_background: sprite.behind_background,
+            flip_horizontal: sprite.flip_horizontal,
+            flip_vertical: sprite.flip_vertical,
+            pattern_table: sprite.pattern_table,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchSize {
+    Byte,
+    Word,
+}
+
+convert!(SearchSize => ram_search::SearchSize { Byte, Word });
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchFormat {
+    Unsigned,
+    Signed,
+    Bcd,
+}
+
+convert!(SearchFormat => ram_search::SearchFormat { Unsigned, Signed, Bcd });
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchCompare {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+}
+
+convert!(SearchCompare => ram_search::SearchCompare { Equal, NotEqual, Greater, Less });
@@ -3,6 +3,7 @@ extern crate wasm_nes_emulator;
 use wasm_nes_emulator::cpu::CPU;
 
 extern crate wasm_bindgen_test;
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen_test::*;
 
 #[cfg(target_arch = "wasm32")]
@@ -562,6 +563,16 @@ mod bne {
 
         assert_eq!(cpu.register_a, 0x00);
     }
+
+    #[test]
+    fn bne_into_own_operand() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xD0, 0xFF]);
+        cpu.reset();
+        cpu.next();
+
+        assert_eq!(cpu.program_counter, 0x0601);
+    }
 }
 mod beq {
     use super::*;
@@ -667,6 +678,7 @@ mod flag_inst {
     }
 }
 mod bit {
+    #![allow(non_snake_case)]
     use super::*;
     #[test]
     fn bit_C2_FF() {
@@ -728,6 +740,17 @@ mod cmp {
 
         assert_eq!(cpu.register_a, 0x00);
     }
+
+    #[test]
+    fn cmp_negative_flag() {
+        let mut cpu = CPU::new();
+        // $05 - $03 is positive, $03 - $05 is $FE
+        cpu.load_and_run(vec![0xa9, 0x05, 0xc9, 0x03, 0x00]);
+        assert_eq!(cpu.status & 0b1000_0000, 0);
+
+        cpu.load_and_run(vec![0xa9, 0x03, 0xc9, 0x05, 0x00]);
+        assert_eq!(cpu.status & 0b1000_0000, 0b1000_0000);
+    }
 }
 mod cpx {
     use super::*;
@@ -883,6 +906,17 @@ mod asl {
 
         assert_eq!(cpu.register_a, 0x10)
     }
+
+    #[test]
+    fn asl_mem_zero() {
+        let mut cpu = CPU::new();
+
+        // LDA #$80; STA $10; ASL $10
+        cpu.load_and_run(vec![0xa9, 0x80, 0x85, 0x10, 0x06, 0x10, 0x00]);
+
+        assert_eq!(cpu.mem_read(0x10), 0x00);
+        assert_eq!(cpu.status, 0b0000_0011);
+    }
 }
 mod jmp {
     use super::*;
@@ -897,6 +931,16 @@ mod jmp {
 
         assert_eq!(cpu.register_a, 0x02);
     }
+
+    #[test]
+    fn jmp_to_own_operand() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0x4c, 0x01, 0x06]);
+        cpu.reset();
+        cpu.next();
+
+        assert_eq!(cpu.program_counter, 0x0601);
+    }
 }
 mod txs {
     use super::*;
@@ -933,8 +977,8 @@ mod pha {
 
         cpu.load_and_run(vec![0xa9, 0x05, 0x48, 0x48, 0x00]);
 
-        assert_eq!(cpu.mem_read(0x01ff), 0x05);
-        assert_eq!(cpu.mem_read(0x01fe), 0x05);
+        assert_eq!(cpu.mem_read(0x01fd), 0x05);
+        assert_eq!(cpu.mem_read(0x01fc), 0x05);
     }
 }
 mod pla {
@@ -947,7 +991,7 @@ mod pla {
         cpu.load_and_run(vec![0xa9, 0x05, 0x48, 0xa9, 0x00, 0x68, 0x00]);
 
         assert_eq!(cpu.register_a, 0x05);
-        assert_eq!(cpu.stack_ptr, 0x00);
+        assert_eq!(cpu.stack_ptr, 0xfd);
     }
 }
 mod php {
@@ -958,7 +1002,8 @@ mod php {
 
         cpu.load_and_run(vec![0xa9, 0x7f, 0x69, 0x01, 0x08, 0x00]);
 
-        assert_eq!(cpu.mem_read(0x01ff), 0xc0);
+        // B and bit 5 are set in the pushed copy
+        assert_eq!(cpu.mem_read(0x01fd), 0xf0);
     }
 }
 mod plp {
@@ -969,7 +1014,8 @@ mod plp {
 
         cpu.load_and_run(vec![0xa9, 0x7f, 0x69, 0x01, 0x08, 0xB8, 0x28, 0x00]);
 
-        assert_eq!(cpu.status, 0xc0);
+        // B is dropped and bit 5 is always set when pulled
+        assert_eq!(cpu.status, 0xe0);
     }
 }
 mod jsr {
@@ -981,13 +1027,14 @@ mod jsr {
         cpu.load(vec![0x20, 0x03, 0x06, 0x00]);
         cpu.reset();
         cpu.next(); //jsr
-        assert_eq!(cpu.mem_read(0x1ff), 0x06);
-        assert_eq!(cpu.mem_read(0x1fe), 0x02);
+        assert_eq!(cpu.mem_read(0x1fd), 0x06);
+        assert_eq!(cpu.mem_read(0x1fc), 0x02);
         assert_eq!(cpu.program_counter, 0x0603);
     }
 }
 mod rts {
     use super::*;
+    #[allow(clippy::bool_assert_comparison)]
     #[test]
     fn rts() {
         let mut cpu = CPU::new();
@@ -1033,6 +1080,17 @@ mod rol {
         assert_eq!(cpu.mem_read(0x0001), 0xfe);
         assert_eq!(cpu.status, 0b1000_0001);
     }
+
+    #[test]
+    fn rol_carry_in() {
+        let mut cpu = CPU::new();
+
+        // SEC; LDA #$40; ROL A
+        cpu.load_and_run(vec![0x38, 0xa9, 0x40, 0x2a, 0x00]);
+
+        assert_eq!(cpu.register_a, 0x81);
+        assert_eq!(cpu.status, 0b1000_0000);
+    }
 }
 
 mod ror {
@@ -1066,6 +1124,17 @@ mod ror {
         assert_eq!(cpu.mem_read(0x0001), 0x7f);
         assert_eq!(cpu.status, 0b0000_0001);
     }
+
+    #[test]
+    fn ror_carry_in() {
+        let mut cpu = CPU::new();
+
+        // SEC; LDA #$02; ROR A
+        cpu.load_and_run(vec![0x38, 0xa9, 0x02, 0x6a, 0x00]);
+
+        assert_eq!(cpu.register_a, 0x81);
+        assert_eq!(cpu.status, 0b1000_0000);
+    }
 }
 mod lsr {
     use super::*;
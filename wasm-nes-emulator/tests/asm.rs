@@ -0,0 +1,154 @@
+#[macro_use]
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::asm::assemble;
+use wasm_nes_emulator::cpu::CPU;
+
+mod common;
+use common::SNAKE;
+
+#[test]
+fn assembles_snake() {
+    let assembly = assemble(include_str!("../www/snake.asm")).unwrap();
+    assert_eq!(assembly.origin, 0x0600);
+    assert_eq!(assembly.bytes, SNAKE.to_vec());
+    assert_eq!(assembly.labels["loop"], 0x0638);
+    assert_eq!(assembly.labels["gameOver"], 0x0735);
+    assert!(!assembly.labels.contains_key("sysRandom"));
+}
+
+#[test]
+fn addressing_modes() {
+    let program = asm!(
+        "  lda #$01",
+        "  lda $10",
+        "  lda $10,x",
+        "  ldx $10,y",
+        "  lda $1234",
+        "  lda $1234,x",
+        "  lda $10,y",
+        "  lda ($10,x)",
+        "  lda ($10),y",
+        "  asl",
+        "  asl a",
+        "  jmp ($fffc)",
+        "  brk",
+    );
+    assert_eq!(
+        program,
+        vec![
+            0xa9, 0x01, 0xa5, 0x10, 0xb5, 0x10, 0xb6, 0x10, 0xad, 0x34, 0x12, 0xbd, 0x34, 0x12,
+            // no zero page,Y form for LDA
+            0xb9, 0x10, 0x00, 0xa1, 0x10, 0xb1, 0x10, 0x0a, 0x0a, 0x6c, 0xfc, 0xff, 0x00,
+        ]
+    );
+}
+
+#[test]
+fn labels_and_branches() {
+    let program = asm!(
+        "start: dex",
+        "  bne start",
+        "  beq done",
+        "  jsr done",
+        "done:",
+        "  rts",
+    );
+    assert_eq!(
+        program,
+        vec![0xca, 0xd0, 0xfd, 0xf0, 0x03, 0x20, 0x08, 0x06, 0x60]
+    );
+}
+
+#[test]
+fn forward_references_are_absolute() {
+    let program = asm!("  lda later", "later = $10", "  lda later");
+    assert_eq!(program, vec![0xad, 0x10, 0x00, 0xa5, 0x10]);
+}
+
+#[test]
+fn expressions_and_directives() {
+    let assembly = assemble(
+        "
+        base = $0300
+          .org $c000
+        table:
+          .byte 1, $02, %11, 'A', \"hi\", <base, >base, 2*3+1
+          .word table, base + $10
+          lda #(base >> 8) | 1
+          ldx #-1
+        here:
+          jmp *
+        ",
+    )
+    .unwrap();
+    assert_eq!(assembly.origin, 0xc000);
+    assert_eq!(
+        assembly.bytes,
+        vec![
+            1, 2, 3, b'A', b'h', b'i', 0x00, 0x03, 7, 0x00, 0xc0, 0x10, 0x03, 0xa9, 0x03, 0xa2,
+            0xff, 0x4c, 0x11, 0xc0,
+        ]
+    );
+    assert_eq!(assembly.labels["here"], 0xc011);
+}
+
+#[test]
+fn org_gaps_are_zero_filled() {
+    let assembly = assemble(".org $10\n.byte 1\n.org $14\n.byte 2").unwrap();
+    assert_eq!(assembly.origin, 0x10);
+    assert_eq!(assembly.bytes, vec![1, 0, 0, 0, 2]);
+}
+
+#[test]
+fn errors_name_the_line() {
+    let error = |src: &str| assemble(src).unwrap_err();
+    assert_eq!(error("nop\nfoo"), "line 2: unknown instruction 'FOO'");
+    assert_eq!(error("  jmp nowhere"), "line 1: unknown label 'nowhere'");
+    assert!(error("a: nop\na: nop").starts_with("line 2:"));
+    assert!(error("lda #$100").contains("does not fit in a byte"));
+    assert!(error("stx $1234,x").contains("STX"));
+
+    assert!(error("start:\n.org $0700\nbne start").contains("bytes away"));
+}
+
+#[test]
+fn load_asm_runs_the_program() {
+    let mut cpu = CPU::new();
+    cpu.load_asm(
+        "
+          ldx #0
+        loop:
+          inx
+          cpx #5
+          bne loop
+          brk
+        ",
+    )
+    .unwrap();
+    assert_eq!(cpu.symbol_addr("loop"), Some(0x0602));
+    cpu.run();
+    assert_eq!(cpu.register_x, 5);
+
+    assert!(cpu.load_asm("lda").is_err());
+}
+
+#[test]
+fn load_asm_keeps_the_programs_own_vectors() {
+    let mut cpu = CPU::new();
+    cpu.load_asm(
+        "
+          .org $8000
+          brk
+        start:
+          inx
+          brk
+          .org $fffa
+          .word 0, start, $1234
+        ",
+    )
+    .unwrap();
+    assert_eq!(cpu.program_counter, 0x8001);
+    assert_eq!(cpu.mem_read_u16(0xfffe), 0x1234);
+    cpu.run();
+    assert_eq!(cpu.register_x, 1);
+}
@@ -0,0 +1,156 @@
+#![cfg(feature = "std")]
+extern crate wasm_nes_emulator;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use wasm_nes_emulator::capture::{samples_for_frames, WavWriter, Y4mWriter, SAMPLE_RATE};
+use wasm_nes_emulator::cpu::CPU;
+
+mod common;
+use common::ines;
+
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("capture-{}-{}", std::process::id(), name))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+// 0600: INC $0200
+// 0603: JMP $0600
+fn blinking_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xee, 0x00, 0x02, 0x4c, 0x00, 0x06]);
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn wav_header_has_the_sizes() {
+    let mut wav = WavWriter::new(Cursor::new(vec![])).unwrap();
+    wav.write_samples(&[0, 1, -1]).unwrap();
+    let data = wav.finish().unwrap().into_inner();
+
+    assert_eq!(&data[0..4], b"RIFF");
+    assert_eq!(u32_at(&data, 4) as usize, data.len() - 8);
+    assert_eq!(&data[8..16], b"WAVEfmt ");
+    assert_eq!(u32_at(&data, 24), SAMPLE_RATE);
+    assert_eq!(&data[36..40], b"data");
+    assert_eq!(u32_at(&data, 40), 6);
+    assert_eq!(&data[44..], &[0, 0, 1, 0, 0xff, 0xff]);
+}
+
+#[test]
+fn y4m_frames_are_full_range_420() {
+    let mut y4m = Y4mWriter::new(vec![], 2, 2).unwrap();
+    y4m.write_frame(&[255; 12]).unwrap();
+    y4m.write_frame(&[255, 0, 0, 255, 0, 0, 255, 0, 0, 255, 0, 0])
+        .unwrap();
+    let data = y4m.finish().unwrap();
+
+    let header = b"YUV4MPEG2 W2 H2 F5369319:89342 Ip A1:1 C420jpeg\n";
+    assert_eq!(&data[..header.len()], &header[..]);
+    let frames = &data[header.len()..];
+    assert_eq!(
+        frames,
+        b"FRAME\n\xff\xff\xff\xff\x80\x80FRAME\n\x4c\x4c\x4c\x4c\x55\xff"
+    );
+}
+
+#[test]
+fn audio_stays_in_step_with_frames() {
+    assert_eq!(samples_for_frames(0), 0);
+    // a little under 44100 / 60
+    assert_eq!(samples_for_frames(1), 733);
+    assert_eq!(samples_for_frames(60), 44027);
+}
+
+#[test]
+fn captures_one_video_frame_and_its_audio_per_frame() {
+    let video = temp("blink.y4m");
+    let audio = temp("blink.wav");
+    let mut cpu = blinking_cpu();
+    cpu.start_capture(
+        Some(Box::new(File::create(&video).unwrap())),
+        Some(Box::new(File::create(&audio).unwrap())),
+        2,
+    )
+    .unwrap();
+
+    while cpu.frame() < 5 {
+        cpu.next();
+    }
+    assert_eq!(cpu.capture().unwrap().frames(), 5);
+    assert_eq!(cpu.stop_capture(), Ok(5));
+    assert!(cpu.capture().is_none());
+
+    let data = std::fs::read(&video).unwrap();
+    let header_len = data.iter().position(|&b| b == b'\n').unwrap() + 1;
+    assert!(data.starts_with(b"YUV4MPEG2 W64 H64 "));
+    let frame_len = 6 + 64 * 64 * 3 / 2;
+    assert_eq!(data.len(), header_len + 5 * frame_len);
+
+    let wav = std::fs::read(&audio).unwrap();
+    let samples = samples_for_frames(5) as usize;
+    assert_eq!(wav.len(), 44 + samples * 2);
+    assert_eq!(u32_at(&wav, 40) as usize, samples * 2);
+    assert!(wav[44..].iter().all(|&b| b == 0));
+
+    std::fs::remove_file(video).unwrap();
+    std::fs::remove_file(audio).unwrap();
+}
+
+#[test]
+fn capture_needs_a_sensible_scale() {
+    let mut cpu = blinking_cpu();
+    assert!(cpu.start_capture(None, None, 0).is_err());
+    assert_eq!(cpu.stop_capture(), Ok(0));
+}
+
+#[test]
+fn nes_video_is_the_full_picture() {
+    let video = temp("nes.y4m");
+    // 8000: JMP $8000
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &[0x4c, 0x00, 0x80])).unwrap();
+    cpu.start_capture(Some(Box::new(File::create(&video).unwrap())), None, 1)
+        .unwrap();
+
+    while cpu.frame() < 2 {
+        cpu.next();
+    }
+    assert_eq!(cpu.stop_capture(), Ok(2));
+
+    let data = std::fs::read(&video).unwrap();
+    let header_len = data.iter().position(|&b| b == b'\n').unwrap() + 1;
+    assert!(data.starts_with(b"YUV4MPEG2 W256 H240 "));
+    assert_eq!(data.len(), header_len + 2 * (6 + 256 * 240 * 3 / 2));
+
+    std::fs::remove_file(video).unwrap();
+}
+
+#[test]
+fn nes_audio_is_refused_up_front() {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &[0x4c, 0x00, 0x80])).unwrap();
+    let audio = Box::new(Cursor::new(vec![]));
+    assert_eq!(
+        cpu.start_capture(None, Some(audio), 1),
+        Err("NES audio cannot be captured until there is an APU".to_string())
+    );
+    assert!(cpu.capture().is_none());
+}
+
+#[test]
+fn capture_stops_when_the_picture_changes_size() {
+    let mut cpu = blinking_cpu();
+    cpu.start_capture(Some(Box::new(vec![])), None, 1).unwrap();
+    cpu.load_rom(&ines(0, &[0x4c, 0x00, 0x80])).unwrap();
+
+    while cpu.frame() < 2 {
+        cpu.next();
+    }
+    assert_eq!(cpu.stop_capture(), Err("frame is not 32x32".to_string()));
+}
@@ -0,0 +1,158 @@
+extern crate wasm_nes_emulator;
+#[cfg(feature = "std")]
+use wasm_nes_emulator::cartridge::sav_path;
+use wasm_nes_emulator::cartridge::{Mirroring, Rom};
+use wasm_nes_emulator::cpu::CPU;
+
+mod common;
+use common::ines;
+
+// LDA #$42; STA $6000; BRK
+const SAVE_GAME: [u8; 6] = [0xa9, 0x42, 0x8d, 0x00, 0x60, 0x00];
+
+#[test]
+fn parses_header() {
+    let rom = Rom::new(&ines(0b0000_0011, &[])).unwrap();
+    assert!(rom.battery);
+    assert_eq!(rom.mirroring, Mirroring::Vertical);
+    assert_eq!(rom.mapper, 0);
+    assert_eq!(rom.prg_rom.len(), 0x4000);
+    assert_eq!(rom.chr_rom.len(), 0x2000);
+
+    let rom = Rom::new(&ines(0b0001_0000, &[])).unwrap();
+    assert!(!rom.battery);
+    assert_eq!(rom.mirroring, Mirroring::Horizontal);
+    assert_eq!(rom.mapper, 1);
+}
+
+#[test]
+fn rejects_bad_images() {
+    assert!(Rom::new(b"not a rom").is_err());
+
+    let mut raw = ines(0, &[]);
+    raw.truncate(0x1000);
+    assert!(Rom::new(&raw).is_err());
+
+    let mut cpu = CPU::new();
+    assert!(cpu.load_rom(&ines(0b0001_0010, &[])).is_err());
+}
+
+#[test]
+fn battery_ram_sets_dirty_flag() {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0b10, &SAVE_GAME)).unwrap();
+    assert!(cpu.has_battery());
+    assert_eq!(cpu.program_counter, 0x8000);
+    assert!(!cpu.save_ram_dirty);
+
+    cpu.run();
+    assert!(cpu.save_ram_dirty);
+    assert_eq!(cpu.save_ram()[0], 0x42);
+    assert_eq!(cpu.save_ram().len(), 0x2000);
+
+    cpu.reset_save_ram_dirty();
+    assert!(!cpu.save_ram_dirty);
+}
+
+#[test]
+fn ram_without_battery_is_never_dirty() {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &SAVE_GAME)).unwrap();
+    cpu.run();
+    assert!(!cpu.has_battery());
+    assert!(!cpu.save_ram_dirty);
+    assert_eq!(cpu.mem_read(0x6000), 0x42);
+}
+
+#[test]
+fn save_ram_import() {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0b10, &SAVE_GAME)).unwrap();
+    cpu.load_save_ram(&[1, 2, 3]).unwrap();
+    assert_eq!(cpu.mem_read(0x6001), 2);
+    assert!(!cpu.save_ram_dirty);
+
+    assert!(cpu.load_save_ram(&vec![0; 0x2001]).is_err());
+}
+
+#[test]
+fn rom_is_read_only_and_mirrored() {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &SAVE_GAME)).unwrap();
+    cpu.mem_write(0x8000, 0xff);
+    assert_eq!(cpu.mem_read(0x8000), 0xa9);
+    assert_eq!(cpu.mem_read(0xc000), 0xa9);
+    assert_eq!(cpu.prg_bank(0xc000), Some(0));
+    assert_eq!(cpu.prg_bank(0x6000), None);
+}
+
+#[test]
+fn loading_a_rom_powers_on_afresh() {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &SAVE_GAME)).unwrap();
+    cpu.mem_write(0x0010, 0x55);
+    cpu.set_buttons(0, 0xff);
+    cpu.add_cheat("0011:07", "lives").unwrap();
+
+    cpu.load_rom(&ines(0, &SAVE_GAME)).unwrap();
+    assert_eq!(cpu.mem_read(0x0010), 0);
+    assert_eq!(cpu.cheat_count(), 0);
+    cpu.mem_write(0x4016, 1);
+    cpu.mem_write(0x4016, 0);
+    assert_eq!(cpu.mem_read(0x4016) & 1, 0);
+}
+
+#[test]
+fn loading_a_rom_restarts_the_tools() {
+    // 8000: INC $10
+    // 8002: JMP $8000
+    let game = ines(0, &[0xe6, 0x10, 0x4c, 0x00, 0x80]);
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &SAVE_GAME)).unwrap();
+    cpu.enable_rewind(1 << 20, 1);
+    cpu.start_recording(false, false);
+    cpu.ram_search_start();
+    cpu.start_event_log();
+    cpu.start_profiler();
+    for _ in 0..3 {
+        cpu.run_frame();
+    }
+
+    cpu.load_rom(&game).unwrap();
+    assert_eq!(cpu.rewind_len(), 1);
+    assert!(cpu.export_fm2().is_none());
+    assert_eq!(cpu.ram_search_count(), 0);
+    assert_eq!(cpu.event_list(true), "");
+    assert_eq!(cpu.profile_report(false), CPU::new().profile_report(false));
+
+    // rewinding stays inside the new game
+    cpu.run_frame();
+    assert!(cpu.profile_report(false).lines().count() > 1);
+    assert!(cpu.rewind());
+    assert_eq!(cpu.mem_peek(0x8000), 0xe6);
+    assert_eq!(cpu.frame(), 0);
+    assert!(!cpu.rewind());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn sav_file_next_to_rom() {
+    let dir = std::env::temp_dir().join(format!("nes-sav-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom_path = dir.join("game.nes");
+    std::fs::write(&rom_path, ines(0b10, &SAVE_GAME)).unwrap();
+    assert_eq!(sav_path(&rom_path), dir.join("game.sav"));
+
+    let mut cpu = CPU::new();
+    cpu.load_rom_file(&rom_path).unwrap();
+    assert!(!cpu.flush_save_ram(&rom_path).unwrap());
+    cpu.run();
+    assert!(cpu.flush_save_ram(&rom_path).unwrap());
+    assert!(!cpu.flush_save_ram(&rom_path).unwrap());
+
+    let mut cpu = CPU::new();
+    cpu.load_rom_file(&rom_path).unwrap();
+    assert_eq!(cpu.mem_read(0x6000), 0x42);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,153 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::asm::assemble;
+use wasm_nes_emulator::cdl::{CODE, DATA, DRAWN, INDIRECT_CODE, INDIRECT_DATA, PCM, READ};
+use wasm_nes_emulator::cpu::CPU;
+
+mod common;
+use common::ines;
+
+fn nes(source: &str) -> CPU {
+    let assembly = assemble(&format!(".org $8000\n{}", source)).unwrap();
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &assembly.bytes)).unwrap();
+    cpu.start_cdl().unwrap();
+    cpu
+}
+
+const PROGRAM: &str = "
+    lda table
+    lda #<pointed
+    sta $00
+    lda #>pointed
+    sta $01
+    ldy #1
+    lda ($00),y
+    jmp (vector)
+target:
+    brk
+vector:
+    .word target + $4000
+table:
+    .byte 1, 2
+pointed:
+    .byte 3, 4
+";
+
+#[test]
+fn marks_code_and_data() {
+    let mut cpu = nes(PROGRAM);
+    cpu.run();
+    let labels = assemble(&format!(".org $8000\n{}", PROGRAM))
+        .unwrap()
+        .labels;
+    let offset = |label: &str| (labels[label] - 0x8000) as usize;
+    let prg = cpu.code_data_log().unwrap().prg();
+
+    assert_eq!(prg[0], CODE);
+    assert_eq!(prg[2], CODE);
+    // the jump lands in the $C000 mirror: window 2
+    assert_eq!(prg[offset("target")], CODE | INDIRECT_CODE | 0b1000);
+    assert_eq!(prg[offset("vector")], DATA);
+    assert_eq!(prg[offset("vector") + 1], DATA);
+    assert_eq!(prg[offset("table")], DATA);
+    assert_eq!(prg[offset("table") + 1], 0);
+    assert_eq!(prg[offset("pointed")], 0);
+    assert_eq!(prg[offset("pointed") + 1], DATA | INDIRECT_DATA);
+    assert_eq!(prg[offset("pointed") + 2], 0);
+
+    assert_eq!(cpu.cdl_flags(0x8000), Some(CODE));
+    assert_eq!(cpu.cdl_flags(0x0000), None);
+}
+
+#[test]
+fn marks_dmc_samples() {
+    // a 17 byte sample at $C040
+    let mut cpu = nes("
+        lda #1
+        sta $4012
+        sta $4013
+        lda #$10
+        sta $4015
+        brk
+    ");
+    cpu.run();
+    let prg = cpu.code_data_log().unwrap().prg();
+    assert_eq!(prg[0x3f], 0);
+    assert!(prg[0x40..0x51].iter().all(|&flags| flags == PCM | 0b1000));
+    assert_eq!(prg[0x51], 0);
+}
+
+#[test]
+fn marks_chr_read_and_drawn() {
+    let mut cpu = nes("
+        lda #$00
+        sta $2006
+        lda #$10
+        sta $2006
+        lda $2007
+        ; tile 1 at the top left of the screen
+        lda #$20
+        sta $2006
+        lda #$00
+        sta $2006
+        lda #1
+        sta $2007
+        ; sprite 0 shows tile 3
+        lda #0
+        sta $2003
+        lda #$10
+        sta $2004
+        lda #3
+        sta $2004
+        lda #0
+        sta $2000
+        sta $2005
+        sta $2005
+        lda #$18
+        sta $2001
+    loop:
+        jmp loop
+    ");
+    cpu.run_frame();
+    let chr = cpu.code_data_log().unwrap().chr();
+    assert_eq!(chr.len(), 0x2000);
+    assert_eq!(chr[0x10], READ | DRAWN);
+    assert_eq!(chr[0x11], DRAWN);
+    // tile 0 fills the rest of the screen and the other sprites
+    assert_eq!(chr[0x00], DRAWN);
+    assert_eq!(chr[0x20], 0);
+    assert_eq!(chr[0x30], DRAWN);
+    assert_eq!(chr[0x3f], DRAWN);
+    assert_eq!(chr[0x40], 0);
+}
+
+#[test]
+fn exports_and_reloads_cdl_files() {
+    let mut cpu = nes(PROGRAM);
+    cpu.run();
+    let cdl = cpu.export_cdl();
+    assert_eq!(cdl.len(), 0x4000 + 0x2000);
+
+    let mut next = nes(PROGRAM);
+    next.load_cdl(&cdl).unwrap();
+    assert_eq!(next.export_cdl(), cdl);
+    assert!(next.load_cdl(&cdl[1..]).is_err());
+    assert!(next.code_data_log().is_none());
+
+    next.stop_cdl();
+    assert!(next.export_cdl().is_empty());
+    assert!(CPU::new().start_cdl().is_err());
+}
+
+#[test]
+fn disassembles_data_as_bytes() {
+    let mut cpu = nes(PROGRAM);
+    cpu.run();
+    // target, vector and the first byte of table
+    let lines = cpu.disassemble(0x8012, 4);
+    let lines: Vec<&str> = lines.lines().collect();
+    assert!(lines[0].ends_with("BRK"), "{}", lines[0]);
+    assert_eq!(lines[1], "8013  12        .byte $12");
+    assert_eq!(lines[2], "8014  C0        .byte $C0");
+    assert_eq!(lines[3], "8015  01        .byte $01");
+}
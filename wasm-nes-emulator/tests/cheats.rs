@@ -0,0 +1,109 @@
+#[macro_use]
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::cheats::{decode_game_genie, parse_cht, Cheat, CheatKind};
+use wasm_nes_emulator::cpu::CPU;
+
+mod common;
+use common::ines;
+
+#[test]
+fn decodes_game_genie_codes() {
+    // Super Mario Bros. infinite lives
+    assert_eq!(decode_game_genie("SXIOPO"), Ok((0x91d9, 0xad, None)));
+    assert_eq!(decode_game_genie("sxiopo"), Ok((0x91d9, 0xad, None)));
+    assert_eq!(
+        decode_game_genie("ZEXPYGLA"),
+        Ok((0x94a7, 0x02, Some(0x03)))
+    );
+
+    assert!(decode_game_genie("SXIOP").is_err());
+    assert!(decode_game_genie("SXIOPB").is_err());
+}
+
+#[test]
+fn parses_raw_codes() {
+    let freeze = Cheat::parse("0075:09", "lives").unwrap();
+    assert_eq!(freeze.kind, CheatKind::Freeze);
+    assert_eq!(
+        (freeze.addr, freeze.value, freeze.compare),
+        (0x75, 0x09, None)
+    );
+    assert!(freeze.enabled);
+
+    let patch = Cheat::parse("C123?EA:60", "").unwrap();
+    assert_eq!(patch.kind, CheatKind::Patch);
+    assert_eq!(
+        (patch.addr, patch.value, patch.compare),
+        (0xc123, 0x60, Some(0xea))
+    );
+
+    assert!(Cheat::parse("10000:01", "").is_err());
+    assert!(Cheat::parse("0075:100", "").is_err());
+    assert!(Cheat::parse("0075:", "").is_err());
+    assert!(Cheat::parse(":01", "").is_err());
+    assert!(Cheat::parse("0075?:01", "").is_err());
+}
+
+#[test]
+fn patches_cartridge_reads() {
+    // 8000: LDA #$01
+    // 8002: BRK
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &[0xa9, 0x01, 0x00])).unwrap();
+    cpu.add_cheat("8001?02:05", "wrong compare").unwrap();
+    let index = cpu.add_cheat("8001?01:07", "seven").unwrap();
+    cpu.run();
+    assert_eq!(cpu.register_a, 0x07);
+
+    cpu.set_cheat_enabled(index, false).unwrap();
+    cpu.reset();
+    cpu.run();
+    assert_eq!(cpu.register_a, 0x01);
+}
+
+#[test]
+fn freezes_ram_every_frame() {
+    let mut cpu = CPU::new();
+    cpu.load(asm!("loop:", "  dec $10", "  jmp loop"));
+    cpu.reset();
+    cpu.add_cheat("0010:63", "").unwrap();
+
+    cpu.run_frame();
+    assert_eq!(cpu.mem_peek(0x10), 0x63);
+    cpu.next();
+    assert_eq!(cpu.mem_peek(0x10), 0x62);
+    cpu.run_frame();
+    assert_eq!(cpu.mem_peek(0x10), 0x63);
+
+    cpu.remove_cheat(0).unwrap();
+    cpu.run_frame();
+    assert_ne!(cpu.mem_peek(0x10), 0x63);
+    assert!(cpu.remove_cheat(0).is_err());
+}
+
+#[test]
+fn reads_and_writes_cht_files() {
+    let cht = "0075:09:Infinite lives\r\n\
+               S:91D9:AD:Disabled patch\n\
+               SC94A7:02:03:Compare: with colon\n";
+    let cheats = parse_cht(cht).unwrap();
+    assert_eq!(cheats.len(), 3);
+    assert_eq!(cheats[0].kind, CheatKind::Freeze);
+    assert_eq!(cheats[0].name, "Infinite lives");
+    assert!(!cheats[1].enabled);
+    assert_eq!(cheats[1].kind, CheatKind::Patch);
+    assert_eq!(cheats[2].compare, Some(0x03));
+    assert_eq!(cheats[2].name, "Compare: with colon");
+
+    let mut cpu = CPU::new();
+    assert_eq!(cpu.load_cht(cht), Ok(3));
+    assert_eq!(cpu.cheat_count(), 3);
+    assert_eq!(
+        cpu.export_cht(),
+        "0075:09:Infinite lives\nS:91D9:AD:Disabled patch\nSC94A7:02:03:Compare: with colon\n"
+    );
+
+    assert!(parse_cht("0075:zz:bad").unwrap_err().starts_with("line 1"));
+    cpu.clear_cheats();
+    assert!(cpu.cheats().is_empty());
+}
@@ -0,0 +1,78 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use wasm_nes_emulator::cpu::CPU;
+
+/// The easy6502 snake game that `www/index.js` runs.
+pub const SNAKE: [u8; 309] = [
+    0x20, 0x06, 0x06, 0x20, 0x38, 0x06, 0x20, 0x0d, 0x06, 0x20, 0x2a, 0x06, 0x60, 0xa9, 0x02, 0x85,
+    0x02, 0xa9, 0x04, 0x85, 0x03, 0xa9, 0x11, 0x85, 0x10, 0xa9, 0x10, 0x85, 0x12, 0xa9, 0x0f, 0x85,
+    0x14, 0xa9, 0x04, 0x85, 0x11, 0x85, 0x13, 0x85, 0x15, 0x60, 0xa5, 0xfe, 0x85, 0x00, 0xa5, 0xfe,
+    0x29, 0x03, 0x18, 0x69, 0x02, 0x85, 0x01, 0x60, 0x20, 0x4d, 0x06, 0x20, 0x8d, 0x06, 0x20, 0xc3,
+    0x06, 0x20, 0x19, 0x07, 0x20, 0x20, 0x07, 0x20, 0x2d, 0x07, 0x4c, 0x38, 0x06, 0xa5, 0xff, 0xc9,
+    0x77, 0xf0, 0x0d, 0xc9, 0x64, 0xf0, 0x14, 0xc9, 0x73, 0xf0, 0x1b, 0xc9, 0x61, 0xf0, 0x22, 0x60,
+    0xa9, 0x04, 0x24, 0x02, 0xd0, 0x26, 0xa9, 0x01, 0x85, 0x02, 0x60, 0xa9, 0x08, 0x24, 0x02, 0xd0,
+    0x1b, 0xa9, 0x02, 0x85, 0x02, 0x60, 0xa9, 0x01, 0x24, 0x02, 0xd0, 0x10, 0xa9, 0x04, 0x85, 0x02,
+    0x60, 0xa9, 0x02, 0x24, 0x02, 0xd0, 0x05, 0xa9, 0x08, 0x85, 0x02, 0x60, 0x60, 0x20, 0x94, 0x06,
+    0x20, 0xa8, 0x06, 0x60, 0xa5, 0x00, 0xc5, 0x10, 0xd0, 0x0d, 0xa5, 0x01, 0xc5, 0x11, 0xd0, 0x07,
+    0xe6, 0x03, 0xe6, 0x03, 0x20, 0x2a, 0x06, 0x60, 0xa2, 0x02, 0xb5, 0x10, 0xc5, 0x10, 0xd0, 0x06,
+    0xb5, 0x11, 0xc5, 0x11, 0xf0, 0x09, 0xe8, 0xe8, 0xe4, 0x03, 0xf0, 0x06, 0x4c, 0xaa, 0x06, 0x4c,
+    0x35, 0x07, 0x60, 0xa6, 0x03, 0xca, 0x8a, 0xb5, 0x10, 0x95, 0x12, 0xca, 0x10, 0xf9, 0xa5, 0x02,
+    0x4a, 0xb0, 0x09, 0x4a, 0xb0, 0x19, 0x4a, 0xb0, 0x1f, 0x4a, 0xb0, 0x2f, 0xa5, 0x10, 0x38, 0xe9,
+    0x20, 0x85, 0x10, 0x90, 0x01, 0x60, 0xc6, 0x11, 0xa9, 0x01, 0xc5, 0x11, 0xf0, 0x28, 0x60, 0xe6,
+    0x10, 0xa9, 0x1f, 0x24, 0x10, 0xf0, 0x1f, 0x60, 0xa5, 0x10, 0x18, 0x69, 0x20, 0x85, 0x10, 0xb0,
+    0x01, 0x60, 0xe6, 0x11, 0xa9, 0x06, 0xc5, 0x11, 0xf0, 0x0c, 0x60, 0xc6, 0x10, 0xa5, 0x10, 0x29,
+    0x1f, 0xc9, 0x1f, 0xf0, 0x01, 0x60, 0x4c, 0x35, 0x07, 0xa0, 0x00, 0xa5, 0xfe, 0x91, 0x00, 0x60,
+    0xa6, 0x03, 0xa9, 0x00, 0x81, 0x10, 0xa2, 0x00, 0xa9, 0x01, 0x81, 0x10, 0x60, 0xa2, 0x00, 0xea,
+    0xea, 0xca, 0xd0, 0xfb, 0x60,
+];
+
+/// Stands in for the random byte the web page pokes into $FE.
+pub struct Rng(pub u32);
+
+impl Rng {
+    pub fn next_byte(&mut self) -> u8 {
+        self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        ((self.0 >> 16) % 16 + 1) as u8
+    }
+}
+
+pub fn snake() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load(SNAKE.to_vec());
+    cpu.reset();
+    cpu
+}
+
+/// Runs `count` instructions of the snake game, feeding it random numbers
+/// the way the web page does. Returns false once the game hits BRK.
+pub fn snake_steps(cpu: &mut CPU, rng: &mut Rng, count: usize) -> bool {
+    for _ in 0..count {
+        cpu.mem_write(0xfe, rng.next_byte());
+        if cpu.next() {
+            return false;
+        }
+    }
+    true
+}
+
+/// The KIL that BRK in an `ines` image jumps to, so runs stop there.
+pub const BRK_JAM: u16 = 0xfff0;
+
+/// An NROM image: one 16K PRG bank with `program` at $8000 and the reset
+/// vector pointing at it, plus 8K of blank CHR. BRK jams at `BRK_JAM`.
+pub fn ines(flags6: u8, program: &[u8]) -> Vec<u8> {
+    let mut raw = vec![
+        b'N', b'E', b'S', 0x1a, 1, 1, flags6, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let mut prg = vec![0; 0x4000];
+    prg[..program.len()].copy_from_slice(program);
+    prg[0x3ff0] = 0x02;
+    prg[0x3ffc] = 0x00;
+    prg[0x3ffd] = 0x80;
+    prg[0x3ffe] = 0xf0;
+    prg[0x3fff] = 0xff;
+    raw.extend(prg);
+    raw.extend(vec![0; 0x2000]);
+    raw
+}
@@ -0,0 +1,234 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::debugger::{Access, StopReason};
+
+// 0600: JSR $0609
+// 0603: LDX #$01
+// 0605: STA $10
+// 0607: BRK
+// 0608: NOP
+// 0609: LDA #$05
+// 060B: JSR $060F
+// 060E: RTS
+// 060F: INX
+// 0610: RTS
+fn subroutine_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0x20, 0x09, 0x06, 0xa2, 0x01, 0x85, 0x10, 0x00, 0xea, 0xa9, 0x05, 0x20, 0x0f, 0x06, 0x60,
+        0xe8, 0x60,
+    ]);
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn breakpoint_stops_and_resumes() {
+    let mut cpu = subroutine_cpu();
+    cpu.add_breakpoint(0x0605);
+
+    assert_eq!(cpu.resume(1000), StopReason::Breakpoint);
+    assert_eq!(cpu.program_counter, 0x0605);
+    assert_eq!(cpu.register_x, 0x01);
+
+    assert_eq!(cpu.resume(1000), StopReason::Brk);
+    assert_eq!(cpu.mem_read(0x10), 0x05);
+}
+
+#[test]
+fn breakpoint_on_the_starting_instruction() {
+    let mut cpu = subroutine_cpu();
+    cpu.add_breakpoint(0x0600);
+
+    assert_eq!(cpu.resume(1000), StopReason::Breakpoint);
+    assert_eq!(cpu.program_counter, 0x0600);
+    assert_eq!(cpu.debugger().breakpoints()[0].hits, 1);
+
+    assert_eq!(cpu.resume(1000), StopReason::Brk);
+    assert_eq!(cpu.debugger().breakpoints()[0].hits, 1);
+}
+
+#[test]
+fn removed_breakpoint_is_ignored() {
+    let mut cpu = subroutine_cpu();
+    cpu.add_breakpoint(0x0605);
+    cpu.remove_breakpoint(0x0605);
+
+    assert_eq!(cpu.resume(1000), StopReason::Brk);
+}
+
+#[test]
+fn step_into_follows_jsr() {
+    let mut cpu = subroutine_cpu();
+
+    assert_eq!(cpu.step_into(), StopReason::Step);
+    assert_eq!(cpu.program_counter, 0x0609);
+}
+
+#[test]
+fn step_over_runs_whole_call() {
+    let mut cpu = subroutine_cpu();
+
+    assert_eq!(cpu.step_over(1000), StopReason::Step);
+    assert_eq!(cpu.program_counter, 0x0603);
+    assert_eq!(cpu.register_a, 0x05);
+    assert_eq!(cpu.register_x, 0x01);
+
+    // not a JSR, so a single step
+    assert_eq!(cpu.step_over(1000), StopReason::Step);
+    assert_eq!(cpu.program_counter, 0x0605);
+}
+
+#[test]
+fn step_over_stops_on_breakpoint_inside_call() {
+    let mut cpu = subroutine_cpu();
+    cpu.add_breakpoint(0x060f);
+
+    assert_eq!(cpu.step_over(1000), StopReason::Breakpoint);
+    assert_eq!(cpu.program_counter, 0x060f);
+}
+
+#[test]
+fn step_out_returns_to_caller() {
+    let mut cpu = subroutine_cpu();
+    cpu.step_into(); // JSR $0609
+    cpu.step_into(); // LDA
+    cpu.step_into(); // JSR $060F
+
+    assert_eq!(cpu.step_out(1000), StopReason::Step);
+    assert_eq!(cpu.program_counter, 0x060e);
+
+    assert_eq!(cpu.step_out(1000), StopReason::Step);
+    assert_eq!(cpu.program_counter, 0x0603);
+}
+
+#[test]
+fn run_to_cursor() {
+    let mut cpu = subroutine_cpu();
+
+    assert_eq!(cpu.run_to(0x060f, 1000), StopReason::Step);
+    assert_eq!(cpu.program_counter, 0x060f);
+    assert_eq!(cpu.register_a, 0x05);
+}
+
+#[test]
+fn limit_stops_run() {
+    let mut cpu = subroutine_cpu();
+
+    assert_eq!(cpu.resume(2), StopReason::Limit);
+    assert_eq!(cpu.program_counter, 0x060b);
+}
+
+#[test]
+fn write_watchpoint() {
+    let mut cpu = subroutine_cpu();
+    cpu.add_watchpoint(0x10, 0x10, false, true);
+
+    assert_eq!(cpu.resume(1000), StopReason::Watchpoint);
+    assert_eq!(cpu.program_counter, 0x0607);
+    let hit = cpu.last_watch_hit().unwrap();
+    assert_eq!(hit.addr, 0x10);
+    assert_eq!(hit.value, 0x05);
+    assert_eq!(hit.access, Access::Write);
+}
+
+#[test]
+fn read_watchpoint_on_register_range() {
+    let mut cpu = CPU::new();
+    // LDA $2002; LDA $2007
+    cpu.load(vec![0xad, 0x02, 0x20, 0xad, 0x07, 0x20, 0x00]);
+    cpu.reset();
+    cpu.add_watchpoint(0x2007, 0x2007, true, false);
+    cpu.add_watchpoint(0x0600, 0x0700, false, true);
+
+    assert_eq!(cpu.resume(1000), StopReason::Watchpoint);
+    assert_eq!(cpu.program_counter, 0x0606);
+    assert_eq!(cpu.last_watch_hit().unwrap().access, Access::Read);
+
+    cpu.remove_watchpoint(0x2007, 0x2007);
+    assert_eq!(cpu.resume(1000), StopReason::Brk);
+    assert_eq!(cpu.last_watch_hit(), None);
+}
+
+// 0600: LDX #$00
+// 0602: INX
+// 0603: CPX #$0A
+// 0605: BNE $0602
+// 0607: BRK
+fn loop_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xa2, 0x00, 0xe8, 0xe0, 0x0a, 0xd0, 0xfb, 0x00]);
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn conditional_breakpoint_on_register() {
+    let mut cpu = loop_cpu();
+    cpu.add_conditional_breakpoint(0x0603, "X == 5").unwrap();
+
+    assert_eq!(cpu.resume(1000), StopReason::Breakpoint);
+    assert_eq!(cpu.program_counter, 0x0603);
+    assert_eq!(cpu.register_x, 5);
+}
+
+#[test]
+fn conditional_breakpoint_on_hit_count() {
+    let mut cpu = loop_cpu();
+    cpu.add_conditional_breakpoint(0x0602, "HITS == 7").unwrap();
+
+    assert_eq!(cpu.resume(1000), StopReason::Breakpoint);
+    assert_eq!(cpu.register_x, 6);
+    assert_eq!(cpu.debugger().breakpoints()[0].hits, 7);
+
+    assert_eq!(cpu.resume(1000), StopReason::Brk);
+    assert_eq!(cpu.debugger().breakpoints()[0].hits, 10);
+}
+
+#[test]
+fn break_condition_without_address() {
+    let mut cpu = subroutine_cpu();
+    cpu.add_break_condition("[$10] == 5 || PC == $0610")
+        .unwrap();
+
+    assert_eq!(cpu.resume(1000), StopReason::Breakpoint);
+    assert_eq!(cpu.program_counter, 0x0610);
+
+    assert_eq!(cpu.resume(1000), StopReason::Breakpoint);
+    assert_eq!(cpu.program_counter, 0x0607);
+    assert_eq!(cpu.mem_read(0x10), 5);
+}
+
+#[test]
+fn invalid_condition_is_rejected() {
+    let mut cpu = loop_cpu();
+    assert!(cpu.add_conditional_breakpoint(0x0603, "X ==").is_err());
+    assert!(cpu.debugger().breakpoints().is_empty());
+}
+
+#[test]
+fn unknown_opcode_stops_with_jam() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xe8, 0x02, 0x00]);
+    cpu.reset();
+
+    assert_eq!(cpu.resume(1000), StopReason::Jam);
+    assert_eq!(cpu.program_counter, 0x0601);
+    assert_eq!(cpu.resume(1000), StopReason::Jam);
+    assert_eq!(cpu.register_x, 1);
+}
+
+#[test]
+fn unknown_opcodes_disassemble_as_bytes() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xa9, 0x01, 0x02, 0xff, 0xe8, 0x00]);
+    cpu.reset();
+    assert_eq!(
+        cpu.disassemble(0x0600, 5),
+        "0600  A9 01     LDA #$01\n\
+         0602  02        .byte $02\n\
+         0603  FF        .byte $FF\n\
+         0604  E8        INX\n\
+         0605  00        BRK"
+    );
+}
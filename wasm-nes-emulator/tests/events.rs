@@ -0,0 +1,166 @@
+extern crate wasm_nes_emulator;
+use std::convert::TryInto;
+use wasm_nes_emulator::asm::assemble;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::events::{Event, EventKind};
+
+mod common;
+use common::ines;
+
+/// A cartridge running `source` from $8000, with the NMI vector pointing
+/// at its `nmi` label if it has one.
+fn nes(source: &str) -> CPU {
+    let assembly = assemble(&format!(".org $8000\n{}", source)).unwrap();
+    let mut rom = ines(0, &assembly.bytes);
+    if let Some(nmi) = assembly.labels.get("nmi") {
+        rom[16 + 0x3ffa..16 + 0x3ffc].copy_from_slice(&nmi.to_le_bytes());
+    }
+    let mut cpu = CPU::new();
+    cpu.load_rom(&rom).unwrap();
+    cpu.start_event_log();
+    cpu
+}
+
+fn kinds(events: &[Event]) -> Vec<(EventKind, u16)> {
+    events.iter().map(|e| (e.kind, e.addr)).collect()
+}
+
+#[test]
+fn nmi_is_taken_at_vblank() {
+    let mut cpu = nes("
+        lda #$80
+        sta $2000
+    wait:
+        jmp wait
+    nmi:
+        lda #$1e
+        sta $2001
+        dcb $02
+    ");
+    cpu.run();
+    assert_eq!(cpu.ppu_position().0, 241);
+    // I is set, and the pushed status has B clear and bit 5 set
+    assert_eq!(cpu.status & 0b0000_0100, 0b0000_0100);
+    assert_eq!(cpu.stack_ptr, 0xfa);
+    assert_eq!(cpu.mem_peek(0x01fb) & 0b0011_0000, 0b0010_0000);
+    assert_eq!(cpu.mem_read_u16(0x01fc), 0x8005);
+
+    let log = cpu.event_log().unwrap();
+    assert_eq!(
+        kinds(log.frame()),
+        vec![
+            (EventKind::PpuWrite, 0x2000),
+            (EventKind::Nmi, 0xfffa),
+            (EventKind::PpuWrite, 0x2001),
+        ]
+    );
+    let nmi = log.frame()[1];
+    assert_eq!(nmi.scanline, 241);
+    assert_eq!(nmi.pc, 0x8005);
+    assert_eq!(log.frame()[0].pc, 0x8002);
+    assert_eq!(log.frame()[2].value, 0x1e);
+}
+
+#[test]
+fn enabling_nmi_in_vblank_raises_one() {
+    let mut cpu = nes("
+    wait:
+        jmp wait
+    nmi:
+        brk
+    ");
+    cpu.run_until(u32::MAX, |cpu, _| cpu.ppu_position().0 == 242);
+    // the next instruction still runs before the interrupt is taken
+    cpu.mem_write(0x2000, 0x80);
+    cpu.next();
+    assert_eq!(cpu.program_counter, 0x8003);
+    let frame = cpu.event_log().unwrap().frame();
+    assert_eq!(frame.last().unwrap().kind, EventKind::Nmi);
+}
+
+#[test]
+fn logs_only_register_writes() {
+    let mut cpu = nes("
+        lda #$3f
+        sta $10
+        sta $6000
+        sta $2006
+        sta $3ffe
+        sta $4000
+        sta $4015
+        sta $4016
+        sta $8000
+        brk
+    ");
+    cpu.run();
+    let frame = cpu.event_log().unwrap().frame();
+    assert_eq!(
+        kinds(frame),
+        vec![
+            (EventKind::PpuWrite, 0x2006),
+            (EventKind::PpuWrite, 0x2006),
+            (EventKind::ApuWrite, 0x4000),
+            (EventKind::ApuWrite, 0x4015),
+            (EventKind::MapperWrite, 0x8000),
+        ]
+    );
+    assert_eq!(
+        cpu.event_list(true).lines().next().unwrap(),
+        format!("{:3} {:3}  $8007  PPUADDR $2006 = $3F", 0, frame[0].dot)
+    );
+
+    // easy6502 has no registers to log
+    let mut cpu = CPU::new();
+    cpu.start_event_log();
+    cpu.load_and_run(vec![0x8d, 0x00, 0x20, 0x00]);
+    assert!(cpu.event_log().unwrap().frame().is_empty());
+}
+
+#[test]
+fn keeps_the_last_whole_frame() {
+    let mut cpu = nes("
+    loop:
+        sta $2005
+        jmp loop
+    ");
+    cpu.run_frame();
+    cpu.run_frame();
+    let log = cpu.event_log().unwrap();
+    // one write every seven cycles, all frame long
+    let last = log.last_frame();
+    assert!(last.len() > 4000);
+    assert_eq!(last.first().unwrap().scanline, 0);
+    assert_eq!(last.last().unwrap().scanline, 261);
+    assert!(log.frame().len() < 2);
+    assert_eq!(cpu.event_list(false).lines().count(), last.len());
+
+    cpu.stop_event_log();
+    assert!(cpu.event_log().is_none());
+    assert_eq!(cpu.event_list(false), "");
+}
+
+#[test]
+fn overlay_marks_each_event() {
+    let mut cpu = nes("
+        lda #$80
+        sta $2000
+    wait:
+        jmp wait
+    nmi:
+        brk
+    ");
+    cpu.run();
+    let overlay = cpu.event_overlay_rgba(true);
+    assert_eq!(overlay.len(), 341 * 262 * 4);
+    let pixel = |e: &Event| {
+        let i = (e.scanline as usize * 341 + e.dot as usize) * 4;
+        let p: [u8; 4] = overlay[i..i + 4].try_into().unwrap();
+        p
+    };
+    for event in cpu.event_log().unwrap().frame() {
+        let [r, g, b] = event.color();
+        assert_eq!(pixel(event), [r, g, b, 0xff]);
+    }
+    let marked = overlay.chunks(4).filter(|p| p[3] != 0).count();
+    assert_eq!(marked, 2);
+}
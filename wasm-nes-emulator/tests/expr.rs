@@ -0,0 +1,90 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::expr::Expr;
+use wasm_nes_emulator::symbols::SymbolTable;
+
+fn eval(src: &str, cpu: &CPU) -> i64 {
+    Expr::parse(src).unwrap().eval(cpu, 0)
+}
+
+#[test]
+fn numbers_and_precedence() {
+    let cpu = CPU::new();
+    assert_eq!(eval("1 + 2 * 3", &cpu), 7);
+    assert_eq!(eval("(1 + 2) * 3", &cpu), 9);
+    assert_eq!(eval("$10 + 0x10 + 16", &cpu), 48);
+    assert_eq!(eval("1 << 4 | 1", &cpu), 17);
+    assert_eq!(eval("-1 + 2", &cpu), 1);
+    assert_eq!(eval("!0 && !(3 > 4)", &cpu), 1);
+    assert_eq!(eval("5 / 0", &cpu), 0);
+}
+
+#[test]
+fn registers_flags_and_memory() {
+    let mut cpu = CPU::new();
+    cpu.register_a = 0x42;
+    cpu.register_x = 3;
+    cpu.register_y = 4;
+    cpu.stack_ptr = 0xfd;
+    cpu.program_counter = 0x0605;
+    cpu.status = 0b1000_0011;
+    cpu.mem_write(0xfe, 0x0f);
+    cpu.mem_write(0x0f, 0x99);
+
+    assert_eq!(eval("a == $42 && x == 3 && Y == 4", &cpu), 1);
+    assert_eq!(eval("SP", &cpu), 0xfd);
+    assert_eq!(eval("PC = $0605", &cpu), 1);
+    assert_eq!(eval("C + Z + N", &cpu), 3);
+    assert_eq!(eval("V", &cpu), 0);
+    assert_eq!(eval("[$00FE] == $0F", &cpu), 1);
+    assert_eq!(eval("[[$FE]]", &cpu), 0x99);
+    assert_eq!(eval("[$FE + X - 3] & $F0", &cpu), 0);
+}
+
+#[test]
+fn timing_and_hits() {
+    let mut cpu = CPU::new();
+    cpu.cycles = 30_000;
+
+    assert_eq!(eval("FRAME", &cpu), 1);
+    assert_eq!(eval("SCANLINE < 262 && DOT < 341", &cpu), 1);
+    assert_eq!(Expr::parse("HITS >= 300").unwrap().eval(&cpu, 300), 1);
+    assert_eq!(Expr::parse("HITS >= 300").unwrap().eval(&cpu, 299), 0);
+}
+
+#[test]
+fn labels_named_like_variables() {
+    let mut symbols = SymbolTable::default();
+    symbols.add("x", 0x0300, None);
+    symbols.add("frame", 0x0400, None);
+    symbols.add("score", 0x0010, None);
+    let mut cpu = CPU::new();
+    cpu.register_x = 7;
+    cpu.mem_write(0x0300, 0x55);
+    let eval = |src: &str| {
+        Expr::parse_with_symbols(src, &symbols)
+            .unwrap()
+            .eval(&cpu, 0)
+    };
+
+    assert_eq!(eval("x"), 7);
+    assert_eq!(eval("@x"), 0x0300);
+    assert_eq!(eval("[@x]"), 0x55);
+    assert_eq!(eval("@frame + score"), 0x0410);
+    assert_eq!(eval("@score"), 0x0010);
+    assert!(Expr::parse_with_symbols("@y", &symbols).is_err());
+    assert!(Expr::parse_with_symbols("@ == 1", &symbols).is_err());
+}
+
+#[test]
+fn parse_errors() {
+    assert!(Expr::parse("").is_err());
+    assert!(Expr::parse("A ==").is_err());
+    assert!(Expr::parse("(A").is_err());
+    assert!(Expr::parse("[$FE").is_err());
+    assert!(Expr::parse("FOO == 1").is_err());
+    assert!(Expr::parse("A # 1").is_err());
+    assert!(Expr::parse("1 2").is_err());
+    assert!(Expr::parse("$").is_err());
+    assert!(Expr::parse("@main").is_err());
+}
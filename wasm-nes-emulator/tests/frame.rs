@@ -0,0 +1,132 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::cpu::{FrameStatus, CPU};
+
+mod common;
+use common::snake;
+
+// 0600: INC $10
+// 0602: JMP $0600
+fn looping_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xe6, 0x10, 0x4c, 0x00, 0x06]);
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn runs_exactly_one_frame() {
+    let mut cpu = looping_cpu();
+    for frame in 1..=3 {
+        assert_eq!(cpu.run_frame(), FrameStatus::Complete);
+        assert_eq!(cpu.frame(), frame);
+        // stops on the first instruction of the new frame
+        assert_eq!(cpu.ppu_position().0, 0);
+    }
+    assert!(!cpu.update);
+}
+
+#[test]
+fn reports_display_changes() {
+    // 0600: INC $0200
+    // 0603: JMP $0600
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xee, 0x00, 0x02, 0x4c, 0x00, 0x06]);
+    cpu.reset();
+    cpu.run_frame();
+    assert!(cpu.update);
+}
+
+#[test]
+fn snake_moves_once_per_three_display_writes() {
+    // the top of snake's game loop
+    let mut cpu = snake();
+    cpu.set_random_seed(1);
+    cpu.add_breakpoint(0x0638);
+
+    let mut steps = 0;
+    let mut frames = 0;
+    while frames < 30 {
+        match cpu.run_frame() {
+            FrameStatus::Breakpoint => steps += 1,
+            FrameStatus::Complete => {
+                assert!(cpu.update);
+                assert_eq!(cpu.frame(), 0);
+                frames += 1;
+            }
+            status => panic!("snake stopped with {:?}", status),
+        }
+    }
+    // it draws the apple, then erases the tail and draws the head
+    assert_eq!(steps, 10);
+}
+
+#[test]
+fn stops_on_brk() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xe8, 0xe8, 0x00]);
+    cpu.reset();
+    assert_eq!(cpu.run_frame(), FrameStatus::Brk);
+    assert_eq!(cpu.register_x, 2);
+    assert_eq!(cpu.frame(), 0);
+}
+
+#[test]
+fn breakpoint_pauses_the_frame() {
+    let mut cpu = looping_cpu();
+    cpu.add_breakpoint(0x0602);
+    assert_eq!(cpu.run_frame(), FrameStatus::Breakpoint);
+    assert_eq!(cpu.program_counter, 0x0602);
+
+    cpu.clear_debugger();
+    assert_eq!(cpu.run_frame(), FrameStatus::Complete);
+    assert_eq!(cpu.frame(), 1);
+}
+
+#[test]
+fn breakpoint_on_the_first_instruction_of_a_frame() {
+    let mut cpu = looping_cpu();
+    cpu.run_frame();
+    let start = cpu.program_counter;
+    cpu.add_breakpoint(start);
+
+    assert_eq!(cpu.run_frame(), FrameStatus::Breakpoint);
+    assert_eq!(cpu.program_counter, start);
+    assert_eq!(cpu.frame(), 1);
+
+    // resuming steps off it once, then it stops the loop again
+    assert_eq!(cpu.run_frame(), FrameStatus::Breakpoint);
+    assert_eq!(cpu.program_counter, start);
+    assert_eq!(cpu.debugger().breakpoints()[0].hits, 2);
+}
+
+#[test]
+fn random_byte_is_seeded() {
+    let sample = |seed: u32| {
+        let mut cpu = looping_cpu();
+        cpu.set_random_seed(seed);
+        (0..8)
+            .map(|_| {
+                cpu.next();
+                cpu.mem_peek(0xfe)
+            })
+            .collect::<Vec<u8>>()
+    };
+    assert_eq!(sample(7), sample(7));
+    assert_ne!(sample(7), sample(8));
+    assert!(sample(0).iter().all(|&b| b == 0));
+}
+
+#[test]
+fn save_state_keeps_the_random_sequence() {
+    let mut cpu = looping_cpu();
+    cpu.set_random_seed(99);
+    cpu.run_frame();
+    let state = cpu.save_state();
+    cpu.run_frame();
+    let expected = cpu.save_state();
+
+    let mut other = CPU::new();
+    other.load_state(&state).unwrap();
+    other.run_frame();
+    assert!(other.save_state() == expected);
+}
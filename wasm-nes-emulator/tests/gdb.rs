@@ -0,0 +1,205 @@
+#![cfg(all(feature = "std", not(target_arch = "wasm32")))]
+extern crate wasm_nes_emulator;
+use std::io::{self, Cursor, Read, Write};
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::gdb::{Connection, GdbStub};
+
+/// A scripted client: everything it will send is queued up front and the
+/// stub's output is captured for inspection.
+struct Script {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Read for Script {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Script {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Connection for Script {}
+
+fn packet(data: &str) -> String {
+    let sum = data.bytes().fold(0u8, |acc, b| acc.wrapping_add(b));
+    format!("${}#{:02x}", data, sum)
+}
+
+/// Runs the stub over `packets` and returns the payloads of its replies.
+fn session(cpu: &mut CPU, packets: &[&str]) -> Vec<String> {
+    let mut input = String::new();
+    for p in packets {
+        input.push_str(&packet(p));
+        input.push('+');
+    }
+    let script = Script {
+        input: Cursor::new(input.into_bytes()),
+        output: vec![],
+    };
+    let mut stub = GdbStub::new(cpu, script);
+    stub.run().unwrap();
+    let output = String::from_utf8(stub.into_connection().output).unwrap();
+
+    output
+        .split('$')
+        .skip(1)
+        .map(|p| p.split('#').next().unwrap().to_string())
+        .collect()
+}
+
+// 0600: LDA #$05
+// 0602: STA $10
+// 0604: INX
+// 0605: BRK
+fn test_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xa9, 0x05, 0x85, 0x10, 0xe8, 0x00]);
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn handshake_and_registers() {
+    let mut cpu = test_cpu();
+    cpu.register_x = 0x12;
+    let replies = session(&mut cpu, &["qSupported:swbreak+", "?", "g", "p5", "D"]);
+
+    assert!(replies[0].contains("qXfer:features:read+"));
+    assert_eq!(replies[1], "S05");
+    assert_eq!(replies[2], "00120000fd0006");
+    assert_eq!(replies[3], "0006");
+    assert_eq!(replies[4], "OK");
+}
+
+#[test]
+fn target_description() {
+    let mut cpu = test_cpu();
+    let replies = session(&mut cpu, &["qXfer:features:read:target.xml:0,fff"]);
+
+    assert!(replies[0].starts_with("l<?xml"));
+    assert!(replies[0].contains("name=\"pc\" bitsize=\"16\""));
+}
+
+#[test]
+fn write_registers() {
+    let mut cpu = test_cpu();
+    let replies = session(&mut cpu, &["G01020324250006", "P1=7f", "P5=0406"]);
+
+    assert_eq!(replies, vec!["OK", "OK", "OK"]);
+    assert_eq!(cpu.register_a, 0x01);
+    assert_eq!(cpu.register_x, 0x7f);
+    assert_eq!(cpu.register_y, 0x03);
+    assert_eq!(cpu.status, 0x24);
+    assert_eq!(cpu.stack_ptr, 0x25);
+    assert_eq!(cpu.program_counter, 0x0604);
+}
+
+#[test]
+fn memory_access() {
+    let mut cpu = test_cpu();
+    let replies = session(&mut cpu, &["m600,4", "M20,3:aabbcc", "m20,3", "mfffc,2"]);
+
+    assert_eq!(replies[0], "a9058510");
+    assert_eq!(replies[1], "OK");
+    assert_eq!(replies[2], "aabbcc");
+    assert_eq!(replies[3], "0006");
+    assert_eq!(cpu.mem_read(0x21), 0xbb);
+}
+
+#[test]
+fn bad_memory_requests() {
+    let mut cpu = test_cpu();
+    cpu.mem_write(0x0000, 0x42);
+    let replies = session(&mut cpu, &["m0,ffffffff", "mffff,2", "m10000,1", "mzz,1"]);
+
+    // cut to what fits in a packet
+    assert_eq!(replies[0].len(), 0x1000);
+    // reads wrap around the address space
+    assert_eq!(replies[1], format!("{:02x}42", cpu.mem_read(0xffff)));
+    assert_eq!(replies[2], "E01");
+    assert_eq!(replies[3], "E01");
+}
+
+#[test]
+fn single_step() {
+    let mut cpu = test_cpu();
+    let replies = session(&mut cpu, &["s", "s", "g"]);
+
+    assert_eq!(replies[0], "S05");
+    assert_eq!(replies[2], "05000000fd0406");
+    assert_eq!(cpu.mem_read(0x10), 0x05);
+}
+
+#[test]
+fn software_breakpoint_and_continue() {
+    let mut cpu = test_cpu();
+    let replies = session(&mut cpu, &["Z0,604,1", "c", "p5", "z0,604,1", "c"]);
+
+    assert_eq!(replies[0], "OK");
+    assert_eq!(replies[1], "T05swbreak:;");
+    assert_eq!(replies[2], "0406");
+    assert_eq!(replies[3], "OK");
+    assert_eq!(replies[4], "W00");
+}
+
+#[test]
+fn watchpoint_stop() {
+    let mut cpu = test_cpu();
+    let replies = session(&mut cpu, &["Z2,10,1", "vCont;c"]);
+
+    assert_eq!(replies[1], "T05watch:0010;");
+    assert_eq!(cpu.program_counter, 0x0604);
+}
+
+#[test]
+fn vcont_with_thread_ids() {
+    let mut cpu = test_cpu();
+    let replies = session(
+        &mut cpu,
+        &[
+            "vCont?",
+            "vCont;s:1",
+            "vCont;s:p1.1;c",
+            "Z0,605,1",
+            "vCont;c:p1.-1",
+            "vCont;t",
+        ],
+    );
+
+    assert!(replies[0].starts_with("vCont;c;C;s;S"));
+    assert_eq!(replies[1], "S05");
+    assert_eq!(replies[2], "S05");
+    assert_eq!(replies[4], "T05swbreak:;");
+    assert_eq!(replies[5], "");
+    assert_eq!(cpu.program_counter, 0x0605);
+}
+
+#[test]
+fn bad_checksum_is_nacked() {
+    let mut cpu = test_cpu();
+    let script = Script {
+        input: Cursor::new(b"$g#00+".to_vec()),
+        output: vec![],
+    };
+    let mut stub = GdbStub::new(&mut cpu, script);
+    stub.run().unwrap();
+
+    assert_eq!(stub.into_connection().output, b"-");
+}
+
+#[test]
+fn unknown_packets_get_empty_reply() {
+    let mut cpu = test_cpu();
+    let replies = session(&mut cpu, &["vMustReplyEmpty", "qfThreadInfo", "k"]);
+
+    assert_eq!(replies, vec!["", "m1"]);
+}
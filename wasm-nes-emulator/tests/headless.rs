@@ -0,0 +1,264 @@
+#![cfg(feature = "std")]
+use std::path::PathBuf;
+use std::process::Command;
+
+// 0600: INC $10
+// 0602: LDA $10
+// 0604: CMP #$80
+// 0606: BNE $0600
+// 0608: BRK
+const COUNT: [u8; 9] = [0xe6, 0x10, 0xa5, 0x10, 0xc9, 0x80, 0xd0, 0xf8, 0x00];
+
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("headless-{}-{}", std::process::id(), name))
+}
+
+fn headless(args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_headless"))
+        .args(args)
+        .output()
+        .unwrap();
+    let text = String::from_utf8_lossy(&output.stdout).into_owned()
+        + &String::from_utf8_lossy(&output.stderr);
+    (output.status.code().unwrap(), text)
+}
+
+#[test]
+fn runs_program_and_dumps_ram() {
+    let program = temp("count.bin");
+    let dump = temp("count.ram");
+    std::fs::write(&program, COUNT).unwrap();
+
+    let (code, out) = headless(&[
+        program.to_str().unwrap(),
+        "--ram-dump",
+        dump.to_str().unwrap(),
+    ]);
+    assert_eq!(code, 0, "{}", out);
+    assert!(out.contains("stopped on BRK"));
+
+    let ram = std::fs::read(&dump).unwrap();
+    assert_eq!(ram.len(), 0x800);
+    assert_eq!(ram[0x10], 0x80);
+    assert_eq!(&ram[0x600..0x609], &COUNT);
+
+    std::fs::remove_file(program).unwrap();
+    std::fs::remove_file(dump).unwrap();
+}
+
+#[test]
+fn stops_on_condition() {
+    let program = temp("until.bin");
+    std::fs::write(&program, COUNT).unwrap();
+    let program = program.to_str().unwrap();
+
+    let (code, out) = headless(&[program, "--until", "[$10] == 5"]);
+    assert_eq!(code, 0, "{}", out);
+    assert!(out.contains("stopped on condition"));
+
+    std::fs::remove_file(program).unwrap();
+}
+
+#[test]
+fn gives_up_after_max_frames() {
+    let program = temp("spin.bin");
+    // 0600: JMP $0600
+    std::fs::write(&program, [0x4c, 0x00, 0x06]).unwrap();
+
+    let (code, out) = headless(&[
+        program.to_str().unwrap(),
+        "--until",
+        "A == 1",
+        "--max-frames",
+        "2",
+    ]);
+    assert_eq!(code, 2, "{}", out);
+    assert!(out.contains("at frame 2"));
+
+    std::fs::remove_file(program).unwrap();
+}
+
+#[test]
+fn endless_runs_are_capped() {
+    let program = temp("forever.bin");
+    let trace = temp("forever.log");
+    // 0600: JMP $0600
+    std::fs::write(&program, [0x4c, 0x00, 0x06]).unwrap();
+
+    let (code, out) = headless(&[
+        program.to_str().unwrap(),
+        "--max-frames",
+        "3",
+        "--trace",
+        trace.to_str().unwrap(),
+    ]);
+    assert_eq!(code, 2, "{}", out);
+    assert!(out.contains("stopped on max frames at frame 3"));
+
+    let log = std::fs::read_to_string(&trace).unwrap();
+    assert!(log.lines().count() > 1000);
+    assert!(log
+        .lines()
+        .all(|line| line.starts_with("0600  4C 00 06  JMP $0600")));
+    assert!(log.ends_with('\n'));
+
+    std::fs::remove_file(program).unwrap();
+    std::fs::remove_file(trace).unwrap();
+}
+
+#[test]
+fn rejects_bad_arguments() {
+    assert_eq!(headless(&["--frames"]).0, 1);
+    assert_eq!(headless(&["--bogus", "x.nes"]).0, 1);
+    assert_eq!(headless(&["does-not-exist.nes"]).0, 1);
+
+    let (code, out) = headless(&["--help"]);
+    assert_eq!(code, 1);
+    assert!(out.contains("usage:"));
+}
+
+#[test]
+fn writes_screenshot_and_framebuffer() {
+    // 0600: LDA #$05
+    // 0602: STA $0200
+    // 0605: BRK
+    let program = temp("pixel.bin");
+    let png = temp("pixel.png");
+    let raw = temp("pixel.fb");
+    std::fs::write(&program, [0xa9, 0x05, 0x8d, 0x00, 0x02, 0x00]).unwrap();
+
+    let (code, out) = headless(&[
+        program.to_str().unwrap(),
+        "--screenshot",
+        png.to_str().unwrap(),
+        "--scale",
+        "2",
+        "--framebuffer",
+        raw.to_str().unwrap(),
+    ]);
+    assert_eq!(code, 0, "{}", out);
+
+    let image = std::fs::read(&png).unwrap();
+    assert_eq!(&image[1..4], b"PNG");
+    assert_eq!(&image[16..24], &[0, 0, 0, 64, 0, 0, 0, 64]);
+    let pixels = std::fs::read(&raw).unwrap();
+    assert_eq!(pixels.len(), 1024);
+    assert_eq!(pixels[0], 5);
+
+    for path in [program, png, raw] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn records_video_and_audio() {
+    // 0600: INC $0200
+    // 0603: JMP $0600
+    let program = temp("blink.bin");
+    let video = temp("blink.y4m");
+    let audio = temp("blink.wav");
+    std::fs::write(&program, [0xee, 0x00, 0x02, 0x4c, 0x00, 0x06]).unwrap();
+
+    let (code, out) = headless(&[
+        program.to_str().unwrap(),
+        "--frames",
+        "3",
+        "--scale",
+        "1",
+        "--video",
+        video.to_str().unwrap(),
+        "--audio",
+        audio.to_str().unwrap(),
+    ]);
+    assert_eq!(code, 0, "{}", out);
+
+    let data = std::fs::read(&video).unwrap();
+    assert!(data.starts_with(b"YUV4MPEG2 W32 H32 "));
+    assert_eq!(data.windows(6).filter(|w| w == b"FRAME\n").count(), 3);
+    assert_eq!(std::fs::read(&audio).unwrap().len(), 44 + 2 * 2201);
+
+    for path in [program, video, audio] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn assembles_source_files() {
+    let program = temp("count.asm");
+    std::fs::write(
+        &program,
+        "loop:\n  inc $10\n  lda $10\n  cmp #$80\n  bne loop\n  brk\n",
+    )
+    .unwrap();
+    let dump = temp("asm.ram");
+
+    let (code, out) = headless(&[
+        program.to_str().unwrap(),
+        "--ram-dump",
+        dump.to_str().unwrap(),
+    ]);
+    assert_eq!(code, 0, "{}", out);
+    assert_eq!(&std::fs::read(&dump).unwrap()[0x600..0x609], &COUNT);
+
+    std::fs::remove_file(program).unwrap();
+    std::fs::remove_file(dump).unwrap();
+}
+
+#[test]
+fn logs_code_and_data_across_runs() {
+    // 8000: LDA $8010
+    // 8003: BRK, with the vector pointing at
+    // 8004: KIL
+    let mut rom = vec![b'N', b'E', b'S', 0x1a, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut prg = vec![0; 0x4000];
+    prg[..5].copy_from_slice(&[0xad, 0x10, 0x80, 0x00, 0x02]);
+    prg[0x3ffd] = 0x80;
+    prg[0x3ffe] = 0x04;
+    prg[0x3fff] = 0x80;
+    rom.extend(prg);
+    rom.extend(vec![0; 0x2000]);
+    let path = temp("cdl.nes");
+    std::fs::write(&path, &rom).unwrap();
+    let cdl = temp("cdl.cdl");
+
+    let args = [path.to_str().unwrap(), "--cdl", cdl.to_str().unwrap()];
+    let (code, out) = headless(&args);
+    assert_eq!(code, 3, "{}", out);
+    assert!(out.contains("jammed"), "{}", out);
+    let log = std::fs::read(&cdl).unwrap();
+    assert_eq!(log.len(), 0x6000);
+    assert_eq!(&log[..5], &[1, 1, 1, 1, 0]);
+    assert_eq!(log[0x10], 2);
+
+    // a second run adds to the same file
+    let mut marked = log.clone();
+    marked[0x20] = 2;
+    std::fs::write(&cdl, &marked).unwrap();
+    let (code, out) = headless(&args);
+    assert_eq!(code, 3, "{}", out);
+    assert_eq!(std::fs::read(&cdl).unwrap(), marked);
+
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(cdl).unwrap();
+}
+
+#[test]
+fn writes_folded_stacks() {
+    let program = temp("profile.asm");
+    std::fs::write(&program, "main:\n jsr work\n brk\nwork:\n nop\n rts\n").unwrap();
+    let folded = temp("profile.folded");
+
+    let (code, out) = headless(&[
+        program.to_str().unwrap(),
+        "--profile",
+        folded.to_str().unwrap(),
+    ]);
+    assert_eq!(code, 0, "{}", out);
+    assert_eq!(
+        std::fs::read_to_string(&folded).unwrap(),
+        "main 6\nmain;work 8\n"
+    );
+
+    std::fs::remove_file(program).unwrap();
+    std::fs::remove_file(folded).unwrap();
+}
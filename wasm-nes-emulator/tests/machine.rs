@@ -0,0 +1,131 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::cpu::{FrameStatus, CPU};
+use wasm_nes_emulator::joypad::BUTTON_A;
+use wasm_nes_emulator::machine::Machine;
+
+mod common;
+use common::{ines, BRK_JAM};
+
+#[test]
+fn loaders_pick_the_machine() {
+    let mut cpu = CPU::new();
+    assert_eq!(cpu.machine, Machine::Easy6502);
+
+    cpu.load_rom(&ines(0, &[0x00])).unwrap();
+    assert_eq!(cpu.machine, Machine::Nes);
+
+    cpu.load_pro(vec![0xe8, 0x00]);
+    assert_eq!(cpu.machine, Machine::Easy6502);
+    assert!(cpu.cartridge().is_none());
+    assert_eq!(cpu.program_counter, 0x0600);
+}
+
+#[test]
+fn easy6502_ports() {
+    let mut cpu = CPU::new();
+    // 0600: LDA $FF
+    // 0602: STA $0200
+    // 0605: LDX $FE
+    // 0607: BRK
+    cpu.load_pro(vec![0xa5, 0xff, 0x8d, 0x00, 0x02, 0xa6, 0xfe, 0x00]);
+    cpu.set_random_seed(1);
+    cpu.key_press(b'w');
+    cpu.run();
+
+    assert_eq!(cpu.register_a, b'w');
+    assert!(cpu.update);
+    // a new xorshift32 value before each instruction; LDX is the third
+    assert_eq!(cpu.register_x, 0xc5);
+}
+
+#[test]
+fn nes_memory_map() {
+    // 8000: STA $0812
+    // 8003: STA $0200
+    // 8006: STA $8000
+    // 8009: BRK
+    let mut cpu = CPU::new();
+    let program = [0x8d, 0x12, 0x08, 0x8d, 0x00, 0x02, 0x8d, 0x00, 0x80, 0x00];
+    cpu.load_rom(&ines(0, &program)).unwrap();
+    cpu.set_random_seed(1);
+    cpu.register_a = 0x33;
+    cpu.run();
+
+    // 2K of RAM mirrored four times
+    assert_eq!(cpu.mem_peek(0x0012), 0x33);
+    assert_eq!(cpu.mem_peek(0x1812), 0x33);
+    // no easy6502 display or random byte
+    assert!(!cpu.update);
+    assert_eq!(cpu.mem_peek(0x00fe), 0);
+    // ROM stays put
+    assert_eq!(cpu.mem_peek(0x8000), 0x8d);
+}
+
+#[test]
+fn controllers_only_on_the_nes() {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &[0x00])).unwrap();
+    cpu.set_buttons(0, BUTTON_A);
+    cpu.mem_write(0x4016, 1);
+    cpu.mem_write(0x4016, 0);
+    assert_eq!(cpu.mem_read(0x4016), 1);
+    assert_eq!(cpu.mem_read(0x4016), 0);
+
+    // plain RAM on easy6502, so whole 64K images load intact
+    let mut cpu = CPU::new();
+    cpu.set_buttons(0, BUTTON_A);
+    cpu.mem_write(0x4016, 0x5a);
+    cpu.mem_write(0x4017, 0xa5);
+    assert_eq!(cpu.mem_read(0x4016), 0x5a);
+    assert_eq!(cpu.mem_read(0x4017), 0xa5);
+}
+
+#[test]
+fn brk_is_an_interrupt_except_on_easy6502() {
+    // 8000: BRK, then on through $FFFE to the KIL at BRK_JAM
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &[0x00])).unwrap();
+    assert_eq!(cpu.run_frame(), FrameStatus::Jam);
+    assert_eq!(cpu.program_counter, BRK_JAM);
+    assert_eq!(cpu.stack_ptr, 0xfa);
+    assert_eq!(cpu.mem_read_u16(0x01fc), 0x8002);
+    assert_eq!(cpu.mem_peek(0x01fb) & 0b0011_0000, 0b0011_0000);
+    assert_eq!(cpu.status & 0b0000_0100, 0b0000_0100);
+
+    // easy6502 programs end on it
+    let mut cpu = CPU::new();
+    cpu.load_pro(vec![0xe8, 0x00]);
+    assert_eq!(cpu.run_frame(), FrameStatus::Brk);
+    assert_eq!(cpu.stack_ptr, 0xfd);
+}
+
+#[test]
+fn unknown_opcodes_jam() {
+    let mut cpu = CPU::new();
+    cpu.machine = Machine::Bare;
+    // 0000: INX; KIL
+    cpu.mem_write(0x0000, 0xe8);
+    cpu.mem_write(0x0001, 0x02);
+    assert_eq!(cpu.run_frame(), FrameStatus::Jam);
+    assert_eq!(cpu.program_counter, 0x0001);
+    assert_eq!(cpu.register_x, 1);
+
+    // it stays jammed
+    let cycles = cpu.cycles;
+    assert!(cpu.next());
+    assert_eq!(cpu.program_counter, 0x0001);
+    assert_eq!(cpu.cycles, cycles);
+}
+
+#[test]
+fn save_state_keeps_the_machine() {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &[0x00])).unwrap();
+    let state = cpu.save_state();
+
+    let mut other = CPU::new();
+    other.load_rom(&ines(0, &[0xea])).unwrap();
+    other.load_state(&state).unwrap();
+    assert_eq!(other.machine, Machine::Nes);
+    assert_eq!(other.mem_peek(0x8000), 0x00);
+}
@@ -0,0 +1,225 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::joypad::{BUTTON_A, DOWN, RIGHT, START, UP};
+use wasm_nes_emulator::movie::{Frame, Movie, Start};
+
+mod common;
+use common::ines;
+
+// 8000: LDA #$01
+// 8002: STA $4016
+// 8005: LDA #$00
+// 8007: STA $4016
+// 800A: LDX #$00
+// 800C: LDA $4016
+// 800F: STA $20,X
+// 8011: BEQ $8015
+// 8013: INC $11
+// 8015: INX
+// 8016: CPX #$08
+// 8018: BNE $800C
+// 801A: JMP $8000
+const READ_PAD: [u8; 29] = [
+    0xa9, 0x01, 0x8d, 0x16, 0x40, 0xa9, 0x00, 0x8d, 0x16, 0x40, 0xa2, 0x00, 0xad, 0x16, 0x40, 0x95,
+    0x20, 0xf0, 0x02, 0xe6, 0x11, 0xe8, 0xe0, 0x08, 0xd0, 0xf2, 0x4c, 0x00, 0x80,
+];
+
+fn pad_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &READ_PAD)).unwrap();
+    cpu
+}
+
+fn run_frame(cpu: &mut CPU) {
+    let frame = cpu.frame();
+    while cpu.frame() == frame {
+        cpu.next();
+    }
+}
+
+fn input(frame: usize) -> u8 {
+    [BUTTON_A, RIGHT | BUTTON_A, 0, DOWN, START, UP | RIGHT][frame % 6]
+}
+
+fn record(cpu: &mut CPU, frames: usize) -> Movie {
+    cpu.start_recording(false, true);
+    for i in 0..frames {
+        cpu.set_buttons(0, input(i));
+        run_frame(cpu);
+    }
+    cpu.stop_movie().unwrap()
+}
+
+#[test]
+fn joypad_reads_serially() {
+    let mut cpu = pad_cpu();
+    cpu.set_buttons(0, BUTTON_A | START | RIGHT);
+    for _ in 0..3 {
+        cpu.next();
+    }
+    // strobe is high: the A button is read over and over
+    assert_eq!(cpu.mem_read(0x4016), 1);
+    assert_eq!(cpu.mem_read(0x4016), 1);
+    cpu.next();
+
+    let bits: Vec<u8> = (0..9).map(|_| cpu.mem_read(0x4016)).collect();
+    assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 1, 1]);
+    assert_eq!(cpu.mem_read(0x4017), 0);
+}
+
+#[test]
+fn recorded_movie_plays_back() {
+    let mut cpu = pad_cpu();
+    let movie = record(&mut cpu, 20);
+    assert_eq!(movie.frames.len(), 20);
+    assert_eq!(movie.frames[1].pads, [RIGHT | BUTTON_A, 0]);
+    assert!(movie.frames.iter().all(|f| f.ram_hash.is_some()));
+    let recorded = cpu.save_state();
+
+    let mut replay = pad_cpu();
+    replay.play_movie(movie).unwrap();
+    while !replay.movie_finished() {
+        run_frame(&mut replay);
+    }
+    assert_eq!(replay.movie_desync(), None);
+    assert!(replay.save_state() == recorded);
+}
+
+#[test]
+fn changed_input_is_a_desync() {
+    let mut movie = record(&mut pad_cpu(), 10);
+    movie.frames[6].pads[0] = 0xff;
+
+    let mut replay = pad_cpu();
+    replay.play_movie(movie).unwrap();
+    for _ in 0..10 {
+        run_frame(&mut replay);
+    }
+    assert_eq!(replay.movie_desync(), Some(6));
+}
+
+#[test]
+fn movie_from_save_state() {
+    let mut cpu = pad_cpu();
+    cpu.set_buttons(0, UP);
+    for _ in 0..3 {
+        run_frame(&mut cpu);
+    }
+    cpu.start_recording(true, true);
+    for i in 0..5 {
+        cpu.set_buttons(0, input(i));
+        run_frame(&mut cpu);
+    }
+    let fm2 = cpu.export_fm2().unwrap();
+    assert!(fm2.contains("savestate base64:"));
+
+    // the state needs the ROM it was saved with
+    assert!(CPU::new().play_fm2(&fm2).is_err());
+    let mut replay = pad_cpu();
+    replay.play_fm2(&fm2).unwrap();
+    assert_eq!(replay.frame(), 3);
+    for _ in 0..5 {
+        run_frame(&mut replay);
+    }
+    assert!(replay.movie_finished());
+    assert_eq!(replay.movie_desync(), None);
+    assert_eq!(replay.mem_read(0x11), cpu.mem_read(0x11));
+}
+
+#[test]
+fn fm2_round_trip() {
+    let text = "version 3\n\
+                emuVersion 20604\n\
+                rerecordCount 12\n\
+                romFilename smb\n\
+                comment author someone\n\
+                |0|R..U...A|........||\n\
+                |1|........|.L....B.||\n\
+                |0|RLDUTSBA|||\n";
+    let movie = Movie::parse_fm2(text).unwrap();
+    assert_eq!(movie.start, Start::PowerOn);
+    assert_eq!(movie.rerecords, 12);
+    assert_eq!(
+        movie.frames,
+        vec![
+            Frame {
+                commands: 0,
+                pads: [RIGHT | UP | BUTTON_A, 0],
+                ram_hash: None
+            },
+            Frame {
+                commands: 1,
+                pads: [0, 0x42],
+                ram_hash: None
+            },
+            Frame {
+                commands: 0,
+                pads: [0xff, 0],
+                ram_hash: None
+            },
+        ]
+    );
+
+    let exported = movie.to_fm2();
+    assert!(exported.contains("romFilename smb\n"));
+    assert!(exported.contains("comment author someone\n"));
+    assert!(exported.contains("|0|R..U...A|........||\n"));
+    let reparsed = Movie::parse_fm2(&exported).unwrap();
+    assert_eq!(reparsed.frames, movie.frames);
+    assert_eq!(reparsed.to_fm2(), exported);
+
+    let mut with_state = Movie::new(Start::SaveState(CPU::new().save_state()));
+    with_state.frames.push(Frame {
+        ram_hash: Some(0xdead_beef),
+        ..Frame::default()
+    });
+    let reparsed = Movie::parse_fm2(&with_state.to_fm2()).unwrap();
+    assert_eq!(reparsed.start, with_state.start);
+    assert_eq!(reparsed.frames, with_state.frames);
+
+    assert!(Movie::parse_fm2("version 2\n").is_err());
+    assert!(Movie::parse_fm2("|x|........|||\n").is_err());
+}
+
+#[test]
+fn first_frame_commands_are_honoured() {
+    let mut cpu = pad_cpu();
+    for _ in 0..3 {
+        run_frame(&mut cpu);
+    }
+
+    // a soft reset as the movie begins, after its power-on
+    cpu.play_fm2("version 3\n|1|........|........||\n|0|........|........||\n")
+        .unwrap();
+    assert_eq!(cpu.stack_ptr, 0xfa);
+    assert_eq!(cpu.program_counter, 0x8000);
+    assert_eq!(cpu.status & 0b0000_0100, 0b0000_0100);
+}
+
+#[test]
+fn fceux_savestates_are_refused() {
+    // FCEUX states start with "FCSX"
+    let error = Movie::parse_fm2("version 3\nsavestate base64:RkNTWAAAAAA=\n").unwrap_err();
+    assert!(error.contains("unsupported FCEUX savestate"), "{}", error);
+}
+
+#[test]
+fn rerecording_truncates_the_future() {
+    let mut cpu = pad_cpu();
+    cpu.start_recording(false, false);
+    run_frame(&mut cpu);
+    run_frame(&mut cpu);
+    let branch = cpu.save_state();
+    for _ in 0..5 {
+        run_frame(&mut cpu);
+    }
+    assert_eq!(cpu.movie().unwrap().frames.len(), 7);
+
+    cpu.load_state(&branch).unwrap();
+    cpu.set_buttons(0, START);
+    run_frame(&mut cpu);
+    let movie = cpu.movie().unwrap();
+    assert_eq!(movie.frames.len(), 3);
+    assert_eq!(movie.frames[2].pads[0], START);
+    assert_eq!(movie.rerecords, 1);
+}
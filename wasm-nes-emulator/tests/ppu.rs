@@ -0,0 +1,250 @@
+#[macro_use]
+extern crate wasm_nes_emulator;
+use std::convert::TryInto;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::ppu_view::{Sprite, SYSTEM_PALETTE};
+
+mod common;
+use common::ines;
+
+fn nes(program: &[u8]) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, program)).unwrap();
+    cpu
+}
+
+fn pixel(rgba: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+    let i = (y * width + x) * 4;
+    rgba[i..i + 4].try_into().unwrap()
+}
+
+fn rgba(color: u8) -> [u8; 4] {
+    let [r, g, b] = SYSTEM_PALETTE[color as usize];
+    [r, g, b, 0xff]
+}
+
+#[test]
+fn writes_vram_through_the_registers() {
+    let mut cpu = nes(&asm!(
+        "lda #$24",
+        "sta $2006",
+        "lda #$05",
+        "sta $2006",
+        "lda #$42",
+        "sta $2007",
+        "lda #$43",
+        "sta $2007",
+        "lda #$3f",
+        "sta $2006",
+        "lda #$10",
+        "sta $2006",
+        "lda #$0f",
+        "sta $2007",
+        "brk"
+    ));
+    cpu.run();
+    let ppu = cpu.ppu();
+    // horizontal mirroring: $2400 is $2000
+    assert_eq!(ppu.read(0x2005), 0x42);
+    assert_eq!(ppu.read(0x2406), 0x43);
+    // $3F10 mirrors the backdrop colour
+    assert_eq!(ppu.read(0x3f00), 0x0f);
+    assert_eq!(ppu.v, 0x3f11);
+}
+
+#[test]
+fn data_reads_are_buffered() {
+    let mut cpu = nes(&asm!(
+        "lda #$20",
+        "sta $2006",
+        "lda #$00",
+        "sta $2006",
+        "lda #$77",
+        "sta $2007",
+        "lda #$20",
+        "sta $2006",
+        "lda #$00",
+        "sta $2006",
+        "lda $2007",
+        "ldx $2007",
+        "brk"
+    ));
+    cpu.run();
+    assert_eq!(cpu.register_a, 0x00);
+    assert_eq!(cpu.register_x, 0x77);
+}
+
+#[test]
+fn vblank_starts_at_scanline_241() {
+    let mut cpu = nes(&asm!(
+        "wait:",
+        "  bit $2002",
+        "  bpl wait",
+        "  lda $2002",
+        "  brk"
+    ));
+    cpu.run();
+    assert_eq!(cpu.ppu_position().0, 241);
+    // the second read sees the flag cleared by the first
+    assert_eq!(cpu.register_a & 0x80, 0);
+}
+
+#[test]
+fn oam_dma_copies_a_page() {
+    let mut cpu = nes(&asm!(
+        "lda #$30",
+        "sta $0204",
+        "lda #$01",
+        "sta $0205",
+        "lda #$c3",
+        "sta $0206",
+        "lda #$40",
+        "sta $0207",
+        "lda #$02",
+        "sta $4014",
+        "brk"
+    ));
+    let before = cpu.cycles;
+    cpu.run();
+    assert!(cpu.cycles - before > 513);
+    assert_eq!(
+        cpu.sprite(1),
+        Ok(Sprite {
+            index: 1,
+            x: 0x40,
+            y: 0x30,
+            tile: 0x01,
+            palette: 3,
+            behind_background: false,
+            flip_horizontal: true,
+            flip_vertical: true,
+            pattern_table: 0,
+        })
+    );
+    assert!(cpu.sprite(64).is_err());
+}
+
+#[test]
+fn draws_pattern_tables_and_palettes() {
+    let mut rom = ines(
+        0,
+        &asm!(
+            "lda #$3f",
+            "sta $2006",
+            "lda #$00",
+            "sta $2006",
+            "lda #$0f",
+            "sta $2007",
+            "lda #$16",
+            "sta $2007",
+            "lda #$27",
+            "sta $2007",
+            "lda #$30",
+            "sta $2007",
+            "brk"
+        ),
+    );
+    // tile 1 of the right table: top row colour 1, second row colour 3
+    let chr = rom.len() - 0x2000;
+    rom[chr + 0x1010] = 0xff;
+    rom[chr + 0x1011] = 0xff;
+    rom[chr + 0x1019] = 0xff;
+    let mut cpu = CPU::new();
+    cpu.load_rom(&rom).unwrap();
+    cpu.run();
+
+    let tables = cpu.pattern_tables_rgba(0).unwrap();
+    assert_eq!(tables.len(), 256 * 128 * 4);
+    assert_eq!(pixel(&tables, 256, 128 + 8, 0), rgba(0x16));
+    assert_eq!(pixel(&tables, 256, 128 + 15, 1), rgba(0x30));
+    assert_eq!(pixel(&tables, 256, 128 + 8, 2), rgba(0x0f));
+    assert_eq!(pixel(&tables, 256, 8, 0), rgba(0x0f));
+    assert!(cpu.pattern_tables_rgba(8).is_err());
+
+    let palette = cpu.palette_rgba();
+    assert_eq!(palette.len(), 32 * 4);
+    assert_eq!(pixel(&palette, 16, 2, 0), rgba(0x27));
+    assert_eq!(pixel(&palette, 16, 0, 1), rgba(0x0f));
+}
+
+#[test]
+fn outlines_the_scroll_window() {
+    let mut cpu = nes(&asm!(
+        "lda #$3f",
+        "sta $2006",
+        "lda #$00",
+        "sta $2006",
+        "lda #$30",
+        "sta $2007",
+        // $2006 left the nametable bits set
+        "lda #$00",
+        "sta $2000",
+        "lda #16",
+        "sta $2005",
+        "lda #8",
+        "sta $2005",
+        "brk"
+    ));
+    cpu.run();
+    assert_eq!(cpu.ppu().scroll(), (16, 8));
+
+    let nametables = cpu.nametables_rgba();
+    assert_eq!(nametables.len(), 512 * 480 * 4);
+    let white = rgba(0x30);
+    let outline = [!white[0], !white[1], !white[2], 0xff];
+    assert_eq!(pixel(&nametables, 512, 0, 0), white);
+    assert_eq!(pixel(&nametables, 512, 16, 8), outline);
+    assert_eq!(pixel(&nametables, 512, 16 + 255, 8 + 239), outline);
+    assert_eq!(pixel(&nametables, 512, 17, 9), white);
+}
+
+#[test]
+fn views_show_the_chosen_scanline() {
+    let mut cpu = nes(&asm!(
+        "lda #$3f",
+        "sta $2006",
+        "lda #$00",
+        "sta $2006",
+        "lda #$01",
+        "sta $2007",
+        "wait:",
+        "bit $2002",
+        "bpl wait",
+        "lda #$3f",
+        "sta $2006",
+        "lda #$00",
+        "sta $2006",
+        "lda #$02",
+        "sta $2007",
+        "brk"
+    ));
+    assert!(cpu.set_ppu_view_scanline(262).is_err());
+    cpu.set_ppu_view_scanline(100).unwrap();
+    cpu.run();
+    assert_eq!(cpu.ppu().palette[0], 0x02);
+    assert_eq!(cpu.viewed_ppu().palette[0], 0x01);
+    assert_eq!(pixel(&cpu.palette_rgba(), 16, 0, 0), rgba(0x01));
+
+    cpu.clear_ppu_view_scanline();
+    assert_eq!(pixel(&cpu.palette_rgba(), 16, 0, 0), rgba(0x02));
+}
+
+#[test]
+fn save_states_keep_the_ppu() {
+    let mut cpu = nes(&asm!(
+        "lda #$21",
+        "sta $2006",
+        "lda #$00",
+        "sta $2006",
+        "lda #$55",
+        "sta $2007",
+        "brk"
+    ));
+    cpu.run();
+    let state = cpu.save_state();
+
+    let mut restored = nes(&[0x00]);
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.ppu().read(0x2100), 0x55);
+    assert_eq!(restored.ppu().v, 0x2101);
+}
@@ -0,0 +1,147 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::asm::assemble;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::profiler::RoutineProfile;
+
+mod common;
+use common::ines;
+
+const CALLS: &str = "
+    main:
+      jsr work
+      jsr work
+      brk
+    work:
+      nop
+      jsr inner
+      rts
+    inner:
+      nop
+      rts
+";
+
+fn profiled(source: &str) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load_asm(source).unwrap();
+    cpu.start_profiler();
+    cpu
+}
+
+fn routine(cpu: &CPU, name: &str, last_frame: bool) -> RoutineProfile {
+    cpu.profile(last_frame)
+        .into_iter()
+        .find(|r| r.name == name)
+        .unwrap()
+}
+
+#[test]
+fn cycles_are_charged_to_the_running_subroutine() {
+    let mut cpu = profiled(CALLS);
+    cpu.run();
+
+    // each JSR is charged to the caller and each RTS to the callee
+    let work = routine(&cpu, "work", false);
+    assert_eq!((work.calls, work.inclusive, work.exclusive), (2, 44, 28));
+    let inner = routine(&cpu, "inner", false);
+    assert_eq!((inner.calls, inner.inclusive, inner.exclusive), (2, 16, 16));
+    let main = routine(&cpu, "main", false);
+    assert_eq!((main.calls, main.inclusive, main.exclusive), (0, 56, 12));
+
+    let names: Vec<_> = cpu.profile(false).into_iter().map(|r| r.name).collect();
+    assert_eq!(names, vec!["main", "work", "inner"]);
+    assert_eq!(routine(&cpu, "work", false).addr, 0x0607);
+}
+
+#[test]
+fn folded_stacks_follow_the_call_paths() {
+    let mut cpu = profiled(CALLS);
+    cpu.run();
+    assert_eq!(
+        cpu.profile_folded(false),
+        "main 12\nmain;work 28\nmain;work;inner 16"
+    );
+
+    let report = cpu.profile_report(false);
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("routine"));
+    assert!(lines[2].starts_with("work"));
+    assert!(lines[2].ends_with("78.6"));
+}
+
+#[test]
+fn unlabelled_routines_are_named_by_address() {
+    let mut cpu = CPU::new();
+    cpu.load_pro(vec![0x20, 0x05, 0x06, 0x00, 0x00, 0x60]);
+    cpu.start_profiler();
+    cpu.run();
+    assert_eq!(cpu.profile_folded(false), "$0600 6\n$0600;$0605 6");
+}
+
+#[test]
+fn the_last_frame_is_kept_apart() {
+    let mut cpu = profiled(
+        "
+        main:
+          jsr work
+          jmp main
+        work:
+          ldx #10
+        loop:
+          dex
+          bne loop
+          rts
+        ",
+    );
+    cpu.run_frame();
+    cpu.run_frame();
+    cpu.run_frame();
+
+    let total = routine(&cpu, "work", false);
+    let last = routine(&cpu, "work", true);
+    assert!(last.calls > 0);
+    assert!(total.calls > 2 * last.calls);
+    // a frame is 29780.67 cycles, ended by whichever instruction crosses it
+    let cycles: u64 = cpu.profile(true).iter().map(|r| r.exclusive).sum();
+    assert!((29774..29788).contains(&cycles), "{}", cycles);
+
+    cpu.stop_profiler();
+    assert!(cpu.profile(false).is_empty());
+    assert_eq!(cpu.profile_folded(false), "");
+}
+
+#[test]
+fn nmi_handlers_count_as_calls() {
+    let assembly = assemble(
+        "
+        .org $8000
+          lda #$80
+          sta $2000
+        wait:
+          jmp wait
+        nmi:
+          inc $00
+          rti
+        ",
+    )
+    .unwrap();
+    let mut rom = ines(0, &assembly.bytes);
+    let nmi = assembly.labels["nmi"];
+    rom[16 + 0x3ffa..16 + 0x3ffc].copy_from_slice(&nmi.to_le_bytes());
+    let mut cpu = CPU::new();
+    cpu.load_rom(&rom).unwrap();
+    cpu.start_profiler();
+    for _ in 0..3 {
+        cpu.run_frame();
+    }
+
+    let handler = cpu
+        .profile(false)
+        .into_iter()
+        .find(|r| r.addr == nmi)
+        .unwrap();
+    assert_eq!(cpu.mem_peek(0x00) as u64, handler.calls);
+    assert!(handler.calls >= 2);
+    // the interrupt sequence, INC and RTI
+    assert_eq!(handler.exclusive, handler.calls * (7 + 5 + 6));
+}
@@ -0,0 +1,104 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::ram_search::{SearchCompare, SearchFormat, SearchSize};
+
+use SearchCompare::*;
+use SearchFormat::*;
+use SearchSize::*;
+
+// work RAM plus PRG-RAM
+const ALL: usize = 0x0800 + 0x2000;
+
+#[test]
+fn starts_with_every_address() {
+    let mut cpu = CPU::new();
+    assert!(cpu.ram_search_previous(Byte, Unsigned, Equal).is_err());
+    cpu.ram_search_start();
+    assert_eq!(cpu.ram_search_count(), ALL);
+    let candidates = cpu.ram_search_candidates();
+    assert_eq!(candidates[0x07ff], 0x07ff);
+    assert_eq!(candidates[0x0800], 0x6000);
+    assert_eq!(*candidates.last().unwrap(), 0x7fff);
+    cpu.ram_search_stop();
+    assert_eq!(cpu.ram_search_count(), 0);
+}
+
+#[test]
+fn narrows_down_a_lives_counter() {
+    let mut cpu = CPU::new();
+    cpu.mem_write(0x0075, 3);
+    cpu.mem_write(0x0200, 3);
+    cpu.ram_search_start();
+    assert_eq!(cpu.ram_search_constant(Byte, Unsigned, Equal, 3), Ok(2));
+
+    // lose a life
+    cpu.mem_write(0x0075, 2);
+    assert_eq!(cpu.ram_search_previous(Byte, Unsigned, Less), Ok(1));
+    assert_eq!(cpu.ram_search_candidates(), vec![0x0075]);
+    assert_eq!(
+        cpu.ram_search_read_previous(0x0075, Byte, Unsigned),
+        Some(2)
+    );
+
+    // previous means the value at the last search
+    assert_eq!(cpu.ram_search_previous(Byte, Unsigned, Equal), Ok(1));
+    assert_eq!(cpu.ram_search_previous(Byte, Unsigned, NotEqual), Ok(0));
+}
+
+#[test]
+fn compares_changes_against_previous() {
+    let mut cpu = CPU::new();
+    cpu.ram_search_start();
+    cpu.mem_write(0x0010, 1);
+    cpu.mem_write(0x6000, 0xff);
+    assert_eq!(cpu.ram_search_previous(Byte, Unsigned, NotEqual), Ok(2));
+    assert_eq!(cpu.ram_search_candidates(), vec![0x0010, 0x6000]);
+
+    // $FF is -1, less than 0
+    cpu.ram_search_start();
+    cpu.mem_write(0x0010, 2);
+    cpu.mem_write(0x6000, 0xfe);
+    assert_eq!(cpu.ram_search_previous(Byte, Signed, Greater), Ok(1));
+    assert_eq!(cpu.ram_search_candidates(), vec![0x0010]);
+}
+
+#[test]
+fn reads_signed_and_bcd_values() {
+    let mut cpu = CPU::new();
+    cpu.mem_write(0x0020, 0x34);
+    cpu.mem_write(0x0021, 0x12);
+    cpu.mem_write(0x0030, 0xfe);
+    cpu.mem_write(0x0031, 0xff);
+    cpu.mem_write(0x0040, 0x1a);
+
+    assert_eq!(cpu.ram_search_read(0x0020, Word, Unsigned), Some(0x1234));
+    assert_eq!(cpu.ram_search_read(0x0020, Word, Bcd), Some(1234));
+    assert_eq!(cpu.ram_search_read(0x0020, Byte, Bcd), Some(34));
+    assert_eq!(cpu.ram_search_read(0x0030, Byte, Signed), Some(-2));
+    assert_eq!(cpu.ram_search_read(0x0030, Word, Signed), Some(-2));
+    assert_eq!(cpu.ram_search_read(0x0030, Word, Unsigned), Some(0xfffe));
+    assert_eq!(cpu.ram_search_read(0x0040, Byte, Bcd), None);
+    // the word would run past the end of work RAM
+    assert_eq!(cpu.ram_search_read(0x07ff, Word, Unsigned), None);
+    assert_eq!(cpu.ram_search_read(0x2000, Byte, Unsigned), None);
+}
+
+#[test]
+fn finds_a_bcd_score() {
+    let mut cpu = CPU::new();
+    cpu.ram_search_start();
+    // score 1250, stored low digits first
+    cpu.mem_write(0x0100, 0x50);
+    cpu.mem_write(0x0101, 0x12);
+    assert_eq!(cpu.ram_search_constant(Word, Bcd, Equal, 1250), Ok(1));
+    assert_eq!(cpu.ram_search_candidates(), vec![0x0100]);
+
+    cpu.mem_write(0x0100, 0x00);
+    cpu.mem_write(0x0101, 0x13);
+    assert_eq!(cpu.ram_search_previous(Word, Bcd, Greater), Ok(1));
+    assert_eq!(cpu.ram_search_read(0x0100, Word, Bcd), Some(1300));
+
+    // an invalid digit drops the candidate
+    cpu.mem_write(0x0100, 0x0f);
+    assert_eq!(cpu.ram_search_previous(Word, Bcd, NotEqual), Ok(0));
+}
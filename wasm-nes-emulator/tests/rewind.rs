@@ -0,0 +1,152 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::rewind::{decode, encode};
+
+// 0600: INC $10
+// 0602: LDA $10
+// 0604: STA $0300,X
+// 0607: INX
+// 0608: INX
+// 0609: JMP $0600
+fn counter_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xe6, 0x10, 0xa5, 0x10, 0x9d, 0x00, 0x03, 0xe8, 0xe8, 0x4c, 0x00, 0x06,
+    ]);
+    cpu.reset();
+    cpu
+}
+
+// runs to the start of the next frame and returns the state there
+fn run_frame(cpu: &mut CPU) -> Vec<u8> {
+    let frame = cpu.frame();
+    while cpu.frame() == frame {
+        cpu.next();
+    }
+    cpu.save_state()
+}
+
+#[test]
+fn delta_encoding_round_trip() {
+    let mut data = vec![0; 1000];
+    data[3] = 1;
+    data[4] = 2;
+    data[6] = 3;
+    data[999] = 0xff;
+    let encoded = encode(&data);
+    assert!(encoded.len() < 20);
+    assert_eq!(decode(&encoded), data);
+
+    assert_eq!(decode(&encode(&[])), Vec::<u8>::new());
+    assert_eq!(decode(&encode(&[0, 0, 0])), vec![0, 0, 0]);
+    assert_eq!(decode(&encode(&[7; 300])), vec![7; 300]);
+}
+
+#[test]
+fn rewind_walks_back_frame_by_frame() {
+    let mut cpu = counter_cpu();
+    let start = cpu.save_state();
+    cpu.enable_rewind(1 << 20, 1);
+
+    let mut frames = vec![];
+    for _ in 0..10 {
+        frames.push(run_frame(&mut cpu));
+    }
+    assert_eq!(cpu.rewind_len(), 11);
+
+    // part way into the next frame, so the first rewind lands on its start
+    for _ in 0..100 {
+        cpu.next();
+    }
+    for expected in frames.iter().rev() {
+        assert!(cpu.rewind());
+        assert!(cpu.save_state() == *expected);
+    }
+    assert!(cpu.rewind());
+    assert!(cpu.save_state() == start);
+    assert!(!cpu.rewind());
+}
+
+#[test]
+fn deltas_are_small() {
+    let mut cpu = counter_cpu();
+    cpu.enable_rewind(1 << 20, 1);
+    for _ in 0..20 {
+        run_frame(&mut cpu);
+    }
+    let full = cpu.save_state().len();
+    // one full state plus 20 deltas of a few hundred bytes each
+    assert!(cpu.rewind_size() < full * 2);
+}
+
+#[test]
+fn budget_drops_oldest_snapshots() {
+    let mut cpu = counter_cpu();
+    let full = cpu.save_state().len();
+    cpu.enable_rewind(full + 2000, 1);
+    for _ in 0..50 {
+        run_frame(&mut cpu);
+    }
+    assert!(cpu.rewind_size() <= full + 2000);
+    assert!(cpu.rewind_len() < 50);
+
+    let mut steps = 0;
+    while cpu.rewind() {
+        steps += 1;
+    }
+    assert!(steps > 1);
+    assert!(cpu.frame() > 0);
+}
+
+#[test]
+fn interval_skips_frames() {
+    let mut cpu = counter_cpu();
+    cpu.enable_rewind(1 << 20, 4);
+    let mut frames = vec![];
+    for _ in 0..8 {
+        frames.push(run_frame(&mut cpu));
+    }
+    assert_eq!(cpu.rewind_len(), 3);
+
+    assert!(cpu.rewind());
+    assert!(cpu.save_state() == frames[3]);
+}
+
+#[test]
+fn rewinding_restarts_the_interval() {
+    let mut cpu = counter_cpu();
+    cpu.enable_rewind(1 << 20, 4);
+    for _ in 0..10 {
+        run_frame(&mut cpu);
+    }
+    assert_eq!(cpu.rewind_len(), 3);
+
+    // back to the snapshot at frame 8, the next one is due at frame 12
+    assert!(cpu.rewind());
+    for _ in 0..3 {
+        run_frame(&mut cpu);
+    }
+    assert_eq!(cpu.rewind_len(), 3);
+    run_frame(&mut cpu);
+    assert_eq!(cpu.rewind_len(), 4);
+}
+
+#[test]
+fn debugger_steps_back_through_frames() {
+    let mut cpu = counter_cpu();
+    cpu.enable_rewind(1 << 20, 1);
+    for _ in 0..5 {
+        run_frame(&mut cpu);
+    }
+    let now = cpu.save_state();
+    let frame = cpu.frame();
+
+    assert!(cpu.step_back());
+    assert!(cpu.step_back());
+    assert_eq!(cpu.frame(), frame - 2);
+
+    // replaying forward reaches the same state again
+    run_frame(&mut cpu);
+    assert!(cpu.save_state() != now);
+    assert!(run_frame(&mut cpu) == now);
+}
@@ -0,0 +1,209 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::cpu::{FrameStatus, CPU};
+use wasm_nes_emulator::savestate::{read_chunks, StateWriter, VERSION};
+
+mod common;
+use common::{ines, snake, Rng};
+
+#[test]
+fn round_trip_registers_and_memory() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![0xa9, 0x42, 0xa2, 0x07, 0x85, 0x10, 0x00]);
+    let state = cpu.save_state();
+
+    let mut restored = CPU::new();
+    restored.load_state(&state).unwrap();
+
+    assert_eq!(restored.register_a, 0x42);
+    assert_eq!(restored.register_x, 0x07);
+    assert_eq!(restored.program_counter, cpu.program_counter);
+    assert_eq!(restored.cycles, cpu.cycles);
+    assert_eq!(restored.mem_read(0x10), 0x42);
+    assert_eq!(restored.save_state(), state);
+}
+
+#[test]
+fn header_and_chunks() {
+    let state = CPU::new().save_state();
+    assert_eq!(&state[0..4], b"NESS");
+
+    let (version, chunks) = read_chunks(&state).unwrap();
+    assert_eq!(version, VERSION);
+    let tags: Vec<&[u8]> = chunks.iter().map(|(tag, _)| &tag[..]).collect();
+    assert_eq!(
+        tags,
+        vec![
+            &b"CPU "[..],
+            &b"RAM "[..],
+            &b"JOY "[..],
+            &b"PPU "[..],
+            &b"APU "[..]
+        ]
+    );
+
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &[0xea])).unwrap();
+    let state = cpu.save_state();
+    let (_, chunks) = read_chunks(&state).unwrap();
+    assert_eq!(chunks[4], (*b"APU ", &[][..]));
+    assert_eq!(chunks[5], (*b"MAP ", &[0][..]));
+    cpu.load_state(&state).unwrap();
+    assert_eq!(cpu.save_state(), state);
+}
+
+#[test]
+fn states_load_only_on_their_machine() {
+    let mut easy = CPU::new();
+    easy.register_a = 1;
+    let easy_state = easy.save_state();
+
+    let mut nes = CPU::new();
+    nes.load_rom(&ines(0, &[0xea])).unwrap();
+    nes.register_a = 2;
+    let nes_state = nes.save_state();
+
+    // the NES state needs a cartridge, the easy6502 one cannot have one
+    assert!(easy.load_state(&nes_state).is_err());
+    assert_eq!(easy.register_a, 1);
+    assert!(nes.load_state(&easy_state).is_err());
+    assert_eq!(nes.register_a, 2);
+
+    // a cartridge of another mapper
+    let mut w = StateWriter::new();
+    w.chunk(b"MAP ", |w| w.u8(1));
+    assert!(nes.load_state(&w.finish()).is_err());
+    assert_eq!(nes.register_a, 2);
+}
+
+#[test]
+fn unknown_chunks_are_skipped() {
+    let mut cpu = CPU::new();
+    cpu.register_y = 9;
+    let (_, chunks) = read_chunks(&cpu.save_state())
+        .map(|(v, c)| {
+            (
+                v,
+                c.iter().map(|(t, p)| (*t, p.to_vec())).collect::<Vec<_>>(),
+            )
+        })
+        .unwrap();
+
+    // a state from a newer build: extra chunk, extra trailing CPU field
+    let mut w = StateWriter::new();
+    w.chunk(b"FUTR", |w| w.u32(0xdead_beef));
+    for (tag, payload) in &chunks {
+        w.chunk(tag, |w| {
+            w.bytes(payload);
+            if tag == b"CPU " {
+                w.u8(0xff);
+            }
+        });
+    }
+
+    let mut restored = CPU::new();
+    restored.load_state(&w.finish()).unwrap();
+    assert_eq!(restored.register_y, 9);
+}
+
+#[test]
+fn newer_versions_are_rejected() {
+    let mut cpu = CPU::new();
+    cpu.register_a = 1;
+
+    let mut newer = CPU::new();
+    newer.register_a = 2;
+    let mut state = newer.save_state();
+    state[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+
+    assert!(cpu.load_state(&state).is_err());
+    assert_eq!(cpu.register_a, 1);
+}
+
+#[test]
+fn older_versions_keep_missing_fields() {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&ines(0, &[0xea])).unwrap();
+    let machine = cpu.machine;
+
+    // a version 0 CPU chunk, written before the seed and machine fields
+    let mut w = StateWriter::new();
+    w.chunk(b"CPU ", |w| {
+        w.bytes(&[3, 0, 0, 0x24, 0xfd]);
+        w.u16(0x0600);
+        w.u64(0);
+        w.bool(false);
+    });
+    let mut state = w.finish();
+    state[4..6].copy_from_slice(&0u16.to_le_bytes());
+
+    cpu.load_state(&state).unwrap();
+    assert_eq!(cpu.register_a, 3);
+    assert_eq!(cpu.program_counter, 0x0600);
+    assert_eq!(cpu.machine, machine);
+}
+
+#[test]
+fn bad_state_leaves_machine_untouched() {
+    let mut cpu = CPU::new();
+    cpu.register_a = 1;
+    cpu.mem_write(0x20, 0x55);
+
+    let mut good = CPU::new();
+    good.register_a = 2;
+    let mut truncated = good.save_state();
+    truncated.truncate(truncated.len() - 1);
+
+    assert!(cpu.load_state(b"nope").is_err());
+    assert!(cpu.load_state(&truncated).is_err());
+
+    // a RAM chunk that is too short fails after the CPU chunk loaded
+    let mut w = StateWriter::new();
+    w.chunk(b"CPU ", |w| {
+        w.bytes(&[3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+    });
+    w.chunk(b"RAM ", |w| w.bytes(&[0; 16]));
+    assert!(cpu.load_state(&w.finish()).is_err());
+
+    assert_eq!(cpu.register_a, 1);
+    assert_eq!(cpu.mem_read(0x20), 0x55);
+}
+
+/// Work RAM and the display, which is what a frame leaves behind.
+fn frame_memory(cpu: &CPU) -> Vec<u8> {
+    (0..0x0800).map(|addr| cpu.mem_peek(addr)).collect()
+}
+
+#[test]
+fn reloaded_game_replays_identically() {
+    let mut cpu = snake();
+    cpu.set_random_seed(7);
+    for _ in 0..5 {
+        cpu.run_frame();
+    }
+    let state = cpu.save_state();
+
+    let play = |cpu: &mut CPU| {
+        let mut keys = Rng(3);
+        let mut frames = vec![];
+        for _ in 0..20 {
+            cpu.key_press(b"wasd"[keys.next_byte() as usize % 4]);
+            let status = cpu.run_frame();
+            frames.push((status, cpu.framebuffer(), frame_memory(cpu)));
+            if status == FrameStatus::Brk {
+                break;
+            }
+        }
+        frames
+    };
+    let expected = play(&mut cpu);
+    assert!(expected.len() > 1);
+    assert!(expected.windows(2).any(|w| w[0].1 != w[1].1));
+
+    let mut reloaded = CPU::new();
+    reloaded.load_state(&state).unwrap();
+    let frames = play(&mut reloaded);
+    assert_eq!(frames.len(), expected.len());
+    for (frame, (got, want)) in frames.iter().zip(&expected).enumerate() {
+        assert!(got == want, "frame {} differs", frame);
+    }
+}
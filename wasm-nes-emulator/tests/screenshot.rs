@@ -0,0 +1,190 @@
+extern crate wasm_nes_emulator;
+use std::convert::TryInto;
+
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::ppu_view::SYSTEM_PALETTE;
+use wasm_nes_emulator::screenshot::{adler32, crc32, PALETTE};
+
+mod common;
+use common::ines;
+
+struct Png {
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+}
+
+// Reads back what encode_png writes: checks every chunk's CRC and unpacks
+// the stored deflate blocks.
+fn decode(png: &[u8]) -> Png {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let mut pos = 8;
+    let mut header = vec![];
+    let mut idat = vec![];
+    while pos < png.len() {
+        let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+        let body = &png[pos + 4..pos + 8 + len];
+        let crc = u32::from_be_bytes(png[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+        assert_eq!(crc32(body), crc);
+        match &body[..4] {
+            b"IHDR" => header = body[4..].to_vec(),
+            b"IDAT" => idat.extend_from_slice(&body[4..]),
+            b"IEND" => {}
+            kind => panic!("unexpected chunk {:?}", kind),
+        }
+        pos += 12 + len;
+    }
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    assert_eq!(&header[8..], &[8, 2, 0, 0, 0]);
+
+    assert_eq!(u16::from_be_bytes([idat[0], idat[1]]) % 31, 0);
+    let mut data = vec![];
+    let mut pos = 2;
+    loop {
+        let last = idat[pos] & 1 == 1;
+        assert_eq!(idat[pos] & 6, 0, "not a stored block");
+        let len = u16::from_le_bytes([idat[pos + 1], idat[pos + 2]]) as usize;
+        let nlen = u16::from_le_bytes([idat[pos + 3], idat[pos + 4]]) as usize;
+        assert_eq!(len ^ 0xffff, nlen);
+        data.extend_from_slice(&idat[pos + 5..pos + 5 + len]);
+        pos += 5 + len;
+        if last {
+            break;
+        }
+    }
+    assert_eq!(
+        adler32(&data),
+        u32::from_be_bytes(idat[pos..pos + 4].try_into().unwrap())
+    );
+
+    let stride = width as usize * 3 + 1;
+    assert_eq!(data.len(), stride * height as usize);
+    let rgb = data
+        .chunks(stride)
+        .flat_map(|row| {
+            assert_eq!(row[0], 0);
+            row[1..].to_vec()
+        })
+        .collect();
+    Png { width, height, rgb }
+}
+
+fn pixel(png: &Png, x: usize, y: usize) -> [u8; 3] {
+    let i = (y * png.width as usize + x) * 3;
+    png.rgb[i..i + 3].try_into().unwrap()
+}
+
+#[test]
+fn checksums() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+}
+
+#[test]
+fn framebuffer_holds_palette_indices() {
+    let mut cpu = CPU::new();
+    cpu.mem_write(0x0200, 0x01);
+    cpu.mem_write(0x021f, 0x12);
+    cpu.mem_write(0x05ff, 0x0e);
+
+    let pixels = cpu.framebuffer();
+    assert_eq!(pixels.len(), 32 * 32);
+    assert_eq!(pixels[0], 1);
+    // only the low nibble picks the colour
+    assert_eq!(pixels[31], 2);
+    assert_eq!(pixels[1023], 14);
+    assert_eq!(pixels.iter().filter(|&&p| p != 0).count(), 3);
+}
+
+#[test]
+fn png_matches_the_display() {
+    let mut cpu = CPU::new();
+    cpu.mem_write(0x0200, 1);
+    cpu.mem_write(0x0221, 2);
+    cpu.mem_write(0x05ff, 7);
+
+    let png = decode(&cpu.screenshot_png(1).unwrap());
+    assert_eq!((png.width, png.height), (32, 32));
+    assert_eq!(pixel(&png, 0, 0), PALETTE[1]);
+    assert_eq!(pixel(&png, 1, 1), PALETTE[2]);
+    assert_eq!(pixel(&png, 31, 31), PALETTE[7]);
+    assert_eq!(pixel(&png, 1, 0), PALETTE[0]);
+}
+
+#[test]
+fn png_scales_by_whole_numbers() {
+    let mut cpu = CPU::new();
+    cpu.mem_write(0x0221, 5);
+
+    // 32*16 pixels a row spans several stored blocks
+    let png = decode(&cpu.screenshot_png(16).unwrap());
+    assert_eq!((png.width, png.height), (512, 512));
+    for (x, y) in [(16, 16), (31, 31), (16, 31)] {
+        assert_eq!(pixel(&png, x, y), PALETTE[5]);
+    }
+    for (x, y) in [(15, 16), (32, 16), (16, 32)] {
+        assert_eq!(pixel(&png, x, y), PALETTE[0]);
+    }
+
+    assert!(cpu.screenshot_png(0).is_err());
+}
+
+// Writes `data` to the PPU from `addr` on, then resets the scroll.
+fn ppu_write(cpu: &mut CPU, addr: u16, data: &[u8]) {
+    cpu.mem_write(0x2006, (addr >> 8) as u8);
+    cpu.mem_write(0x2006, addr as u8);
+    for &byte in data {
+        cpu.mem_write(0x2007, byte);
+    }
+    cpu.mem_write(0x2000, 0);
+    cpu.mem_write(0x2005, 0);
+    cpu.mem_write(0x2005, 0);
+}
+
+#[test]
+fn nes_picture_comes_from_the_ppu() {
+    // tile 1: a solid top row in colour 1; tile 2: one dot in colour 2
+    let mut rom = ines(0, &[0x00]);
+    let chr = 16 + 0x4000;
+    rom[chr + 16] = 0xff;
+    rom[chr + 32 + 8] = 0x80;
+    let mut cpu = CPU::new();
+    cpu.load_rom(&rom).unwrap();
+
+    ppu_write(&mut cpu, 0x3f00, &[0x0f, 0x21]);
+    ppu_write(&mut cpu, 0x3f12, &[0x16]);
+    // tile 1 at column 1, row 1
+    ppu_write(&mut cpu, 0x2021, &[0x01]);
+    // tile 2 with its top row on line 20 at x 40, and again behind the
+    // background over tile 1
+    cpu.mem_write(0x2003, 0);
+    for byte in [19, 2, 0x00, 40, 7, 2, 0x20, 8] {
+        cpu.mem_write(0x2004, byte);
+    }
+
+    // nothing but the backdrop until rendering is on
+    assert!(cpu.framebuffer().iter().all(|&p| p == 0x0f));
+    cpu.mem_write(0x2001, 0x1e);
+
+    let pixels = cpu.framebuffer();
+    assert_eq!(pixels.len(), 256 * 240);
+    let at = |x: usize, y: usize| pixels[y * 256 + x];
+    assert_eq!(at(8, 8), 0x21);
+    assert_eq!(at(15, 8), 0x21);
+    assert_eq!(at(16, 8), 0x0f);
+    assert_eq!(at(8, 9), 0x0f);
+    assert_eq!(at(40, 20), 0x16);
+    assert_eq!(at(41, 20), 0x0f);
+
+    let png = decode(&cpu.screenshot_png(1).unwrap());
+    assert_eq!((png.width, png.height), (256, 240));
+    assert_eq!(pixel(&png, 8, 8), SYSTEM_PALETTE[0x21]);
+    assert_eq!(pixel(&png, 40, 20), SYSTEM_PALETTE[0x16]);
+
+    // scrolled 8 pixels right, tile 1 starts at the left edge
+    cpu.mem_write(0x2005, 8);
+    cpu.mem_write(0x2005, 0);
+    assert_eq!(cpu.framebuffer()[8 * 256], 0x21);
+}
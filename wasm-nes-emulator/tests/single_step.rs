@@ -0,0 +1,387 @@
+extern crate wasm_nes_emulator;
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::debugger::Access;
+use wasm_nes_emulator::machine::Machine;
+use wasm_nes_emulator::opcodes;
+
+// Runs Tom Harte's SingleStepTests for the NES 2A03 (the `nes6502/v1` set,
+// no decimal mode). The JSON files are not checked in; put 00.json..ff.json
+// in tests/single_step or point SINGLE_STEP_TESTS at them.
+//
+// Every case sets up registers and RAM, runs one instruction and compares
+// registers, RAM and every bus cycle, reads and writes, in order. Opcodes
+// missing from the core's table jam the CPU, so they are expected to fail
+// and listed in UNIMPLEMENTED. Any other opcode that fails or panics fails
+// the test, and so does a listed one that starts passing.
+fn fixture_dir() -> PathBuf {
+    std::env::var_os("SINGLE_STEP_TESTS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/single_step"))
+}
+
+/// The unofficial opcodes, which the core does not implement.
+const UNIMPLEMENTED: &[u8] = &[
+    0x02, 0x03, 0x04, 0x07, 0x0b, 0x0c, 0x0f, 0x12, 0x13, 0x14, 0x17, 0x1a, 0x1b, 0x1c, 0x1f, 0x22,
+    0x23, 0x27, 0x2b, 0x2f, 0x32, 0x33, 0x34, 0x37, 0x3a, 0x3b, 0x3c, 0x3f, 0x42, 0x43, 0x44, 0x47,
+    0x4b, 0x4f, 0x52, 0x53, 0x54, 0x57, 0x5a, 0x5b, 0x5c, 0x5f, 0x62, 0x63, 0x64, 0x67, 0x6b, 0x6f,
+    0x72, 0x73, 0x74, 0x77, 0x7a, 0x7b, 0x7c, 0x7f, 0x80, 0x82, 0x83, 0x87, 0x89, 0x8b, 0x8f, 0x92,
+    0x93, 0x97, 0x9b, 0x9c, 0x9e, 0x9f, 0xa3, 0xa7, 0xab, 0xaf, 0xb2, 0xb3, 0xb7, 0xbb, 0xbf, 0xc2,
+    0xc3, 0xc7, 0xcb, 0xcf, 0xd2, 0xd3, 0xd4, 0xd7, 0xda, 0xdb, 0xdc, 0xdf, 0xe2, 0xe3, 0xe7, 0xeb,
+    0xef, 0xf2, 0xf3, 0xf4, 0xf7, 0xfa, 0xfb, 0xfc, 0xff,
+];
+
+/// A CPU on flat RAM, where BRK goes through $FFFE.
+fn bare_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.machine = Machine::Bare;
+    cpu.debugger_mut().log_bus(true);
+    cpu
+}
+
+fn field(state: &Value, name: &str) -> u64 {
+    state[name].as_u64().unwrap_or(0)
+}
+
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|e| (e[0].as_u64().unwrap() as u16, e[1].as_u64().unwrap() as u8))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+type Cycle = (u16, u8, Access);
+
+fn cycles(case: &Value) -> Vec<Cycle> {
+    case["cycles"]
+        .as_array()
+        .map(|cycles| {
+            cycles
+                .iter()
+                .map(|c| {
+                    let access = if c[2] == "write" {
+                        Access::Write
+                    } else {
+                        Access::Read
+                    };
+                    (
+                        c[0].as_u64().unwrap() as u16,
+                        c[1].as_u64().unwrap() as u8,
+                        access,
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn format_cycle(cycle: Option<&Cycle>) -> String {
+    match cycle {
+        Some((addr, value, Access::Read)) => format!("read {:02X} at {:04X}", value, addr),
+        Some((addr, value, Access::Write)) => format!("write {:02X} at {:04X}", value, addr),
+        None => "nothing".to_string(),
+    }
+}
+
+fn run_case(cpu: &mut CPU, case: &Value) -> Result<(), String> {
+    let initial = &case["initial"];
+    let expected = &case["final"];
+
+    cpu.program_counter = field(initial, "pc") as u16;
+    cpu.stack_ptr = field(initial, "s") as u8;
+    cpu.register_a = field(initial, "a") as u8;
+    cpu.register_x = field(initial, "x") as u8;
+    cpu.register_y = field(initial, "y") as u8;
+    cpu.status = field(initial, "p") as u8;
+    for (addr, value) in ram(initial) {
+        cpu.mem_write(addr, value);
+    }
+    cpu.cycles = 0;
+    cpu.debugger_mut().take_bus_log();
+
+    cpu.next();
+
+    let mut errors = vec![];
+    let mut check = |what: &str, got: u64, want: u64| {
+        if got != want {
+            errors.push(format!("{} {:X}, expected {:X}", what, got, want));
+        }
+    };
+    check("PC", cpu.program_counter as u64, field(expected, "pc"));
+    check("S", cpu.stack_ptr as u64, field(expected, "s"));
+    check("A", cpu.register_a as u64, field(expected, "a"));
+    check("X", cpu.register_x as u64, field(expected, "x"));
+    check("Y", cpu.register_y as u64, field(expected, "y"));
+    // B and bit 5 only exist on the stack
+    check("P", (cpu.status & 0xcf) as u64, field(expected, "p") & 0xcf);
+    for (addr, value) in ram(expected) {
+        check(
+            &format!("[{:04X}]", addr),
+            cpu.mem_peek(addr) as u64,
+            value as u64,
+        );
+    }
+
+    let want = cycles(case);
+    check("cycles", cpu.cycles, want.len() as u64);
+
+    let got: Vec<Cycle> = cpu
+        .debugger_mut()
+        .take_bus_log()
+        .into_iter()
+        .map(|a| (a.addr, a.value, a.access))
+        .collect();
+    if let Some(i) = (0..got.len().max(want.len())).find(|&i| got.get(i) != want.get(i)) {
+        errors.push(format!(
+            "cycle {}: {}, expected {}",
+            i + 1,
+            format_cycle(got.get(i)),
+            format_cycle(want.get(i))
+        ));
+    }
+
+    // leave RAM clean for the next case
+    for (addr, _) in ram(expected) {
+        cpu.mem_write(addr, 0);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join(", "))
+    }
+}
+
+#[derive(Debug)]
+enum Verdict {
+    Passed(usize),
+    Failed(usize, String),
+    /// The opcode is not in the core's table.
+    Unimplemented,
+}
+
+fn panic_message(e: Box<dyn std::any::Any + Send>) -> String {
+    e.downcast_ref::<String>()
+        .cloned()
+        .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
+
+fn run_file(opcode: u8, cases: &[Value]) -> Verdict {
+    if opcodes::opcode(opcode).is_none() {
+        return Verdict::Unimplemented;
+    }
+    let mut cpu = bare_cpu();
+    let mut passed = 0;
+    let mut failed = 0;
+    let mut first_failure = None;
+
+    for case in cases {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run_case(&mut cpu, case)))
+            .unwrap_or_else(|e| Err(format!("panicked: {}", panic_message(e))));
+        match result {
+            Ok(()) => passed += 1,
+            Err(e) => {
+                failed += 1;
+                if first_failure.is_none() {
+                    first_failure = Some(format!("{}: {}", case["name"], e));
+                }
+                // a failed or panicked case can leave stray state behind
+                cpu = bare_cpu();
+            }
+        }
+    }
+    match first_failure {
+        Some(first) => Verdict::Failed(failed, first),
+        None => Verdict::Passed(passed),
+    }
+}
+
+#[test]
+fn single_step_suite() {
+    let dir = fixture_dir();
+    let mut files = BTreeMap::new();
+    for opcode in 0..=0xffu8 {
+        let path = dir.join(format!("{:02x}.json", opcode));
+        if path.exists() {
+            files.insert(opcode, path);
+        }
+    }
+    if files.is_empty() {
+        println!("no SingleStepTests in {}, skipping", dir.display());
+        return;
+    }
+
+    let mut failures = vec![];
+    for (opcode, path) in files {
+        let text = std::fs::read_to_string(&path).unwrap();
+        let cases: Vec<Value> = serde_json::from_str(&text).unwrap();
+        let expected = UNIMPLEMENTED.contains(&opcode);
+        match run_file(opcode, &cases) {
+            Verdict::Unimplemented if expected => {}
+            Verdict::Unimplemented => failures.push(format!("{:02X}: unimplemented", opcode)),
+            Verdict::Passed(_) if expected => {
+                failures.push(format!("{:02X}: passes, take it off UNIMPLEMENTED", opcode))
+            }
+            Verdict::Passed(_) => {}
+            Verdict::Failed(count, first) => {
+                failures.push(format!("{:02X}: {} failed, first {}", opcode, count, first))
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn runner_checks_registers_memory_and_bus_cycles() {
+    // PHA with A=$42, S=$FD; then the same case expecting a wrong write
+    let pha = r#"{
+        "name": "48 00 00",
+        "initial": {"pc": 1024, "s": 253, "a": 66, "x": 0, "y": 0, "p": 36,
+                    "ram": [[1024, 72], [1025, 0]]},
+        "final": {"pc": 1025, "s": 252, "a": 66, "x": 0, "y": 0, "p": 36,
+                  "ram": [[1024, 72], [1025, 0], [509, 66]]},
+        "cycles": [[1024, 72, "read"], [1025, 0, "read"], [509, 66, "write"]]
+    }"#;
+    let case: Value = serde_json::from_str(pha).unwrap();
+    assert!(matches!(
+        run_file(0x48, std::slice::from_ref(&case)),
+        Verdict::Passed(1)
+    ));
+
+    let mut wrong = case.clone();
+    wrong["final"]["ram"][2] = serde_json::json!([508, 66]);
+    wrong["cycles"][2] = serde_json::json!([508, 66, "write"]);
+    match run_file(0x48, &[wrong]) {
+        Verdict::Failed(1, message) => {
+            assert!(message.contains("[01FC] 0, expected 42"), "{}", message);
+            assert!(
+                message.contains("cycle 3: write 42 at 01FD, expected write 42 at 01FC"),
+                "{}",
+                message
+            );
+        }
+        other => panic!("expected a failure, got {:?}", other),
+    }
+
+    // ASL $10: a dummy write of the old value, then the result
+    let asl = r#"{
+        "name": "06 10",
+        "initial": {"pc": 1024, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                    "ram": [[1024, 6], [1025, 16], [16, 65]]},
+        "final": {"pc": 1026, "s": 253, "a": 0, "x": 0, "y": 0, "p": 164,
+                  "ram": [[1024, 6], [1025, 16], [16, 130]]},
+        "cycles": [[1024, 6, "read"], [1025, 16, "read"], [16, 65, "read"],
+                   [16, 65, "write"], [16, 130, "write"]]
+    }"#;
+    let case: Value = serde_json::from_str(asl).unwrap();
+    assert!(matches!(run_file(0x06, &[case]), Verdict::Passed(1)));
+}
+
+#[test]
+fn runner_checks_dummy_reads() {
+    // LDA $04FF,X with X=1 first reads $0400, before the carry reaches
+    // the high byte
+    let lda = r#"{
+        "name": "bd ff 04",
+        "initial": {"pc": 1024, "s": 253, "a": 0, "x": 1, "y": 0, "p": 36,
+                    "ram": [[1024, 189], [1025, 255], [1026, 4], [1280, 51]]},
+        "final": {"pc": 1027, "s": 253, "a": 51, "x": 1, "y": 0, "p": 36,
+                  "ram": [[1024, 189], [1025, 255], [1026, 4], [1280, 51]]},
+        "cycles": [[1024, 189, "read"], [1025, 255, "read"], [1026, 4, "read"],
+                   [1024, 189, "read"], [1280, 51, "read"]]
+    }"#;
+    let case: Value = serde_json::from_str(lda).unwrap();
+    assert!(matches!(
+        run_file(0xbd, std::slice::from_ref(&case)),
+        Verdict::Passed(1)
+    ));
+
+    let mut wrong = case;
+    wrong["cycles"].as_array_mut().unwrap().remove(3);
+    match run_file(0xbd, &[wrong]) {
+        Verdict::Failed(1, message) => {
+            assert!(message.contains("cycles 5, expected 4"), "{}", message);
+            assert!(
+                message.contains("cycle 4: read BD at 0400, expected read 33 at 0500"),
+                "{}",
+                message
+            );
+        }
+        other => panic!("expected a failure, got {:?}", other),
+    }
+
+    // JSR $0500 reads the stack before pushing and fetches the high byte
+    // of the target last
+    let jsr = r#"{
+        "name": "20 00 05",
+        "initial": {"pc": 1024, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                    "ram": [[1024, 32], [1025, 0], [1026, 5]]},
+        "final": {"pc": 1280, "s": 251, "a": 0, "x": 0, "y": 0, "p": 36,
+                  "ram": [[1024, 32], [1025, 0], [1026, 5], [509, 4], [508, 2]]},
+        "cycles": [[1024, 32, "read"], [1025, 0, "read"], [509, 0, "read"],
+                   [509, 4, "write"], [508, 2, "write"], [1026, 5, "read"]]
+    }"#;
+    let case: Value = serde_json::from_str(jsr).unwrap();
+    assert!(matches!(run_file(0x20, &[case]), Verdict::Passed(1)));
+}
+
+#[test]
+fn unimplemented_list_matches_the_opcode_table() {
+    for opcode in 0..=0xffu8 {
+        assert_eq!(
+            UNIMPLEMENTED.contains(&opcode),
+            opcodes::opcode(opcode).is_none(),
+            "{:02X}",
+            opcode
+        );
+    }
+}
+
+#[test]
+fn runner_takes_brk_through_the_vector() {
+    // BRK at $0400 pushes $0402 and the status with B set, then jumps
+    // through $FFFE to $0500 with I set
+    let brk = r#"{
+        "name": "00 00",
+        "initial": {"pc": 1024, "s": 253, "a": 0, "x": 0, "y": 0, "p": 32,
+                    "ram": [[1024, 0], [1025, 0], [65534, 0], [65535, 5]]},
+        "final": {"pc": 1280, "s": 250, "a": 0, "x": 0, "y": 0, "p": 36,
+                  "ram": [[1024, 0], [1025, 0], [65534, 0], [65535, 5],
+                          [509, 4], [508, 2], [507, 48]]},
+        "cycles": [[1024, 0, "read"], [1025, 0, "read"], [509, 4, "write"],
+                   [508, 2, "write"], [507, 48, "write"], [65534, 0, "read"],
+                   [65535, 5, "read"]]
+    }"#;
+    let case: Value = serde_json::from_str(brk).unwrap();
+    assert!(matches!(run_file(0x00, &[case]), Verdict::Passed(1)));
+}
+
+#[test]
+fn only_missing_opcodes_are_unimplemented() {
+    assert!(matches!(run_file(0x02, &[]), Verdict::Unimplemented));
+
+    // a panic in an implemented opcode is a failure
+    let broken = r#"{
+        "name": "ea",
+        "initial": {"pc": 1024, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                    "ram": [[1024, "nop"]]},
+        "final": {"pc": 1025, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36},
+        "cycles": []
+    }"#;
+    let case: Value = serde_json::from_str(broken).unwrap();
+    match run_file(0xea, &[case]) {
+        Verdict::Failed(1, message) => assert!(message.contains("panicked"), "{}", message),
+        other => panic!("expected a failure, got {:?}", other),
+    }
+}
@@ -0,0 +1,107 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::debugger::StopReason;
+use wasm_nes_emulator::symbols::SymbolTable;
+use wasm_nes_emulator::trace::trace;
+
+const NL: &str = "$0600#main#entry point\n\
+$0606#update_player#moves the player\\\n\
+\\continued comment\n\
+$0010#player_x#\n\
+$C000#bad\n";
+
+const DBG: &str = r#"version	major=2,minor=0
+info	csym=0,file=1,lib=0,line=0,mod=1,scope=1,seg=3,span=0,sym=4,type=0
+file	id=0,name="src/main, game.s",size=1024,mtime=0x5D5B8C6A,mod=0
+seg	id=0,name="ZEROPAGE",start=0x000000,size=0x0010,addrsize=zeropage,type=rw
+seg	id=1,name="BANK0",start=0x008000,size=0x4000,addrsize=absolute,type=ro,oname="game.nes",ooffs=16
+seg	id=2,name="BANK1",start=0x008000,size=0x4000,addrsize=absolute,type=ro,oname="game.nes",ooffs=16400
+sym	id=0,name="reset_handler",addrsize=absolute,scope=0,def=1,ref=3,val=0x8000,seg=1,type=lab
+sym	id=1,name="title_screen",addrsize=absolute,scope=0,def=2,val=0x8000,seg=2,type=lab
+sym	id=2,name="frame_count",addrsize=zeropage,scope=0,def=3,val=0x2,seg=0,type=lab
+sym	id=3,name="PPUCTRL",addrsize=absolute,scope=0,def=4,val=0x2000,type=equ
+"#;
+
+#[test]
+fn load_fceux_nl() {
+    let mut symbols = SymbolTable::default();
+    assert_eq!(symbols.load_nl(NL, None), Ok(4));
+
+    assert_eq!(symbols.label_at(0x0606, None), Some("update_player"));
+    assert_eq!(symbols.lookup("player_x").unwrap().addr, 0x10);
+    assert_eq!(symbols.lookup("bad").unwrap().addr, 0xc000);
+    assert!(symbols.load_nl("$zz#oops#", None).is_err());
+}
+
+#[test]
+fn load_ld65_dbg_with_banks() {
+    let mut symbols = SymbolTable::default();
+    assert_eq!(symbols.load_dbg(DBG), Ok(3));
+
+    assert_eq!(symbols.lookup("reset_handler").unwrap().bank, Some(0));
+    assert_eq!(symbols.lookup("title_screen").unwrap().bank, Some(1));
+    assert_eq!(symbols.lookup("frame_count").unwrap().bank, None);
+    assert!(symbols.lookup("PPUCTRL").is_none());
+
+    assert_eq!(symbols.label_at(0x8000, Some(0)), Some("reset_handler"));
+    assert_eq!(symbols.label_at(0x8000, Some(1)), Some("title_screen"));
+    assert_eq!(symbols.label_at(0x0002, Some(1)), Some("frame_count"));
+}
+
+// 0600: JSR update_player
+// 0603: LDA player_x
+// 0605: BRK
+// 0606: INC player_x
+// 0608: RTS
+fn labelled_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0x20, 0x06, 0x06, 0xa5, 0x10, 0x00, 0xe6, 0x10, 0x60]);
+    cpu.reset();
+    cpu.load_nl(NL, None).unwrap();
+    cpu
+}
+
+#[test]
+fn trace_uses_labels() {
+    let mut cpu = labelled_cpu();
+    let mut result: Vec<String> = vec![];
+    cpu.run_with_callback(|cpu| result.push(trace(cpu)));
+
+    assert!(result[0].starts_with("0600  20 06 06  JSR update_player "));
+    assert!(result[1].starts_with("0606  E6 10     INC player_x = 00 "));
+    assert!(result[3].starts_with("0603  A5 10     LDA player_x = 01 "));
+}
+
+#[test]
+fn disassembly_listing() {
+    let cpu = labelled_cpu();
+
+    assert_eq!(
+        cpu.disassemble(0x0600, 3),
+        "main:\n\
+         0600  20 06 06  JSR update_player\n\
+         0603  A5 10     LDA player_x = 00\n\
+         0605  00        BRK"
+    );
+}
+
+#[test]
+fn break_on_label() {
+    let mut cpu = labelled_cpu();
+    cpu.add_label_breakpoint("update_player").unwrap();
+    assert!(cpu.add_label_breakpoint("nowhere").is_err());
+
+    assert_eq!(cpu.resume(100), StopReason::Breakpoint);
+    assert_eq!(cpu.program_counter, 0x0606);
+    assert_eq!(cpu.label_at(cpu.program_counter).unwrap(), "update_player");
+}
+
+#[test]
+fn labels_in_conditions() {
+    let mut cpu = labelled_cpu();
+    cpu.add_break_condition("PC == main + 3 && [player_x] == 1")
+        .unwrap();
+
+    assert_eq!(cpu.resume(100), StopReason::Breakpoint);
+    assert_eq!(cpu.program_counter, 0x0603);
+}
@@ -0,0 +1,241 @@
+#![cfg(feature = "std")]
+extern crate wasm_nes_emulator;
+use std::path::{Path, PathBuf};
+
+use wasm_nes_emulator::testrom::{compatibility_table, run_test_rom, Outcome, Protocol};
+
+mod common;
+use common::ines;
+
+// Test ROMs are not checked in. Put nestest.nes, blargg's tests and
+// 6502_functional_test.bin in tests/roms (or point NES_TEST_ROMS at them)
+// and list the ones that must pass in expected-pass.txt there. Klaus
+// images are listed in klaus.txt with the success address from their
+// assembler listing. Only NROM is emulated, so blargg's MMC1 builds
+// report unsupported.
+fn fixture_dir() -> PathBuf {
+    std::env::var_os("NES_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms"))
+}
+
+fn find_roms(dir: &Path, found: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            find_roms(&path, found);
+        } else {
+            found.push(path);
+        }
+    }
+}
+
+#[test]
+fn test_rom_suite() {
+    let dir = fixture_dir();
+    let mut paths = vec![];
+    find_roms(&dir, &mut paths);
+    if paths.is_empty() {
+        println!("no test ROMs in {}, skipping", dir.display());
+        return;
+    }
+
+    let klaus = std::fs::read_to_string(dir.join("klaus.txt"))
+        .map(|text| Protocol::klaus_config(&text).unwrap())
+        .unwrap_or_default();
+
+    let mut results = vec![];
+    for path in paths {
+        let name = path.strip_prefix(&dir).unwrap().display().to_string();
+        let configured = klaus.iter().find(|(n, _)| *n == name).map(|(_, p)| *p);
+        let protocol = match configured.or_else(|| Protocol::for_file(&name)) {
+            Some(protocol) => protocol,
+            None => continue,
+        };
+        let data = std::fs::read(&path).unwrap();
+        results.push((name, run_test_rom(&data, protocol, 3600)));
+    }
+
+    let table = compatibility_table(&results);
+    println!("{}", table);
+    let report = Path::new(env!("CARGO_TARGET_TMPDIR")).join("test-roms.md");
+    std::fs::write(&report, &table).unwrap();
+
+    let expected = std::fs::read_to_string(dir.join("expected-pass.txt")).unwrap_or_default();
+    for name in expected.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let outcome = results.iter().find(|(n, _)| n == name).map(|(_, o)| o);
+        assert_eq!(outcome, Some(&Outcome::Pass), "{}", name);
+    }
+}
+
+// 8000: LDA #$80 / STA $6000
+// 8005: LDA #$DE / STA $6001 / LDA #$B0 / STA $6002 / LDA #$61 / STA $6003
+// 8014: LDA #'F' / STA $6004 / LDA #$00 / STA $6005
+// 801E: LDA #result / STA $6000
+// 8023: JMP $8023
+fn blargg_rom(result: u8) -> Vec<u8> {
+    ines(
+        0,
+        &[
+            0xa9, 0x80, 0x8d, 0x00, 0x60, 0xa9, 0xde, 0x8d, 0x01, 0x60, 0xa9, 0xb0, 0x8d, 0x02,
+            0x60, 0xa9, 0x61, 0x8d, 0x03, 0x60, 0xa9, b'F', 0x8d, 0x04, 0x60, 0xa9, 0x00, 0x8d,
+            0x05, 0x60, 0xa9, result, 0x8d, 0x00, 0x60, 0x4c, 0x23, 0x80,
+        ],
+    )
+}
+
+#[test]
+fn blargg_protocol() {
+    assert_eq!(
+        run_test_rom(&blargg_rom(0), Protocol::Blargg, 10),
+        Outcome::Pass
+    );
+    assert_eq!(
+        run_test_rom(&blargg_rom(3), Protocol::Blargg, 10),
+        Outcome::Fail("result 3: F".to_string())
+    );
+    // still running when time is up
+    assert_eq!(
+        run_test_rom(&blargg_rom(0x80), Protocol::Blargg, 10),
+        Outcome::Timeout
+    );
+    // a result without $80 first is left over from before the test
+    let mut never_ran = blargg_rom(0);
+    never_ran[16 + 1] = 0x00;
+    assert_eq!(
+        run_test_rom(&never_ran, Protocol::Blargg, 10),
+        Outcome::Timeout
+    );
+}
+
+#[test]
+fn nestest_protocol() {
+    // C000: LDA #$00 / STA $02 / LDA #code / STA $03 / JMP $C66E
+    let rom = |code: u8| {
+        ines(
+            0,
+            &[
+                0xa9, 0x00, 0x85, 0x02, 0xa9, code, 0x85, 0x03, 0x4c, 0x6e, 0xc6,
+            ],
+        )
+    };
+    assert_eq!(run_test_rom(&rom(0), Protocol::Nestest, 10), Outcome::Pass);
+    assert_eq!(
+        run_test_rom(&rom(0x15), Protocol::Nestest, 10),
+        Outcome::Fail("error codes $00 $15".to_string())
+    );
+}
+
+#[test]
+fn klaus_protocol() {
+    let mut image = vec![0; 0x10000];
+    // 0400: JMP $0403 / 0403: JMP $0403
+    image[0x400..0x406].copy_from_slice(&[0x4c, 0x03, 0x04, 0x4c, 0x03, 0x04]);
+
+    let pass = Protocol::Klaus { success: 0x0403 };
+    assert_eq!(run_test_rom(&image, pass, 10), Outcome::Pass);
+
+    let fail = Protocol::Klaus { success: 0x3469 };
+    assert_eq!(
+        run_test_rom(&image, fail, 10),
+        Outcome::Fail("trapped at $0403".to_string())
+    );
+    assert!(matches!(run_test_rom(&[0; 16], fail, 10), Outcome::Fail(_)));
+}
+
+#[test]
+fn klaus_runs_through_brk() {
+    let mut image = vec![0; 0x10000];
+    // 0400: BRK / 0402: JMP $0402
+    image[0x400..0x405].copy_from_slice(&[0x00, 0xff, 0x4c, 0x02, 0x04]);
+    // 0500: PLA / PHA / AND #$10 / BNE $0508
+    // 0506: BEQ $0506, trapped when B was not pushed
+    // 0508: RTI
+    image[0x500..0x509].copy_from_slice(&[0x68, 0x48, 0x29, 0x10, 0xd0, 0x02, 0xf0, 0xfe, 0x40]);
+    image[0xfffe] = 0x00;
+    image[0xffff] = 0x05;
+
+    let pass = Protocol::Klaus { success: 0x0402 };
+    assert_eq!(run_test_rom(&image, pass, 10), Outcome::Pass);
+}
+
+#[test]
+fn other_mappers_are_unsupported() {
+    // mapper 1
+    let rom = ines(0x10, &[0x4c, 0x00, 0x80]);
+    assert_eq!(
+        run_test_rom(&rom, Protocol::Blargg, 10),
+        Outcome::Unsupported("mapper 1 is not supported".to_string())
+    );
+    assert!(matches!(
+        run_test_rom(b"not a rom", Protocol::Nestest, 10),
+        Outcome::Fail(_)
+    ));
+}
+
+#[test]
+fn unknown_opcode_is_a_crash() {
+    // KIL
+    let rom = ines(0, &[0x02]);
+    assert_eq!(
+        run_test_rom(&rom, Protocol::Blargg, 10),
+        Outcome::Crash("jammed on $02 at $8000".to_string())
+    );
+}
+
+#[test]
+fn protocol_from_file_name() {
+    assert_eq!(Protocol::for_file("nestest.nes"), Some(Protocol::Nestest));
+    assert_eq!(
+        Protocol::for_file("instr_test-v5/01-basics.nes"),
+        Some(Protocol::Blargg)
+    );
+    assert_eq!(Protocol::for_file("6502_functional_test.bin"), None);
+    assert_eq!(Protocol::for_file("readme.txt"), None);
+}
+
+#[test]
+fn klaus_success_comes_from_the_config() {
+    let config = "# decimal mode off\n\
+                  6502_functional_test.bin $336D\n\
+                  \n\
+                  bcd/6502_functional_test.bin 3469 # with decimal mode\n";
+    assert_eq!(
+        Protocol::klaus_config(config),
+        Ok(vec![
+            (
+                "6502_functional_test.bin".to_string(),
+                Protocol::Klaus { success: 0x336d }
+            ),
+            (
+                "bcd/6502_functional_test.bin".to_string(),
+                Protocol::Klaus { success: 0x3469 }
+            ),
+        ])
+    );
+    assert!(Protocol::klaus_config("6502_functional_test.bin\n").is_err());
+    assert!(Protocol::klaus_config("test.bin $zz\n").is_err());
+}
+
+#[test]
+fn table_lists_every_rom() {
+    let table = compatibility_table(&[
+        ("a.nes".to_string(), Outcome::Pass),
+        (
+            "b.nes".to_string(),
+            Outcome::Fail("result 2: x|y".to_string()),
+        ),
+        (
+            "c.nes".to_string(),
+            Outcome::Unsupported("mapper 1 is not supported".to_string()),
+        ),
+    ]);
+    assert!(table.contains("| a.nes | pass |"));
+    assert!(table.contains("| b.nes | FAIL: result 2: x\\|y |"));
+    assert!(table.contains("| c.nes | unsupported: mapper 1 is not supported |"));
+    assert!(table.contains("1 of 3 passed, 1 unsupported"));
+}
@@ -0,0 +1,128 @@
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::cpu::CPU;
+use wasm_nes_emulator::trace::trace;
+
+fn nestest_cpu(program: &[u8]) -> CPU {
+    let mut cpu = CPU::new();
+    for (i, byte) in program.iter().enumerate() {
+        cpu.mem_write(0x64 + i as u16, *byte);
+    }
+    cpu.program_counter = 0x64;
+    cpu.status = 0x24;
+    cpu.stack_ptr = 0xfd;
+    cpu.cycles = 7;
+    cpu
+}
+
+#[test]
+fn format_trace() {
+    let mut cpu = nestest_cpu(&[0xa2, 0x01, 0xca, 0x88, 0x00]);
+    cpu.register_a = 1;
+    cpu.register_x = 2;
+    cpu.register_y = 3;
+
+    let mut result: Vec<String> = vec![];
+    cpu.run_with_callback(|cpu| {
+        result.push(trace(cpu));
+    });
+
+    assert_eq!(
+        "0064  A2 01     LDX #$01                        A:01 X:02 Y:03 P:24 SP:FD PPU:  0, 21 CYC:7",
+        result[0]
+    );
+    assert_eq!(
+        "0066  CA        DEX                             A:01 X:01 Y:03 P:24 SP:FD PPU:  0, 27 CYC:9",
+        result[1]
+    );
+    assert_eq!(
+        "0067  88        DEY                             A:01 X:00 Y:03 P:26 SP:FD PPU:  0, 33 CYC:11",
+        result[2]
+    );
+}
+
+#[test]
+fn format_mem_access() {
+    // ORA ($33), Y
+    let mut cpu = nestest_cpu(&[0x11, 0x33, 0x00]);
+    cpu.mem_write(0x33, 0x00);
+    cpu.mem_write(0x34, 0x04);
+    cpu.mem_write(0x400, 0xaa);
+
+    let mut result: Vec<String> = vec![];
+    cpu.run_with_callback(|cpu| {
+        result.push(trace(cpu));
+    });
+
+    assert_eq!(
+        "0064  11 33     ORA ($33),Y = 0400 @ 0400 = AA  A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+        result[0]
+    );
+}
+
+#[test]
+fn format_jumps_and_accumulator() {
+    // JMP $0069; (unused); ASL A; BNE -3
+    let mut cpu = nestest_cpu(&[0x4c, 0x69, 0x00, 0xea, 0xea, 0x0a, 0xd0, 0xfd, 0x00]);
+    cpu.register_a = 0x01;
+
+    let mut result: Vec<String> = vec![];
+    cpu.run_with_callback(|cpu| {
+        result.push(trace(cpu));
+    });
+
+    assert_eq!(
+        "0064  4C 69 00  JMP $0069                       A:01 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+        result[0]
+    );
+    assert_eq!(
+        "0069  0A        ASL A                           A:01 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10",
+        result[1]
+    );
+    assert_eq!(
+        "006A  D0 FD     BNE $0069                       A:02 X:00 Y:00 P:24 SP:FD PPU:  0, 36 CYC:12",
+        result[2]
+    );
+    // the taken branch costs an extra cycle
+    assert!(result[3].ends_with("CYC:15"));
+}
+
+#[test]
+fn trace_log_collects_lines() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xa9, 0x05, 0xaa, 0x00]);
+    cpu.reset();
+    cpu.enable_trace();
+    cpu.run();
+
+    let log = cpu.take_trace();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("0600  A9 05     LDA #$05"));
+    assert!(lines[1].starts_with("0602  AA        TAX"));
+    assert!(lines[2].starts_with("0603  00        BRK"));
+    assert_eq!(cpu.take_trace(), "");
+}
+
+#[test]
+fn instructions_wrap_around_the_address_space() {
+    // FFFE: LDA $1234, with the high byte of the operand at $0000
+    let mut cpu = CPU::new();
+    cpu.mem_write(0xfffe, 0xad);
+    cpu.mem_write(0xffff, 0x34);
+    cpu.mem_write(0x0000, 0x12);
+    cpu.mem_write(0x1234, 0x77);
+    cpu.program_counter = 0xfffe;
+
+    assert!(trace(&cpu).starts_with("FFFE  AD 34 12  LDA $1234 = 77"));
+    assert_eq!(cpu.mem_read_u16(0xffff), 0x1234);
+    cpu.next();
+    assert_eq!(cpu.register_a, 0x77);
+    assert_eq!(cpu.program_counter, 0x0001);
+
+    // FFFF: INX
+    cpu.mem_write(0xffff, 0xe8);
+    cpu.program_counter = 0xffff;
+    assert!(trace(&cpu).starts_with("FFFF  E8        INX"));
+    cpu.next();
+    assert_eq!(cpu.program_counter, 0x0000);
+}
@@ -0,0 +1,43 @@
+#![cfg(feature = "wasm")]
+extern crate wasm_nes_emulator;
+use wasm_nes_emulator::wasm::{FrameStatus, Machine, SearchCompare, SearchFormat, SearchSize, CPU};
+
+#[test]
+fn bindings_forward_to_the_core() {
+    let mut cpu = CPU::new();
+    cpu.load_asm("ldx #5\n inx\n brk\n").unwrap();
+    assert_eq!(cpu.machine(), Machine::Easy6502);
+    assert_eq!(cpu.run_frame(), FrameStatus::Brk);
+    assert_eq!(cpu.register_x(), 6);
+    assert_eq!(cpu.core().register_x, 6);
+
+    cpu.set_register_a(0x42);
+    assert_eq!(cpu.core().register_a, 0x42);
+    cpu.core_mut().register_y = 7;
+    assert_eq!(cpu.register_y(), 7);
+
+    assert!(cpu.load_rom(&[0; 4]).is_err());
+}
+
+#[test]
+fn bindings_convert_the_core_types() {
+    let mut cpu = CPU::new();
+    cpu.load_asm("lda #3\n sta $10\n brk\n").unwrap();
+    cpu.add_watchpoint(0x10, 0x10, false, true);
+    assert_eq!(cpu.run_frame(), FrameStatus::Watchpoint);
+    let hit = cpu.last_watch_hit().unwrap();
+    assert_eq!((hit.addr, hit.value), (0x10, 3));
+
+    cpu.set_machine(Machine::Nes);
+    assert_eq!(cpu.core().machine, wasm_nes_emulator::machine::Machine::Nes);
+
+    cpu.ram_search_start();
+    cpu.ram_search_constant(
+        SearchSize::Byte,
+        SearchFormat::Unsigned,
+        SearchCompare::Equal,
+        3,
+    )
+    .unwrap();
+    assert!(cpu.ram_search_candidates().contains(&0x10));
+}
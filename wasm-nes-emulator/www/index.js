@@ -1,9 +1,7 @@
-import { CPU } from "wasm-nes-emulator";
+import { CPU, FrameStatus } from "wasm-nes-emulator";
 import { memory } from "wasm-nes-emulator/wasm_nes_emulator_bg";
 const CELL_SIZE = 16; // px
 
-//CHECK FOR NEED SCREEN UPDATE -
-
 const COLORS = [
   "#000000", // Black
   "#FFFFFF", // White
@@ -24,34 +22,32 @@ const COLORS = [
 ];
 let cpu = CPU.new();
 
-let game_code = [
-  0x20, 0x06, 0x06, 0x20, 0x38, 0x06, 0x20, 0x0d, 0x06, 0x20, 0x2a, 0x06, 0x60,
-  0xa9, 0x02, 0x85, 0x02, 0xa9, 0x04, 0x85, 0x03, 0xa9, 0x11, 0x85, 0x10, 0xa9,
-  0x10, 0x85, 0x12, 0xa9, 0x0f, 0x85, 0x14, 0xa9, 0x04, 0x85, 0x11, 0x85, 0x13,
-  0x85, 0x15, 0x60, 0xa5, 0xfe, 0x85, 0x00, 0xa5, 0xfe, 0x29, 0x03, 0x18, 0x69,
-  0x02, 0x85, 0x01, 0x60, 0x20, 0x4d, 0x06, 0x20, 0x8d, 0x06, 0x20, 0xc3, 0x06,
-  0x20, 0x19, 0x07, 0x20, 0x20, 0x07, 0x20, 0x2d, 0x07, 0x4c, 0x38, 0x06, 0xa5,
-  0xff, 0xc9, 0x77, 0xf0, 0x0d, 0xc9, 0x64, 0xf0, 0x14, 0xc9, 0x73, 0xf0, 0x1b,
-  0xc9, 0x61, 0xf0, 0x22, 0x60, 0xa9, 0x04, 0x24, 0x02, 0xd0, 0x26, 0xa9, 0x01,
-  0x85, 0x02, 0x60, 0xa9, 0x08, 0x24, 0x02, 0xd0, 0x1b, 0xa9, 0x02, 0x85, 0x02,
-  0x60, 0xa9, 0x01, 0x24, 0x02, 0xd0, 0x10, 0xa9, 0x04, 0x85, 0x02, 0x60, 0xa9,
-  0x02, 0x24, 0x02, 0xd0, 0x05, 0xa9, 0x08, 0x85, 0x02, 0x60, 0x60, 0x20, 0x94,
-  0x06, 0x20, 0xa8, 0x06, 0x60, 0xa5, 0x00, 0xc5, 0x10, 0xd0, 0x0d, 0xa5, 0x01,
-  0xc5, 0x11, 0xd0, 0x07, 0xe6, 0x03, 0xe6, 0x03, 0x20, 0x2a, 0x06, 0x60, 0xa2,
-  0x02, 0xb5, 0x10, 0xc5, 0x10, 0xd0, 0x06, 0xb5, 0x11, 0xc5, 0x11, 0xf0, 0x09,
-  0xe8, 0xe8, 0xe4, 0x03, 0xf0, 0x06, 0x4c, 0xaa, 0x06, 0x4c, 0x35, 0x07, 0x60,
-  0xa6, 0x03, 0xca, 0x8a, 0xb5, 0x10, 0x95, 0x12, 0xca, 0x10, 0xf9, 0xa5, 0x02,
-  0x4a, 0xb0, 0x09, 0x4a, 0xb0, 0x19, 0x4a, 0xb0, 0x1f, 0x4a, 0xb0, 0x2f, 0xa5,
-  0x10, 0x38, 0xe9, 0x20, 0x85, 0x10, 0x90, 0x01, 0x60, 0xc6, 0x11, 0xa9, 0x01,
-  0xc5, 0x11, 0xf0, 0x28, 0x60, 0xe6, 0x10, 0xa9, 0x1f, 0x24, 0x10, 0xf0, 0x1f,
-  0x60, 0xa5, 0x10, 0x18, 0x69, 0x20, 0x85, 0x10, 0xb0, 0x01, 0x60, 0xe6, 0x11,
-  0xa9, 0x06, 0xc5, 0x11, 0xf0, 0x0c, 0x60, 0xc6, 0x10, 0xa5, 0x10, 0x29, 0x1f,
-  0xc9, 0x1f, 0xf0, 0x01, 0x60, 0x4c, 0x35, 0x07, 0xa0, 0x00, 0xa5, 0xfe, 0x91,
-  0x00, 0x60, 0xa6, 0x03, 0xa9, 0x00, 0x81, 0x10, 0xa2, 0x00, 0xa9, 0x01, 0x81,
-  0x10, 0x60, 0xa2, 0x00, 0xea, 0xea, 0xca, 0xd0, 0xfb, 0x60,
-];
+const source = document.getElementById("source");
+const error = document.getElementById("error");
 
-cpu.load_pro(game_code);
+const randomSeed = () => Math.floor(Math.random() * 0xffffffff) + 1;
+
+// assembles the program in the text box into a fresh CPU
+const start = () => {
+  const next = CPU.new();
+  try {
+    next.load_asm(source.value);
+  } catch (message) {
+    error.textContent = message;
+    next.free();
+    return;
+  }
+  error.textContent = "";
+  // wasm objects are not garbage collected
+  cpu.free();
+  cpu = next;
+  cpu.set_random_seed(randomSeed());
+  drawPixel();
+  if (stopped) {
+    stopped = false;
+    renderLoop();
+  }
+};
 
 const canvas = document.getElementById("canvas");
 canvas.height = CELL_SIZE * 32;
@@ -59,32 +55,33 @@ canvas.width = CELL_SIZE * 32;
 
 const ctx = canvas.getContext("2d");
 
-document.getElementById("reset").addEventListener("click", (event) => {
-  cpu = CPU.new();
-  cpu.load_pro(game_code);
-  drawPixel();
-  renderLoop();
+document.getElementById("reset").addEventListener("click", start);
+document.getElementById("assemble").addEventListener("click", start);
+
+document.getElementById("screenshot").addEventListener("click", (event) => {
+  const png = cpu.screenshot_png(CELL_SIZE);
+  const link = document.createElement("a");
+  link.href = URL.createObjectURL(new Blob([png], { type: "image/png" }));
+  link.download = "screenshot.png";
+  link.click();
+  URL.revokeObjectURL(link.href);
 });
 
 addEventListener("keypress", (event) => {
-  cpu.mem_write(0xff, event.keyCode);
-  const memPtr = cpu.mem_ptr();
+  cpu.key_press(event.keyCode);
 });
 
-let not_one = false;
-let over_2 = false;
+let stopped = true;
 const renderLoop = () => {
-  while (cpu.update == false) {
-    cpu.mem_write(0xfe, Math.floor(Math.random() * 16) + 1);
-    cpu.next();
-    if (cpu.register_x > 2) {
-      over_2 = true;
-    }
+  const status = cpu.run_frame();
+  if (cpu.update) {
+    drawPixel();
+  }
+  if (status == FrameStatus.Complete) {
+    requestAnimationFrame(renderLoop);
+  } else {
+    stopped = true;
   }
-  drawPixel();
-  cpu.reset_update();
-
-  setTimeout(requestAnimationFrame(renderLoop), 0.7);
 };
 
 const drawPixel = () => {
@@ -103,5 +100,9 @@ const drawPixel = () => {
   ctx.stroke();
 };
 
-drawPixel();
-renderLoop();
+fetch("snake.asm")
+  .then((response) => response.text())
+  .then((text) => {
+    source.value = text;
+    start();
+  });
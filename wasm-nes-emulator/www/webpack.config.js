@@ -8,7 +8,7 @@ module.exports = {
     filename: "bootstrap.js",
   },
   mode: "development",
-  plugins: [new CopyWebpackPlugin(["index.html"])],
+  plugins: [new CopyWebpackPlugin(["index.html", "snake.asm"])],
   devServer: {
     disableHostCheck: true,
   },